@@ -3,6 +3,7 @@
 //! 这个脚本在 `cargo build` 时自动执行，用于：
 //! 1. 使用 bindgen 从 C 头文件生成 Rust FFI 绑定
 //! 2. 链接 macOS 系统的 libproc 库
+//! 3. 启用 `embedded-geoip-gzip` 特性时，压缩内置的 GeoIP 数据库
 //!
 //! ## 为什么需要 bindgen？
 //! macOS 的 libproc API 是 C 语言接口，包含复杂的结构体（如 socket_fdinfo）。
@@ -98,4 +99,30 @@ fn main() {
     bindings
         .write_to_file(out_path.join("libproc_bindings.rs"))
         .expect("Couldn't write bindings!");
+
+    // ========================================
+    // 5. （可选）压缩内置 GeoIP 数据库
+    // ========================================
+    #[cfg(feature = "embedded-geoip-gzip")]
+    compress_embedded_geoip(&out_path);
+}
+
+/// 把内置数据库压缩为 OUT_DIR/GeoLite2-Country.mmdb.gz，供 include_bytes! 引入
+#[cfg(feature = "embedded-geoip-gzip")]
+fn compress_embedded_geoip(out_path: &std::path::Path) {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const SOURCE: &str = "GeoLite2-Country_20251212/GeoLite2-Country.mmdb";
+    println!("cargo:rerun-if-changed={}", SOURCE);
+
+    let data = std::fs::read(SOURCE).expect("Couldn't read embedded GeoIP database");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(&data)
+        .expect("Couldn't compress GeoIP database");
+    let compressed = encoder.finish().expect("Couldn't compress GeoIP database");
+    std::fs::write(out_path.join("GeoLite2-Country.mmdb.gz"), compressed)
+        .expect("Couldn't write compressed GeoIP database");
 }
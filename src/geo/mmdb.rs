@@ -3,6 +3,18 @@
 //! 这个模块使用 MaxMind 的 GeoLite2-Country.mmdb 数据库
 //! 将 IP 地址映射到国家/地区信息。
 //!
+//! GeoLite2/GeoIP2 记录直接解码为类型化结构；其他记录按形状宽松解码（见 `schema` 模块），同样支持 Clash/mihomo 的 Country.mmdb、
+//! sing-geoip、Meta-geoip 以及 DB-IP lite 等非 MaxMind 数据库。
+//!
+//! 可以额外加载 GeoLite2-ASN.mmdb（或任何带 ASN 字段的 mmdb），
+//! 查询结果中会附带自治系统编号和运营商/机房名称。
+//!
+//! 使用 GeoLite2-City.mmdb 时还会返回城市、省/州、经纬度和精度半径。
+//! 地名按 `set_language` 指定的语言（如 "zh-CN"、"ja"）从 `names` 中选取，
+//! 缺少该语言时回退到英文。
+//!
+//! 设置了自定义网段标签（见 `overlay` 模块）时，命中的 IP 会带上用户标签。
+//!
 //! ## 使用示例
 //! ```rust
 //! let db = GeoDb::open("/path/to/GeoLite2-Country.mmdb")?;
@@ -14,30 +26,90 @@
 //!
 //! ## 如何获取数据库
 //! 1. 访问 https://dev.maxmind.com/geoip/geolite2-free-geolocation-data
-//! 2. 注册账户并下载 GeoLite2-Country.mmdb
+//! 2. 注册账户并下载 GeoLite2-Country.mmdb（以及可选的 GeoLite2-ASN.mmdb）
 
 use std::net::IpAddr;
 use std::path::Path;
 
 use anyhow::{Context, Result};
-use maxminddb::geoip2;
+use maxminddb::{geoip2, MaxMindDBError, Metadata, Mmap, Reader};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::overlay::LabelOverlay;
+use super::schema::{self, Place};
 
 // ========================================
 // GeoIP 查询结果
 // ========================================
 
+/// 默认地名语言
+pub const DEFAULT_LANGUAGE: &str = "en";
+
 /// GeoIP 查询结果
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct GeoResult {
+    /// 用户自定义标签（来自网段标签文件）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
     /// 国家 ISO 代码（如 "US", "CN", "JP"）
     pub iso_code: String,
-    /// 国家英文名称（如 "United States", "China", "Japan"）
+    /// 国家名称（按所选语言，如 "United States", "中国", "日本"）；数据库只有代码时为空
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub name: String,
+    /// 城市名称（需要 City 数据库）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// 一级行政区（省/州）名称（需要 City 数据库）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subdivision: Option<String>,
+    /// 纬度
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    /// 经度
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    /// 坐标精度半径（公里）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accuracy_radius: Option<u16>,
+    /// 自治系统编号（如 15169）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// 自治系统所属组织（如 "GOOGLE"，通常是运营商或机房）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_org: Option<String>,
+}
+
+impl GeoResult {
+    /// 运营商标签，如 "AS15169 GOOGLE"
+    pub fn asn_label(&self) -> Option<String> {
+        let asn = self.asn?;
+        Some(match &self.as_org {
+            Some(org) => format!("AS{} {}", asn, org),
+            None => format!("AS{}", asn),
+        })
+    }
+
+    /// 城市级位置，如 "东京都 Tokyo" 或 "California"；Country 数据库返回 None
+    pub fn place(&self) -> Option<String> {
+        match (&self.subdivision, &self.city) {
+            (Some(sub), Some(city)) if sub != city => Some(format!("{} {}", sub, city)),
+            (_, Some(city)) => Some(city.clone()),
+            (Some(sub), None) => Some(sub.clone()),
+            (None, None) => None,
+        }
+    }
 }
 
 impl std::fmt::Display for GeoResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.iso_code, self.name)
+        match &self.label {
+            // 自定义标签优先，mmdb 有国家时附在后面
+            Some(label) if self.iso_code == "??" => write!(f, "{}", label),
+            Some(label) => write!(f, "{} ({})", label, self.iso_code),
+            None if self.name.is_empty() => write!(f, "{}", self.iso_code),
+            None => write!(f, "{} {}", self.iso_code, self.name),
+        }
     }
 }
 
@@ -45,30 +117,145 @@ impl std::fmt::Display for GeoResult {
 // GeoIP 数据库封装
 // ========================================
 
+/// 不同存储方式的 mmdb Reader
+///
+/// maxminddb 的 Reader 对底层字节类型是泛型的，这里按来源区分，
+/// 避免把所有数据都拷贝到 Vec<u8> 中：
+/// - 外部文件使用内存映射，只有被访问的页才会读入内存
+/// - 编译时嵌入的数据直接引用静态内存，无需拷贝
+enum DbReader {
+    /// 内存映射的外部文件
+    Mapped(Reader<Mmap>),
+    /// 编译时嵌入的静态数据
+    #[cfg_attr(not(feature = "embedded-geoip"), allow(dead_code))]
+    Static(Reader<&'static [u8]>),
+}
+
+impl DbReader {
+    /// 内存映射打开文件
+    ///
+    /// 映射建立后文件被 update-geo 通过 rename 替换也不受影响（旧 inode 仍然有效）。
+    fn open_mmap(path: &Path) -> Result<Self, maxminddb::MaxMindDBError> {
+        Reader::open_mmap(path).map(DbReader::Mapped)
+    }
+
+    /// 类型化查询
+    fn lookup<'a, T: Deserialize<'a>>(&'a self, ip: IpAddr) -> Result<T, MaxMindDBError> {
+        match self {
+            DbReader::Mapped(r) => r.lookup(ip),
+            DbReader::Static(r) => r.lookup(ip),
+        }
+    }
+
+    /// 按数据库类型选择解码方式，每条记录只解码一次
+    ///
+    /// 声明为 MaxMind 结构但解码失败的库（如改过字段的 Country.mmdb）回退到宽松解码；
+    /// IP 不在库中时返回 None。
+    fn lookup_typed<'a, T: Deserialize<'a>>(&'a self, ip: IpAddr) -> Option<Record<T>> {
+        if schema::is_maxmind(&self.metadata().database_type) {
+            match self.lookup(ip) {
+                Ok(record) => return Some(Record::Typed(record)),
+                Err(MaxMindDBError::DecodingError(_)) => {}
+                Err(_) => return None,
+            }
+        }
+        self.lookup(ip).ok().map(Record::Value)
+    }
+
+    /// 数据库元数据
+    fn metadata(&self) -> &Metadata {
+        match self {
+            DbReader::Mapped(r) => &r.metadata,
+            DbReader::Static(r) => &r.metadata,
+        }
+    }
+}
+
+/// 解码后的一条记录
+enum Record<T> {
+    /// MaxMind GeoIP2 结构
+    Typed(T),
+    /// 其他结构，宽松提取
+    Value(Value),
+}
+
 /// MaxMind GeoIP 数据库封装
 pub struct GeoDb {
-    /// 内部 Reader 实例
-    /// 使用 Vec<u8> 作为某些场景下的所有权数据容器不太灵活，
-    /// 这里为了同时支持文件读取（Vec<u8>）和嵌入（&[u8]），
-    /// 我们简单地统一使用 Vec<u8>，虽然对 include_bytes 会有一次内存拷贝，但几MB数据可以接受。
-    reader: maxminddb::Reader<Vec<u8>>,
+    /// 主数据库（国家或城市）
+    reader: DbReader,
+    /// ASN 数据库（可选，与国家数据库并列加载）
+    asn_reader: Option<DbReader>,
+    /// 地名语言（mmdb `names` 的键，如 "en", "zh-CN", "ja"）
+    language: String,
+    /// 自定义网段标签，查询 mmdb 之前先匹配
+    overlay: Option<LabelOverlay>,
 }
 
 impl GeoDb {
-    /// 打开 GeoIP 数据库文件
+    /// 打开 GeoIP 数据库文件（内存映射）
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        let reader = maxminddb::Reader::open_readfile(path)
+        let reader = DbReader::open_mmap(path)
             .with_context(|| format!("Failed to open GeoIP database: {}", path.display()))?;
-        Ok(Self { reader })
+        Ok(Self::with_reader(reader))
+    }
+
+    /// 从静态字节数组加载数据库（编译时嵌入的数据，零拷贝）
+    #[cfg_attr(not(feature = "embedded-geoip"), allow(dead_code))]
+    pub fn from_static(bytes: &'static [u8]) -> Result<Self> {
+        let reader =
+            Reader::from_source(bytes).context("Failed to parse embedded GeoIP database")?;
+        Ok(Self::with_reader(DbReader::Static(reader)))
     }
 
-    /// 从字节数组加载数据库
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        // 这里的 to_vec() 会发生一次内存拷贝
-        let reader = maxminddb::Reader::from_source(bytes.to_vec())
-            .context("Failed to parse embedded GeoIP database")?;
-        Ok(Self { reader })
+    /// 使用默认设置包装 Reader
+    fn with_reader(reader: DbReader) -> Self {
+        Self {
+            reader,
+            asn_reader: None,
+            language: DEFAULT_LANGUAGE.to_string(),
+            overlay: None,
+        }
+    }
+
+    /// 加载 ASN 数据库（如 GeoLite2-ASN.mmdb），与国家数据库并列查询
+    pub fn attach_asn<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let reader = DbReader::open_mmap(path)
+            .with_context(|| format!("Failed to open ASN database: {}", path.display()))?;
+        self.asn_reader = Some(reader);
+        Ok(())
+    }
+
+    /// 设置自定义网段标签
+    pub fn set_overlay(&mut self, overlay: LabelOverlay) {
+        self.overlay = Some(overlay);
+    }
+
+    /// 查询 IP 命中的自定义标签（最长前缀匹配）
+    pub fn label(&self, ip: IpAddr) -> Option<&str> {
+        self.overlay.as_ref()?.lookup(ip)
+    }
+
+    /// 主数据库的元数据（类型、构建时间等）
+    pub fn metadata(&self) -> &Metadata {
+        self.reader.metadata()
+    }
+
+    /// ASN 数据库的元数据（未加载时返回 None）
+    pub fn asn_metadata(&self) -> Option<&Metadata> {
+        self.asn_reader.as_ref().map(DbReader::metadata)
+    }
+
+    /// 设置地名语言
+    ///
+    /// 返回数据库元数据是否声明支持该语言；不支持时查询会回退到英文。
+    pub fn set_language(&mut self, lang: &str) -> bool {
+        self.language = lang.to_string();
+        let languages = &self.reader.metadata().languages;
+        languages
+            .iter()
+            .any(|l| l == lang || l.split('-').next() == Some(lang))
     }
 
     /// 查询 IP 地址对应的国家/地区
@@ -77,33 +264,80 @@ impl GeoDb {
     /// - `ip`: 要查询的 IP 地址
     ///
     /// ## 返回
-    /// - `Some(GeoResult)`: 查询成功
+    /// - `Some(GeoResult)`: 查询成功（自定义标签、国家或 ASN 至少有一项）
     /// - `None`: IP 不在数据库中或查询失败
     pub fn lookup(&self, ip: IpAddr) -> Option<GeoResult> {
-        // 使用 geoip2::Country 类型进行类型化查询
-        // 这是 maxminddb crate 预定义的结构体，匹配 GeoLite2-Country 数据库
-        let country: geoip2::Country = self.reader.lookup(ip).ok()?;
+        let label = self.label(ip).map(str::to_string);
 
-        // 提取国家信息
-        // country.country 是 Option<geoip2::country::Country>
-        let country_info = country.country?;
+        // 主数据库只解码一次，国家、城市和 ASN 字段都从同一条记录提取
+        // Enterprise 结构是 Country/City 的超集，三种 GeoIP2 库都能解码为它
+        let (place, main_asn) = match self.reader.lookup_typed::<geoip2::Enterprise>(ip) {
+            Some(Record::Typed(record)) => (
+                Place::from_geoip2(&record, &self.language),
+                schema::geoip2_asn(&record),
+            ),
+            Some(Record::Value(record)) => (
+                Place::from_value(&record, &self.language),
+                schema::asn(&record),
+            ),
+            None => (Place::default(), None),
+        };
 
-        // 提取 ISO 代码
-        let iso_code = country_info.iso_code?;
+        // 优先使用独立加载的 ASN 数据库；没有时使用主数据库中的 ASN 字段，
+        // 兼容同时包含国家和 ASN 字段的 mmdb（如 DB-IP、ipinfo 的合并库）
+        let (asn, as_org) = match self.lookup_asn(ip).or(main_asn) {
+            Some((asn, org)) => (Some(asn), org),
+            None => (None, None),
+        };
 
-        // 提取英文名称
-        // names 是 HashMap<&str, &str>，键是语言代码
-        let name = country_info
-            .names
-            .as_ref()
-            .and_then(|names| names.get("en").copied())
-            .unwrap_or("Unknown");
+        if label.is_none() && place.iso_code.is_none() && asn.is_none() {
+            return None;
+        }
 
+        // 只有国家代码的数据库（如部分 Clash Country.mmdb）名称为空，城市级字段仅 City 数据库有
         Some(GeoResult {
-            iso_code: iso_code.to_string(),
-            name: name.to_string(),
+            label,
+            iso_code: place.iso_code.unwrap_or_else(|| "??".to_string()),
+            name: place.name.unwrap_or_default(),
+            city: place.city,
+            subdivision: place.subdivision,
+            latitude: place.latitude,
+            longitude: place.longitude,
+            accuracy_radius: place.accuracy_radius,
+            asn,
+            as_org,
         })
     }
+
+    /// 检查探测地址中是否有结构可用的记录
+    ///
+    /// ASN 库（`database_type` 含 "ASN"）要求能解码出自治系统编号；
+    /// 其他库只要求能读出任意记录，不要求命中国家，
+    /// 只收录部分网段的 Country.mmdb（如仅含 CN）也能通过。
+    pub fn has_records(&self, probes: &[IpAddr]) -> bool {
+        let is_asn = self.metadata().database_type.contains("ASN");
+        probes.iter().any(|&ip| {
+            if !is_asn {
+                return self.reader.lookup::<Value>(ip).is_ok();
+            }
+            match self.reader.lookup_typed::<geoip2::Asn>(ip) {
+                Some(Record::Typed(record)) => record.autonomous_system_number.is_some(),
+                Some(Record::Value(record)) => schema::asn(&record).is_some(),
+                None => false,
+            }
+        })
+    }
+
+    /// 在独立加载的 ASN 数据库中查询 IP 所属的自治系统
+    fn lookup_asn(&self, ip: IpAddr) -> Option<(u32, Option<String>)> {
+        match self.asn_reader.as_ref()?.lookup_typed::<geoip2::Asn>(ip)? {
+            Record::Typed(record) => {
+                let org = record.autonomous_system_organization.map(str::to_string);
+                Some((record.autonomous_system_number?, org))
+            }
+            Record::Value(record) => schema::asn(&record),
+        }
+    }
 }
 
 // ========================================
@@ -138,4 +372,60 @@ mod tests {
 
         println!("Skipping test: no GeoIP database found");
     }
+
+    #[test]
+    fn test_asn_label() {
+        let mut result = GeoResult {
+            iso_code: "JP".to_string(),
+            name: "Japan".to_string(),
+            asn: Some(20473),
+            as_org: Some("AS-CHOOPA".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(result.asn_label().as_deref(), Some("AS20473 AS-CHOOPA"));
+
+        result.as_org = None;
+        assert_eq!(result.asn_label().as_deref(), Some("AS20473"));
+
+        result.asn = None;
+        assert_eq!(result.asn_label(), None);
+    }
+
+    #[test]
+    fn test_label_display() {
+        let mut result = GeoResult {
+            label: Some("Tokyo node 3 (ours)".to_string()),
+            iso_code: "JP".to_string(),
+            name: "Japan".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(result.to_string(), "Tokyo node 3 (ours) (JP)");
+
+        result.iso_code = "??".to_string();
+        assert_eq!(result.to_string(), "Tokyo node 3 (ours)");
+
+        result.label = None;
+        assert_eq!(result.to_string(), "?? Japan");
+
+        result.iso_code = "CN".to_string();
+        result.name.clear();
+        assert_eq!(result.to_string(), "CN");
+    }
+
+    #[test]
+    fn test_place() {
+        let mut result = GeoResult {
+            subdivision: Some("Tokyo".to_string()),
+            city: Some("Tokyo".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(result.place().as_deref(), Some("Tokyo"));
+
+        result.city = Some("Shinjuku".to_string());
+        assert_eq!(result.place().as_deref(), Some("Tokyo Shinjuku"));
+
+        result.city = None;
+        result.subdivision = None;
+        assert_eq!(result.place(), None);
+    }
 }
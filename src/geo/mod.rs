@@ -1,8 +1,13 @@
 //! # GeoIP 模块
 //!
-//! 提供 GeoIP 查询功能，将 IP 地址映射到国家/地区。
+//! 提供 GeoIP 查询功能，将 IP 地址映射到国家/地区，以及数据库的安全更新。
+//! 用户自定义的网段标签在 mmdb 之前生效。
 
 pub mod mmdb;
+pub mod overlay;
+mod schema;
+pub mod source;
+pub mod update;
 
 // 重新导出常用类型
 pub use mmdb::{GeoDb, GeoResult};
@@ -0,0 +1,161 @@
+//! # 自定义网段标签
+//!
+//! GeoLite 对自建节点、公司网段只能给出笼统的归属地。
+//! 这个模块读取用户维护的标签文件，在查询 mmdb 之前按最长前缀匹配打上标签。
+//!
+//! ## 文件格式
+//! 默认位置 `~/.config/proxy-audit/labels.conf`，每行一条，`#` 开头为注释：
+//! ```text
+//! 203.0.113.0/24 = "Tokyo node 3 (ours)"
+//! 10.0.0.0/8     = "Corp LAN"
+//! 2001:db8::/32  = "Lab v6"
+//! 198.51.100.7   = "Jump host"      # 单个 IP 视为 /32 或 /128
+//! ```
+
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use ipnet::IpNet;
+
+/// 默认标签文件名（位于配置目录下）
+pub const LABELS_FILE: &str = "labels.conf";
+
+/// 网段标签表
+#[derive(Debug, Clone, Default)]
+pub struct LabelOverlay {
+    /// (网段, 标签) 列表，保持文件中的顺序
+    entries: Vec<(IpNet, String)>,
+}
+
+impl LabelOverlay {
+    /// 从文件加载标签表
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read label file: {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid label file: {}", path.display()))
+    }
+
+    /// 解析标签文件内容
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (index, raw) in content.lines().enumerate() {
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((cidr, label)) = line.split_once('=') else {
+                bail!("line {}: expected `CIDR = \"label\"`", index + 1);
+            };
+            let net = parse_net(cidr.trim())
+                .with_context(|| format!("line {}: invalid CIDR '{}'", index + 1, cidr.trim()))?;
+            let label = unquote(label.trim());
+            if label.is_empty() {
+                bail!("line {}: empty label", index + 1);
+            }
+            entries.push((net, label.to_string()));
+        }
+        Ok(Self { entries })
+    }
+
+    /// 标签数量
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 按最长前缀匹配查询标签
+    ///
+    /// 前缀长度相同时，文件中靠前的条目优先。
+    pub fn lookup(&self, ip: IpAddr) -> Option<&str> {
+        let mut best: Option<&(IpNet, String)> = None;
+        for entry in &self.entries {
+            if !entry.0.contains(&ip) {
+                continue;
+            }
+            if best.is_none_or(|b| entry.0.prefix_len() > b.0.prefix_len()) {
+                best = Some(entry);
+            }
+        }
+        best.map(|(_, label)| label.as_str())
+    }
+}
+
+/// 解析网段，单个 IP 视为主机路由
+fn parse_net(s: &str) -> Result<IpNet> {
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Ok(IpNet::from(ip));
+    }
+    // trunc() 把 10.1.2.3/8 规范化为 10.0.0.0/8
+    Ok(s.parse::<IpNet>()?.trunc())
+}
+
+/// 去掉引号外的 `#` 注释
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// 去掉标签两侧的引号（允许不加引号）
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_longest_prefix() {
+        let overlay = LabelOverlay::parse(
+            r#"
+# our ranges
+10.0.0.0/8        = "Corp LAN"
+10.20.0.0/16      = "Corp VPN # pool"
+203.0.113.0/24    = "Tokyo node 3 (ours)"   # trailing comment
+198.51.100.7      = Jump host
+2001:db8::/32     = "Lab v6"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(overlay.len(), 5);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(overlay.lookup(ip("10.1.2.3")), Some("Corp LAN"));
+        assert_eq!(overlay.lookup(ip("10.20.1.1")), Some("Corp VPN # pool"));
+        assert_eq!(
+            overlay.lookup(ip("203.0.113.9")),
+            Some("Tokyo node 3 (ours)")
+        );
+        assert_eq!(overlay.lookup(ip("198.51.100.7")), Some("Jump host"));
+        assert_eq!(overlay.lookup(ip("198.51.100.8")), None);
+        assert_eq!(overlay.lookup(ip("2001:db8::1")), Some("Lab v6"));
+        assert_eq!(overlay.lookup(ip("8.8.8.8")), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(LabelOverlay::parse("10.0.0.0/8 Corp").is_err());
+        assert!(LabelOverlay::parse("10.0.0.0/33 = \"x\"").is_err());
+        assert!(LabelOverlay::parse("10.0.0.0/8 = \"\"").is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! # mmdb 记录的宽松解码
+//!
+//! 代理客户端使用的 mmdb 与 MaxMind GeoLite2 的记录结构并不统一：
+//! - GeoLite2 / DB-IP lite: `{"country": {"iso_code": "JP", "names": {...}}, ...}`
+//! - Hackl0us / Loyalsoldier 的 Clash `Country.mmdb`: 同上，但常常只有 `country.iso_code`
+//! - 部分 IP（如 anycast）只有 `registered_country`，没有 `country`
+//! - sing-geoip (`database_type = "sing-geoip"`): 记录本身是字符串，如 `"cn"`
+//! - MetaCubeX Meta-geoip (`"Meta-geoip0"`): 字符串或字符串数组，如 `["cn", "private"]`
+//! - ipinfo lite 等扁平结构: `{"country_code": "JP", "country": "Japan", "asn": "AS2516", ...}`
+//!
+//! 元数据声明为 GeoIP2/GeoLite2/DB-IP 的库直接解码为 `maxminddb::geoip2` 结构体；
+//! 其他库把记录解码为通用的 `serde_json::Value`，再按形状逐一尝试提取字段，
+//! 任何字段缺失或类型不符都只影响该字段，不会让整条查询失败。
+//! 这样 proxy-audit 显示的国家与代理客户端 GEOIP 规则使用的数据一致。
+
+use std::collections::BTreeMap;
+
+use maxminddb::geoip2;
+use serde_json::{Map, Value};
+
+use super::mmdb::DEFAULT_LANGUAGE;
+
+/// 按国家、注册国家、代表国家的顺序查找国家信息
+const COUNTRY_KEYS: [&str; 3] = ["country", "registered_country", "represented_country"];
+
+/// 记录结构与 MaxMind GeoIP2 一致的数据库类型前缀
+const MAXMIND_TYPES: [&str; 3] = ["GeoIP2", "GeoLite2", "DBIP"];
+
+/// 从一条记录中提取的位置信息
+#[derive(Debug, Default, PartialEq)]
+pub struct Place {
+    pub iso_code: Option<String>,
+    pub name: Option<String>,
+    pub city: Option<String>,
+    pub subdivision: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub accuracy_radius: Option<u16>,
+}
+
+impl Place {
+    /// 从通用结构的记录宽松提取
+    pub fn from_value(record: &Value, lang: &str) -> Self {
+        let (latitude, longitude, accuracy_radius) = location(record);
+        Self {
+            iso_code: country_code(record),
+            name: country_name(record, lang),
+            city: city_name(record, lang),
+            subdivision: subdivision_name(record, lang),
+            latitude,
+            longitude,
+            accuracy_radius,
+        }
+    }
+
+    /// 从 GeoIP2 记录提取
+    ///
+    /// Enterprise 结构是 Country/City 的超集，三种库都解码为它，缺少的字段为 None。
+    pub fn from_geoip2(record: &geoip2::Enterprise, lang: &str) -> Self {
+        let represented = record.represented_country.as_ref();
+        let countries = [record.country.as_ref(), record.registered_country.as_ref()];
+        let countries = countries.into_iter().flatten();
+        let iso_code = countries
+            .clone()
+            .find_map(|c| c.iso_code)
+            .or_else(|| represented?.iso_code)
+            .and_then(normalize_code);
+        let name = countries
+            .filter_map(|c| c.names.as_ref())
+            .chain(represented.and_then(|c| c.names.as_ref()))
+            .find_map(|names| pick_geoip2_name(names, lang));
+        let location = record.location.as_ref();
+        Self {
+            iso_code,
+            name,
+            city: record
+                .city
+                .as_ref()
+                .and_then(|c| pick_geoip2_name(c.names.as_ref()?, lang)),
+            subdivision: record
+                .subdivisions
+                .as_ref()
+                .and_then(|subs| pick_geoip2_name(subs.first()?.names.as_ref()?, lang)),
+            latitude: location.and_then(|l| l.latitude),
+            longitude: location.and_then(|l| l.longitude),
+            accuracy_radius: location.and_then(|l| l.accuracy_radius),
+        }
+    }
+}
+
+/// 数据库类型（mmdb 元数据的 `database_type`）是否使用 MaxMind GeoIP2 记录结构
+pub fn is_maxmind(database_type: &str) -> bool {
+    MAXMIND_TYPES
+        .iter()
+        .any(|prefix| database_type.starts_with(prefix))
+}
+
+/// GeoIP2 Enterprise 记录 `traits` 中的自治系统
+pub fn geoip2_asn(record: &geoip2::Enterprise) -> Option<(u32, Option<String>)> {
+    let traits = record.traits.as_ref()?;
+    let org = traits.autonomous_system_organization.map(str::to_string);
+    Some((traits.autonomous_system_number?, org))
+}
+
+/// 国家 ISO 代码
+pub fn country_code(record: &Value) -> Option<String> {
+    match record {
+        // sing-geoip: "cn"
+        Value::String(code) => normalize_code(code),
+        // Meta-geoip: ["cn", "private"]，取第一个国家代码
+        Value::Array(codes) => codes
+            .iter()
+            .filter_map(Value::as_str)
+            .find_map(normalize_code),
+        Value::Object(obj) => COUNTRY_KEYS
+            .iter()
+            .filter_map(|key| obj.get(*key))
+            .find_map(|country| match country {
+                Value::Object(c) => c.get("iso_code").and_then(Value::as_str),
+                // ipinfo 的 "country" 是国家名，只有两位时才当作代码
+                Value::String(s) if s.len() == 2 => Some(s.as_str()),
+                _ => None,
+            })
+            .or_else(|| str_field(obj, &["country_code", "iso_code"]))
+            .and_then(normalize_code),
+        _ => None,
+    }
+}
+
+/// 国家名称（按语言选取，缺少时回退到英文）
+pub fn country_name(record: &Value, lang: &str) -> Option<String> {
+    let obj = record.as_object()?;
+    COUNTRY_KEYS
+        .iter()
+        .filter_map(|key| obj.get(*key))
+        .find_map(|country| match country {
+            Value::Object(c) => localized(c, lang),
+            Value::String(s) if s.len() > 2 => Some(s.clone()),
+            _ => None,
+        })
+}
+
+/// 城市名称
+pub fn city_name(record: &Value, lang: &str) -> Option<String> {
+    match record.get("city")? {
+        Value::Object(city) => localized(city, lang),
+        Value::String(city) => Some(city.clone()),
+        _ => None,
+    }
+}
+
+/// 一级行政区（省/州）名称
+pub fn subdivision_name(record: &Value, lang: &str) -> Option<String> {
+    match record.get("subdivisions")? {
+        Value::Array(subs) => subs.first()?.as_object().and_then(|s| localized(s, lang)),
+        _ => None,
+    }
+    .or_else(|| record.get("region")?.as_str().map(str::to_string))
+}
+
+/// 坐标 (纬度, 经度, 精度半径)
+pub fn location(record: &Value) -> (Option<f64>, Option<f64>, Option<u16>) {
+    let Some(loc) = record.get("location").and_then(Value::as_object) else {
+        return (None, None, None);
+    };
+    let radius = loc
+        .get("accuracy_radius")
+        .and_then(Value::as_u64)
+        .and_then(|r| u16::try_from(r).ok());
+    (
+        loc.get("latitude").and_then(Value::as_f64),
+        loc.get("longitude").and_then(Value::as_f64),
+        radius,
+    )
+}
+
+/// 自治系统编号和组织
+///
+/// 兼容 GeoLite2-ASN（`autonomous_system_number`）、GeoIP2 的 `traits`
+/// 以及 ipinfo 的 `asn: "AS2516"` / `as_name`。
+pub fn asn(record: &Value) -> Option<(u32, Option<String>)> {
+    let obj = record.as_object()?;
+    let sources = std::iter::once(obj).chain(obj.get("traits").and_then(Value::as_object));
+    for source in sources {
+        let number = source
+            .get("autonomous_system_number")
+            .or_else(|| source.get("asn"))
+            .and_then(parse_asn);
+        if let Some(number) = number {
+            let org = str_field(
+                source,
+                &["autonomous_system_organization", "as_name", "as_org"],
+            )
+            .map(str::to_string);
+            return Some((number, org));
+        }
+    }
+    None
+}
+
+/// 从 `names` 中选取指定语言的名称
+///
+/// 依次尝试：完全匹配（"zh-CN"）→ 同一语种的变体（"zh" 匹配 "zh-CN"）→ 英文。
+pub fn pick_name(names: &Map<String, Value>, lang: &str) -> Option<String> {
+    let base = lang.split('-').next().unwrap_or(lang);
+    names
+        .get(lang)
+        .or_else(|| {
+            names
+                .iter()
+                .find(|(key, _)| key.split('-').next() == Some(base))
+                .map(|(_, name)| name)
+        })
+        .or_else(|| names.get(DEFAULT_LANGUAGE))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+// ========================================
+// 内部辅助函数
+// ========================================
+
+/// 与 `pick_name` 相同的规则，用于类型化解码的 `names`
+fn pick_geoip2_name(names: &BTreeMap<&str, &str>, lang: &str) -> Option<String> {
+    let base = lang.split('-').next().unwrap_or(lang);
+    names
+        .get(lang)
+        .or_else(|| {
+            names
+                .iter()
+                .find(|(key, _)| key.split('-').next() == Some(base))
+                .map(|(_, name)| name)
+        })
+        .or_else(|| names.get(DEFAULT_LANGUAGE))
+        .map(|name| name.to_string())
+}
+
+/// 从对象的 `names` 中按语言选取名称
+fn localized(obj: &Map<String, Value>, lang: &str) -> Option<String> {
+    pick_name(obj.get("names")?.as_object()?, lang)
+}
+
+/// 按顺序取第一个存在的字符串字段
+fn str_field<'a>(obj: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().find_map(|key| obj.get(*key)?.as_str())
+}
+
+/// 规范化国家代码：统一大写，忽略 "private" 等非国家标记
+fn normalize_code(code: &str) -> Option<String> {
+    let code = code.trim();
+    (code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()))
+        .then(|| code.to_ascii_uppercase())
+}
+
+/// 解析 ASN：数字或 "AS13335" 形式的字符串
+fn parse_asn(value: &Value) -> Option<u32> {
+    match value {
+        Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(s) => {
+            let digits = s
+                .strip_prefix("AS")
+                .or_else(|| s.strip_prefix("as"))
+                .unwrap_or(s);
+            digits.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_geolite2_city() {
+        let record = json!({
+            "city": {"names": {"en": "Tokyo", "ja": "東京"}},
+            "country": {"iso_code": "JP", "names": {"en": "Japan", "zh-CN": "日本"}},
+            "location": {"latitude": 35.6, "longitude": 139.7, "accuracy_radius": 20},
+            "subdivisions": [{"iso_code": "13", "names": {"en": "Tokyo"}}]
+        });
+        assert_eq!(country_code(&record).as_deref(), Some("JP"));
+        assert_eq!(country_name(&record, "zh").as_deref(), Some("日本"));
+        assert_eq!(city_name(&record, "ja").as_deref(), Some("東京"));
+        assert_eq!(city_name(&record, "de").as_deref(), Some("Tokyo"));
+        assert_eq!(subdivision_name(&record, "en").as_deref(), Some("Tokyo"));
+        assert_eq!(location(&record), (Some(35.6), Some(139.7), Some(20)));
+    }
+
+    #[test]
+    fn test_geoip2_matches_lenient() {
+        let json = r#"{
+            "city": {"names": {"en": "Osaka", "ja": "大阪"}},
+            "registered_country": {"iso_code": "JP", "names": {"en": "Japan", "ja": "日本"}},
+            "location": {"latitude": 34.7, "longitude": 135.5, "accuracy_radius": 50},
+            "subdivisions": [{"iso_code": "27", "names": {"en": "Osaka"}}],
+            "traits": {"autonomous_system_number": 2516}
+        }"#;
+        let typed: geoip2::Enterprise = serde_json::from_str(json).unwrap();
+        let value: Value = serde_json::from_str(json).unwrap();
+        for lang in ["ja", "zh-CN"] {
+            assert_eq!(
+                Place::from_geoip2(&typed, lang),
+                Place::from_value(&value, lang)
+            );
+        }
+        assert_eq!(geoip2_asn(&typed), Some((2516, None)));
+        assert!(is_maxmind("GeoLite2-City"));
+        assert!(!is_maxmind("sing-geoip"));
+    }
+
+    #[test]
+    fn test_clash_country_variants() {
+        // 只有 iso_code
+        let record = json!({"country": {"iso_code": "CN"}});
+        assert_eq!(country_code(&record).as_deref(), Some("CN"));
+        assert_eq!(country_name(&record, "en"), None);
+
+        // 只有 registered_country
+        let record =
+            json!({"registered_country": {"iso_code": "US", "names": {"en": "United States"}}});
+        assert_eq!(country_code(&record).as_deref(), Some("US"));
+        assert_eq!(
+            country_name(&record, "en").as_deref(),
+            Some("United States")
+        );
+
+        // sing-geoip / Meta-geoip
+        assert_eq!(country_code(&json!("cn")).as_deref(), Some("CN"));
+        assert_eq!(
+            country_code(&json!(["private", "cn"])).as_deref(),
+            Some("CN")
+        );
+        assert_eq!(country_code(&json!("private")), None);
+    }
+
+    #[test]
+    fn test_flat_schema_and_asn() {
+        let record = json!({
+            "country_code": "JP",
+            "country": "Japan",
+            "asn": "AS2516",
+            "as_name": "KDDI CORPORATION"
+        });
+        assert_eq!(country_code(&record).as_deref(), Some("JP"));
+        assert_eq!(country_name(&record, "en").as_deref(), Some("Japan"));
+        assert_eq!(
+            asn(&record),
+            Some((2516, Some("KDDI CORPORATION".to_string())))
+        );
+
+        let record = json!({
+            "autonomous_system_number": 15169,
+            "autonomous_system_organization": "GOOGLE"
+        });
+        assert_eq!(asn(&record), Some((15169, Some("GOOGLE".to_string()))));
+        assert_eq!(asn(&json!("cn")), None);
+    }
+
+    #[test]
+    fn test_pick_name() {
+        let names = json!({"en": "Japan", "ja": "日本", "zh-CN": "日本国"});
+        let names = names.as_object().unwrap();
+        assert_eq!(pick_name(names, "ja").as_deref(), Some("日本"));
+        assert_eq!(pick_name(names, "zh-CN").as_deref(), Some("日本国"));
+        assert_eq!(pick_name(names, "zh").as_deref(), Some("日本国"));
+        assert_eq!(pick_name(names, "de").as_deref(), Some("Japan"));
+    }
+}
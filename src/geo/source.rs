@@ -0,0 +1,286 @@
+//! # GeoIP 数据库来源解析
+//!
+//! scan、geo info、geo lookup 共用同一套查找顺序：
+//! 1. CLI 参数 `--geo-db`
+//! 2. 配置目录 `~/.config/proxy-audit/`（City 优先于 Country）
+//! 3. (Dev) 桌面开发路径
+//! 4. 最终 Fallback: 嵌入在二进制文件中的数据库（`embedded-geoip` 特性，默认开启）
+//!
+//! 外部文件打开失败时给出警告并回退到嵌入版本。
+//! 自定义网段标签文件（`--labels` 或配置目录下的 `labels.conf`）同时加载。
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::mmdb::DEFAULT_LANGUAGE;
+use super::overlay::{LabelOverlay, LABELS_FILE};
+use super::GeoDb;
+
+// ========================================
+// 常量定义
+// ========================================
+
+/// 编译时嵌入的数据库
+#[cfg(all(feature = "embedded-geoip", not(feature = "embedded-geoip-gzip")))]
+const EMBEDDED_DB_BYTES: &[u8] =
+    include_bytes!("../../GeoLite2-Country_20251212/GeoLite2-Country.mmdb");
+
+/// 编译时嵌入的 gzip 压缩数据库（由 build.rs 生成）
+#[cfg(feature = "embedded-geoip-gzip")]
+const EMBEDDED_DB_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/GeoLite2-Country.mmdb.gz"));
+
+/// 数据库构建时间超过这个天数视为过期
+pub const STALE_AFTER_DAYS: u64 = 30;
+
+// ========================================
+// 数据库来源
+// ========================================
+
+/// 实际加载的数据库来源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeoSource {
+    /// CLI 参数指定的文件
+    Cli(PathBuf),
+    /// 配置目录下的文件
+    ConfigDir(PathBuf),
+    /// 开发路径下的文件
+    DevPath(PathBuf),
+    /// 嵌入在二进制文件中的数据库
+    Embedded,
+}
+
+impl GeoSource {
+    /// 外部文件路径（嵌入版本返回 None）
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            GeoSource::Cli(p) | GeoSource::ConfigDir(p) | GeoSource::DevPath(p) => Some(p),
+            GeoSource::Embedded => None,
+        }
+    }
+}
+
+impl std::fmt::Display for GeoSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeoSource::Cli(p) => write!(f, "CLI argument ({})", p.display()),
+            GeoSource::ConfigDir(p) => write!(f, "config dir ({})", p.display()),
+            GeoSource::DevPath(p) => write!(f, "dev path ({})", p.display()),
+            GeoSource::Embedded => write!(f, "embedded"),
+        }
+    }
+}
+
+/// 数据库加载选项（对应 CLI 参数）
+#[derive(Debug, Clone, Default)]
+pub struct GeoDbOptions {
+    /// `--geo-db` 指定的主数据库
+    pub geo_db: Option<String>,
+    /// `--asn-db` 指定的 ASN 数据库
+    pub asn_db: Option<String>,
+    /// `--lang` 指定的地名语言
+    pub lang: String,
+    /// `--labels` 指定的网段标签文件
+    pub labels: Option<String>,
+}
+
+/// 加载完成的数据库及其来源
+pub struct LoadedGeoDb {
+    /// 数据库实例
+    pub db: GeoDb,
+    /// 主数据库来源
+    pub source: GeoSource,
+    /// 成功加载的 ASN 数据库路径
+    pub asn_path: Option<PathBuf>,
+    /// 成功加载的网段标签文件路径及条目数
+    pub labels: Option<(PathBuf, usize)>,
+}
+
+// ========================================
+// 公开 API 函数
+// ========================================
+
+/// 按优先级查找并加载数据库
+///
+/// ## 参数
+/// - `options`: CLI 参数
+/// - `config_dir`: 配置目录（`~/.config/proxy-audit`）
+/// - `home`: 用户主目录（用于开发路径）
+///
+/// ## 返回
+/// 外部文件和嵌入版本都不可用时返回 None
+pub fn open_geodb(options: &GeoDbOptions, config_dir: &Path, home: &str) -> Option<LoadedGeoDb> {
+    // 尝试查找外部文件
+    let city_path = config_dir.join("GeoLite2-City.mmdb");
+    let country_path = config_dir.join("GeoLite2-Country.mmdb");
+    let dev_path = PathBuf::from(format!(
+        "{}/Desktop/backend/net_check/proxy-audit/GeoLite2-Country_20251212/GeoLite2-Country.mmdb",
+        home
+    ));
+
+    let file_source = if let Some(p) = &options.geo_db {
+        Some(GeoSource::Cli(PathBuf::from(p)))
+    } else if city_path.exists() {
+        Some(GeoSource::ConfigDir(city_path))
+    } else if country_path.exists() {
+        Some(GeoSource::ConfigDir(country_path))
+    } else if dev_path.exists() {
+        Some(GeoSource::DevPath(dev_path))
+    } else {
+        None
+    };
+
+    // 加载数据库实例，外部文件失败时使用嵌入版本
+    let opened = file_source.and_then(|source| {
+        let path = source.path()?.to_path_buf();
+        match GeoDb::open(&path) {
+            Ok(db) => Some((db, source)),
+            Err(e) => {
+                eprintln!("Warning: Failed to open GeoDB at {}: {}", path.display(), e);
+                None
+            }
+        }
+    });
+    let (mut db, source) = match opened {
+        Some(loaded) => loaded,
+        None => (load_embedded()?, GeoSource::Embedded),
+    };
+
+    // ASN 数据库: CLI 参数优先，否则使用配置目录下的默认文件（存在时）
+    let default_asn_path = config_dir.join("GeoLite2-ASN.mmdb");
+    let asn_path = options
+        .asn_db
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| default_asn_path.exists().then_some(default_asn_path));
+    let asn_path = asn_path.filter(|path| match db.attach_asn(path) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!(
+                "Warning: Failed to open ASN database at {}: {}",
+                path.display(),
+                e
+            );
+            false
+        }
+    });
+
+    // 网段标签: CLI 参数优先，否则使用配置目录下的默认文件（存在时）
+    let default_labels_path = config_dir.join(LABELS_FILE);
+    let labels_path = options
+        .labels
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| default_labels_path.exists().then_some(default_labels_path));
+    let labels = labels_path.and_then(|path| match LabelOverlay::load(&path) {
+        Ok(overlay) if overlay.is_empty() => {
+            eprintln!("Warning: label file {} has no entries", path.display());
+            None
+        }
+        Ok(overlay) => {
+            let count = overlay.len();
+            db.set_overlay(overlay);
+            Some((path, count))
+        }
+        Err(e) => {
+            eprintln!("Warning: {:#}", e);
+            None
+        }
+    });
+
+    // 只有国家代码的数据库（如 sing-geoip）没有任何地名，默认英文时不必提示
+    let supported = db.set_language(&options.lang);
+    if !supported && options.lang != DEFAULT_LANGUAGE {
+        eprintln!(
+            "Warning: GeoIP database has no '{}' names, falling back to English",
+            options.lang
+        );
+    }
+
+    Some(LoadedGeoDb {
+        db,
+        source,
+        asn_path,
+        labels,
+    })
+}
+
+/// 加载嵌入的 GeoIP 数据库
+///
+/// 未压缩时直接引用二进制中的静态数据，不做拷贝；
+/// 压缩时解压一次并在进程生命周期内保留；构建时关闭了 `embedded-geoip` 则返回 None。
+pub fn load_embedded() -> Option<GeoDb> {
+    match open_embedded() {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Warning: Failed to load embedded GeoDB: {:#}", e);
+            None
+        }
+    }
+}
+
+#[cfg(all(feature = "embedded-geoip", not(feature = "embedded-geoip-gzip")))]
+fn open_embedded() -> anyhow::Result<Option<GeoDb>> {
+    GeoDb::from_static(EMBEDDED_DB_BYTES).map(Some)
+}
+
+#[cfg(feature = "embedded-geoip-gzip")]
+fn open_embedded() -> anyhow::Result<Option<GeoDb>> {
+    use std::io::Read;
+
+    let mut bytes = Vec::new();
+    flate2::read::GzDecoder::new(EMBEDDED_DB_GZ).read_to_end(&mut bytes)?;
+    // 数据库在整个进程中都会使用，直接泄漏为 'static 与未压缩版本共用同一条路径
+    GeoDb::from_static(Box::leak(bytes.into_boxed_slice())).map(Some)
+}
+
+#[cfg(not(feature = "embedded-geoip"))]
+fn open_embedded() -> anyhow::Result<Option<GeoDb>> {
+    Ok(None)
+}
+
+/// 数据库构建至今的天数
+pub fn age_days(build_epoch: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    now.saturating_sub(build_epoch) / 86_400
+}
+
+/// 把 Unix 时间戳格式化为 UTC 日期（YYYY-MM-DD）
+pub fn format_date(epoch: u64) -> String {
+    // Howard Hinnant 的 civil_from_days 算法，避免为此引入日期库
+    let days = (epoch / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_765_497_600), "2025-12-12");
+    }
+
+    #[test]
+    fn test_source_display() {
+        assert_eq!(GeoSource::Embedded.to_string(), "embedded");
+        let source = GeoSource::Cli(PathBuf::from("/tmp/a.mmdb"));
+        assert_eq!(source.to_string(), "CLI argument (/tmp/a.mmdb)");
+        assert_eq!(source.path(), Some(Path::new("/tmp/a.mmdb")));
+    }
+}
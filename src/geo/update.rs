@@ -0,0 +1,847 @@
+//! # GeoIP 数据库更新
+//!
+//! 这个模块负责安全地下载并替换 mmdb 数据库文件：
+//! 1. 带上次记录的 ETag / Last-Modified 发起条件请求，未变化时服务器返回 304，不重复下载
+//! 2. 检查 HTTP 状态码，下载到同目录的临时文件
+//! 3. 镜像提供 `.sha256sum` / `.sha256` 校验文件时校验 SHA-256
+//! 4. 用 `GeoDb` 打开临时文件并做一次查询，确认是可用的 mmdb
+//! 5. 旧文件硬链接为 `.bak` 以便回滚，再用 rename 原子替换目标文件
+//!
+//! 下载中断或校验失败时目标文件保持不变。
+//!
+//! ## MaxMind 官方下载
+//! 提供账号 ID 和 License Key 时可以直接从 MaxMind 下载 `.tar.gz` 压缩包，
+//! 解压出 mmdb 以及 COPYRIGHT.txt / LICENSE.txt（保存为 `<数据库>.COPYRIGHT.txt` 等）。
+//!
+//! ## 线路与镜像回退
+//! 默认镜像在 raw.githubusercontent.com，国内直连经常不可达。
+//! 更新时按顺序尝试多条线路（显式代理 → 系统代理 → 本地代理监听端口 → 直连），
+//! 每条线路上再依次尝试镜像列表，第一个成功的组合生效。
+//!
+//! ## 使用示例
+//! ```rust,ignore
+//! let routes = [DownloadRoute::Explicit("socks5h://127.0.0.1:7890".into()), DownloadRoute::Direct];
+//! let sources = [DownloadSource::Mirror(url)];
+//! let report = update_database(&sources, &routes, Path::new("GeoLite2-Country.mmdb"), false)?;
+//! println!("via {} from {}", report.route, report.source);
+//! ```
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::Proxy;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::GeoDb;
+
+// ========================================
+// 常量定义
+// ========================================
+
+/// 下载超时（数据库一般为几 MB 到几十 MB）
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 连接超时（不可达的线路/镜像尽快失败，换下一个）
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 镜像常见的校验文件后缀，按顺序尝试
+const CHECKSUM_SUFFIXES: [&str; 2] = [".sha256sum", ".sha256"];
+
+/// MaxMind 官方下载地址（`{edition}` 为数据库版本，如 GeoLite2-Country）
+const MAXMIND_DOWNLOAD_URL: &str =
+    "https://download.maxmind.com/geoip/databases/{edition}/download";
+
+/// 压缩包中随数据库一起保留的许可文件
+const LICENSE_FILES: [&str; 2] = ["COPYRIGHT.txt", "LICENSE.txt"];
+
+/// 校验下载结果时探测的地址（境外和国内公共 DNS 各两个，兼容只收录部分网段的库）
+const VERIFY_PROBES: [Ipv4Addr; 4] = [
+    Ipv4Addr::new(8, 8, 8, 8),
+    Ipv4Addr::new(1, 1, 1, 1),
+    Ipv4Addr::new(114, 114, 114, 114),
+    Ipv4Addr::new(223, 5, 5, 5),
+];
+
+/// 解压出的许可文件 (文件名, 内容)
+type LicenseFiles = Vec<(String, Vec<u8>)>;
+
+// ========================================
+// 下载线路
+// ========================================
+
+/// 下载数据库使用的网络线路
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadRoute {
+    /// `--proxy` 显式指定的代理
+    Explicit(String),
+    /// 系统代理设置中的代理
+    SystemProxy(String),
+    /// 本地监听端口上识别出的代理
+    LocalListener(String),
+    /// 不使用代理
+    Direct,
+}
+
+impl DownloadRoute {
+    /// 代理地址（直连返回 None）
+    pub fn proxy_url(&self) -> Option<&str> {
+        match self {
+            DownloadRoute::Explicit(url)
+            | DownloadRoute::SystemProxy(url)
+            | DownloadRoute::LocalListener(url) => Some(url),
+            DownloadRoute::Direct => None,
+        }
+    }
+
+    /// 为该线路构建 HTTP 客户端
+    fn client(&self) -> Result<Client> {
+        let mut builder = Client::builder()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT);
+        builder = match self.proxy_url() {
+            Some(url) => builder
+                .proxy(Proxy::all(url).with_context(|| format!("Invalid proxy URL: {}", url))?),
+            // 直连时也忽略 HTTP_PROXY 等环境变量，保证线路名副其实
+            None => builder.no_proxy(),
+        };
+        builder.build().context("Failed to build HTTP client")
+    }
+}
+
+impl std::fmt::Display for DownloadRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadRoute::Explicit(url) => write!(f, "proxy {}", url),
+            DownloadRoute::SystemProxy(url) => write!(f, "system proxy {}", url),
+            DownloadRoute::LocalListener(url) => write!(f, "local proxy {}", url),
+            DownloadRoute::Direct => write!(f, "direct"),
+        }
+    }
+}
+
+// ========================================
+// 下载来源
+// ========================================
+
+/// 数据库下载来源
+#[derive(Debug, Clone)]
+pub enum DownloadSource {
+    /// 直接提供 mmdb 文件的镜像
+    Mirror(String),
+    /// MaxMind 官方下载（tar.gz 压缩包，需要账号）
+    MaxMind {
+        /// 数据库版本，如 GeoLite2-Country、GeoLite2-City、GeoLite2-ASN
+        edition: String,
+        /// 账号 ID
+        account_id: String,
+        /// License Key
+        license_key: String,
+    },
+}
+
+impl DownloadSource {
+    /// 下载地址
+    fn url(&self) -> String {
+        match self {
+            DownloadSource::Mirror(url) => url.clone(),
+            DownloadSource::MaxMind { edition, .. } => format!(
+                "{}?suffix=tar.gz",
+                MAXMIND_DOWNLOAD_URL.replace("{edition}", edition)
+            ),
+        }
+    }
+
+    /// 可能存在的校验文件地址，按顺序尝试
+    fn checksum_urls(&self) -> Vec<String> {
+        match self {
+            DownloadSource::Mirror(url) => CHECKSUM_SUFFIXES
+                .iter()
+                .map(|suffix| format!("{}{}", url, suffix))
+                .collect(),
+            DownloadSource::MaxMind { edition, .. } => vec![format!(
+                "{}?suffix=tar.gz.sha256",
+                MAXMIND_DOWNLOAD_URL.replace("{edition}", edition)
+            )],
+        }
+    }
+
+    /// 构建带认证信息的 GET 请求
+    fn get(&self, client: &Client, url: &str) -> RequestBuilder {
+        let request = client.get(url);
+        match self {
+            DownloadSource::Mirror(_) => request,
+            DownloadSource::MaxMind {
+                account_id,
+                license_key,
+                ..
+            } => request.basic_auth(account_id, Some(license_key)),
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadSource::Mirror(url) => write!(f, "{}", url),
+            // 不输出 License Key
+            DownloadSource::MaxMind { edition, .. } => write!(f, "MaxMind {}", edition),
+        }
+    }
+}
+
+/// 成功的更新及其线路、来源
+#[derive(Debug)]
+pub struct UpdateReport {
+    /// 更新结果
+    pub outcome: UpdateOutcome,
+    /// 成功的线路
+    pub route: DownloadRoute,
+    /// 成功的下载来源
+    pub source: DownloadSource,
+}
+
+// ========================================
+// 更新结果
+// ========================================
+
+/// 一次更新的结果
+#[derive(Debug)]
+pub enum UpdateOutcome {
+    /// 下载并替换了数据库
+    Updated {
+        /// 文件大小（字节）
+        size: usize,
+        /// mmdb 元数据中的数据库类型（如 "GeoLite2-Country"）
+        database_type: String,
+        /// 数据库构建时间（Unix 时间戳）
+        build_epoch: u64,
+        /// 是否通过了镜像提供的 SHA-256 校验
+        checksum_verified: bool,
+    },
+    /// 服务器返回 304，本地已是最新
+    NotModified,
+}
+
+/// 把代理服务器地址转换为下载使用的代理 URL
+///
+/// SOCKS 使用 socks5h，让代理端解析域名（避免本地 DNS 污染）。
+pub fn proxy_url(scheme: &str, host: &str, port: u16) -> String {
+    let scheme = if scheme == "socks5" {
+        "socks5h"
+    } else {
+        scheme
+    };
+    if host.contains(':') && !host.starts_with('[') {
+        format!("{}://[{}]:{}", scheme, host, port)
+    } else {
+        format!("{}://{}:{}", scheme, host, port)
+    }
+}
+
+/// 去掉重复的代理线路，保留第一次出现的位置
+pub fn dedup_routes(routes: Vec<DownloadRoute>) -> Vec<DownloadRoute> {
+    let mut seen = std::collections::HashSet::new();
+    routes
+        .into_iter()
+        .filter(|route| seen.insert(route.proxy_url().map(str::to_string)))
+        .collect()
+}
+
+/// 上次下载的条件请求信息，保存在 `<数据库>.meta.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UpdateMeta {
+    /// 下载地址（地址变化时不使用条件请求）
+    url: String,
+    /// 响应头 ETag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    /// 响应头 Last-Modified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+impl UpdateMeta {
+    /// 读取元数据文件，不存在或损坏时返回 None
+    fn load(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// 写入元数据文件
+    fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write update metadata: {}", path.display()))
+    }
+}
+
+// ========================================
+// 公开 API 函数
+// ========================================
+
+/// 依次尝试线路和下载来源，下载数据库并原子替换目标文件
+///
+/// ## 参数
+/// - `sources`: 下载来源列表（按优先级）
+/// - `routes`: 下载线路列表（按优先级）
+/// - `target`: 目标 mmdb 路径
+/// - `force`: 为 true 时不发送条件请求，总是重新下载
+///
+/// ## 返回
+/// 第一个成功的 (线路, 来源) 组合及结果；全部失败时返回包含每次尝试原因的错误
+pub fn update_database(
+    sources: &[DownloadSource],
+    routes: &[DownloadRoute],
+    target: &Path,
+    force: bool,
+) -> Result<UpdateReport> {
+    if sources.is_empty() {
+        bail!("No GeoIP mirror configured");
+    }
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    }
+
+    let mut failures = Vec::new();
+    for route in routes {
+        let client = match route.client() {
+            Ok(client) => client,
+            Err(e) => {
+                failures.push(format!("{}: {:#}", route, e));
+                continue;
+            }
+        };
+        for source in sources {
+            match download_and_install(&client, source, target, force) {
+                Ok(outcome) => {
+                    return Ok(UpdateReport {
+                        outcome,
+                        route: route.clone(),
+                        source: source.clone(),
+                    })
+                }
+                Err(e) => {
+                    eprintln!("  failed via {} from {}: {:#}", route, source, e);
+                    failures.push(format!("{} / {}: {:#}", route, source, e));
+                }
+            }
+        }
+    }
+
+    bail!(
+        "All {} download attempts failed:\n  {}",
+        failures.len(),
+        failures.join("\n  ")
+    )
+}
+
+/// 通过指定客户端从一个来源下载、校验并安装数据库
+fn download_and_install(
+    client: &Client,
+    source: &DownloadSource,
+    target: &Path,
+    force: bool,
+) -> Result<UpdateOutcome> {
+    // 1. 条件请求：只有目标文件存在且下载来源未变时才带上缓存验证头
+    //    元数据中记录来源的显示名，避免把 License Key 写入磁盘
+    let url = source.url();
+    let source_key = source.to_string();
+    let meta_path = sidecar_path(target, "meta.json");
+    let previous = UpdateMeta::load(&meta_path).filter(|m| m.url == source_key);
+
+    let mut request = source.get(client, &url);
+    if !force && target.exists() {
+        if let Some(meta) = &previous {
+            if let Some(etag) = &meta.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &meta.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+    }
+
+    let response = request
+        .send()
+        .with_context(|| format!("Failed to request {}", source))?;
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(UpdateOutcome::NotModified);
+    }
+    if status == StatusCode::UNAUTHORIZED {
+        bail!(
+            "{} rejected the account ID / license key (HTTP 401)",
+            source
+        );
+    }
+    if !status.is_success() {
+        bail!("Download failed: {} returned HTTP {}", source, status);
+    }
+
+    let header = |name: HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let meta = UpdateMeta {
+        url: source_key,
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    let content = response
+        .bytes()
+        .with_context(|| format!("Failed to read response body from {}", source))?;
+
+    // 2. 校验和（镜像没有发布校验文件时跳过；MaxMind 校验的是压缩包）
+    let checksum_verified = match fetch_checksum(client, source) {
+        Some(expected) => {
+            let actual = sha256_hex(&content);
+            if actual != expected {
+                bail!(
+                    "Checksum mismatch: expected {}, got {} (download corrupted or tampered)",
+                    expected,
+                    actual
+                );
+            }
+            true
+        }
+        None => false,
+    };
+
+    // 3. MaxMind 压缩包先解压出 mmdb 和许可文件
+    let (database, license_files) = match source {
+        DownloadSource::Mirror(_) => (content.to_vec(), Vec::new()),
+        DownloadSource::MaxMind { .. } => extract_archive(&content)?,
+    };
+
+    // 4. 写入临时文件并验证（文件名带上 pid，同时运行的多个 update-geo 不会互相覆盖）
+    let temp_path = sidecar_path(target, &format!("download-{}", std::process::id()));
+    let verified = write_file(&temp_path, &database).and_then(|_| verify_database(&temp_path));
+    let (database_type, build_epoch) = match verified {
+        Ok(info) => info,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+
+    // 5. 保留旧版本并原子替换
+    install(&temp_path, target)?;
+
+    for (name, data) in &license_files {
+        let path = sidecar_path_with_stem(target, name);
+        if let Err(e) = fs::write(&path, data) {
+            eprintln!("Warning: Failed to write {}: {}", path.display(), e);
+        }
+    }
+
+    if let Err(e) = meta.save(&meta_path) {
+        eprintln!("Warning: {:#}", e);
+    }
+
+    Ok(UpdateOutcome::Updated {
+        size: database.len(),
+        database_type,
+        build_epoch,
+        checksum_verified,
+    })
+}
+
+/// 回滚到上一次更新前的数据库
+pub fn rollback_database(target: &Path) -> Result<()> {
+    let backup = backup_path(target);
+    if !backup.exists() {
+        bail!("No previous database to roll back to: {}", backup.display());
+    }
+    verify_database(&backup)?;
+
+    fs::rename(&backup, target).with_context(|| {
+        format!(
+            "Failed to restore {} to {}",
+            backup.display(),
+            target.display()
+        )
+    })?;
+    // 回滚后的文件与服务器上的版本不同，清除条件请求信息，下次更新会重新下载
+    let _ = fs::remove_file(sidecar_path(target, "meta.json"));
+    Ok(())
+}
+
+/// 旧版本数据库的备份路径（`<数据库>.bak`）
+pub fn backup_path(target: &Path) -> PathBuf {
+    sidecar_path(target, "bak")
+}
+
+/// 数据库的年龄（按 mmdb 元数据中的构建时间计算）
+///
+/// 文件不存在或无法打开时返回 None。
+pub fn database_age(target: &Path) -> Option<Duration> {
+    let db = GeoDb::open(target).ok()?;
+    let built = UNIX_EPOCH + Duration::from_secs(db.metadata().build_epoch);
+    Some(SystemTime::now().duration_since(built).unwrap_or_default())
+}
+
+/// 解析时长，如 "7d"、"12h"、"2w"、"30m"（不带单位时按天计算）
+pub fn parse_age(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{}', expected e.g. 7d, 12h, 2w", s))?;
+    let unit_secs = match unit {
+        "" | "d" => 86_400,
+        "h" => 3_600,
+        "m" => 60,
+        "w" => 7 * 86_400,
+        _ => bail!("Invalid duration unit in '{}', expected m, h, d or w", s),
+    };
+    let secs = number
+        .checked_mul(unit_secs)
+        .with_context(|| format!("Duration '{}' is too large", s))?;
+    Ok(Duration::from_secs(secs))
+}
+
+// ========================================
+// 内部辅助函数
+// ========================================
+
+/// 同目录下的附属文件路径，如 `GeoLite2-Country.mmdb.bak`
+fn sidecar_path(target: &Path, suffix: &str) -> PathBuf {
+    let mut name = target.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// 以数据库文件名（去掉 .mmdb）为前缀的附属文件，如 `GeoLite2-Country.COPYRIGHT.txt`
+fn sidecar_path_with_stem(target: &Path, name: &str) -> PathBuf {
+    let stem = target
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!("{}.{}", stem, name))
+}
+
+/// 从 MaxMind 的 tar.gz 压缩包中取出 mmdb 和许可文件
+///
+/// 压缩包结构为 `GeoLite2-Country_20251212/{GeoLite2-Country.mmdb, COPYRIGHT.txt, LICENSE.txt}`。
+fn extract_archive(archive: &[u8]) -> Result<(Vec<u8>, LicenseFiles)> {
+    let mut tar = tar::Archive::new(GzDecoder::new(archive));
+    let mut database = None;
+    let mut license_files = Vec::new();
+
+    for entry in tar.entries().context("Failed to read tar.gz archive")? {
+        let mut entry = entry.context("Corrupted tar.gz archive")?;
+        let path = entry.path()?.into_owned();
+        let Some(name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(str::to_string)
+        else {
+            continue;
+        };
+
+        if name.ends_with(".mmdb") || LICENSE_FILES.contains(&name.as_str()) {
+            let mut data = Vec::new();
+            entry
+                .read_to_end(&mut data)
+                .with_context(|| format!("Failed to extract {}", path.display()))?;
+            if name.ends_with(".mmdb") {
+                database = Some(data);
+            } else {
+                license_files.push((name, data));
+            }
+        }
+    }
+
+    let database = database.context("Archive contains no .mmdb file")?;
+    Ok((database, license_files))
+}
+
+/// 写入文件并落盘
+fn write_file(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file =
+        fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// 打开 mmdb 并按数据库类型做结构检查，确认文件可用
+///
+/// 不要求命中国家：ASN 库只有 ASN 字段，部分 Country.mmdb 只收录 CN 网段。
+/// 返回数据库类型和构建时间。
+fn verify_database(path: &Path) -> Result<(String, u64)> {
+    let db = GeoDb::open(path).context("Downloaded file is not a valid mmdb database")?;
+    if !db.has_records(&VERIFY_PROBES.map(IpAddr::V4)) {
+        bail!(
+            "Downloaded {} database returned no usable record for any probe address",
+            db.metadata().database_type
+        );
+    }
+    let metadata = db.metadata();
+    Ok((metadata.database_type.clone(), metadata.build_epoch))
+}
+
+/// 用临时文件替换目标文件
+///
+/// 旧文件先硬链接为 `.bak`，再 rename 覆盖目标，
+/// 因此任意时刻目标路径都指向一个完整的数据库。
+fn install(temp_path: &Path, target: &Path) -> Result<()> {
+    if target.exists() {
+        let backup = backup_path(target);
+        let _ = fs::remove_file(&backup);
+        if fs::hard_link(target, &backup).is_err() {
+            fs::copy(target, &backup)
+                .with_context(|| format!("Failed to back up {}", target.display()))?;
+        }
+    }
+
+    fs::rename(temp_path, target)
+        .with_context(|| format!("Failed to move new database to {}", target.display()))
+}
+
+/// 获取镜像发布的 SHA-256（如 `GeoLite2-Country.mmdb.sha256sum`）
+///
+/// 校验文件不存在或格式无法识别时返回 None。
+fn fetch_checksum(client: &Client, source: &DownloadSource) -> Option<String> {
+    source.checksum_urls().iter().find_map(|url| {
+        let response = source.get(client, url).send().ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        parse_checksum(&response.text().ok()?)
+    })
+}
+
+/// 解析 sha256sum 格式（`<hex>  <文件名>`）或纯哈希文本
+fn parse_checksum(text: &str) -> Option<String> {
+    let token = text.split_whitespace().next()?;
+    let is_sha256 = token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit());
+    is_sha256.then(|| token.to_ascii_lowercase())
+}
+
+/// 计算 SHA-256 的十六进制表示
+fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checksum() {
+        let hash = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+        let line = format!("{}  GeoLite2-Country.mmdb\n", hash);
+        assert_eq!(parse_checksum(&line), Some(hash.to_ascii_lowercase()));
+        assert_eq!(parse_checksum(hash), Some(hash.to_ascii_lowercase()));
+        assert_eq!(parse_checksum("<html>Not Found</html>"), None);
+        assert_eq!(parse_checksum(""), None);
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"test"),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        let target = Path::new("/tmp/GeoLite2-Country.mmdb");
+        assert_eq!(
+            backup_path(target),
+            PathBuf::from("/tmp/GeoLite2-Country.mmdb.bak")
+        );
+        assert_eq!(
+            sidecar_path(target, "meta.json"),
+            PathBuf::from("/tmp/GeoLite2-Country.mmdb.meta.json")
+        );
+    }
+
+    #[test]
+    fn test_proxy_url_and_dedup() {
+        assert_eq!(
+            proxy_url("socks5", "127.0.0.1", 7890),
+            "socks5h://127.0.0.1:7890"
+        );
+        assert_eq!(proxy_url("http", "::1", 6152), "http://[::1]:6152");
+
+        let routes = dedup_routes(vec![
+            DownloadRoute::SystemProxy("http://127.0.0.1:7890".to_string()),
+            DownloadRoute::LocalListener("http://127.0.0.1:7890".to_string()),
+            DownloadRoute::LocalListener("socks5h://127.0.0.1:7891".to_string()),
+            DownloadRoute::Direct,
+        ]);
+        assert_eq!(routes.len(), 3);
+        assert_eq!(
+            routes[0],
+            DownloadRoute::SystemProxy("http://127.0.0.1:7890".to_string())
+        );
+        assert_eq!(routes[2], DownloadRoute::Direct);
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("7d").unwrap(), Duration::from_secs(7 * 86_400));
+        assert_eq!(parse_age("12h").unwrap(), Duration::from_secs(12 * 3_600));
+        assert_eq!(parse_age("2w").unwrap(), Duration::from_secs(14 * 86_400));
+        assert_eq!(parse_age("3").unwrap(), Duration::from_secs(3 * 86_400));
+        assert!(parse_age("7y").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn test_extract_archive() {
+        use flate2::write::GzEncoder;
+
+        // 构造与 MaxMind 相同结构的压缩包
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Default::default()));
+        for (name, data) in [
+            ("GeoLite2-Country_20251212/COPYRIGHT.txt", &b"copyright"[..]),
+            (
+                "GeoLite2-Country_20251212/GeoLite2-Country.mmdb",
+                &b"mmdb"[..],
+            ),
+            ("GeoLite2-Country_20251212/README.txt", &b"ignored"[..]),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data).unwrap();
+        }
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let (database, license_files) = extract_archive(&archive).unwrap();
+        assert_eq!(database, b"mmdb");
+        assert_eq!(
+            license_files,
+            vec![("COPYRIGHT.txt".to_string(), b"copyright".to_vec())]
+        );
+        assert_eq!(
+            sidecar_path_with_stem(Path::new("/tmp/GeoLite2-Country.mmdb"), "COPYRIGHT.txt"),
+            PathBuf::from("/tmp/GeoLite2-Country.COPYRIGHT.txt")
+        );
+    }
+
+    /// 编码 mmdb 字符串（长度小于 29）
+    fn mmdb_str(s: &str) -> Vec<u8> {
+        let mut out = vec![0x40 | s.len() as u8];
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    /// 编码 mmdb uint32
+    fn mmdb_u32(v: u32) -> Vec<u8> {
+        let mut out = vec![0xC4];
+        out.extend_from_slice(&v.to_be_bytes());
+        out
+    }
+
+    /// 构造只有一个节点的 IPv4 mmdb
+    ///
+    /// `low_half` 为 true 时记录覆盖 0.0.0.0/1，否则覆盖 128.0.0.0/1。
+    fn build_mmdb(database_type: &str, record: &[u8], low_half: bool) -> Vec<u8> {
+        // 记录值 = node_count(1) + 16 指向数据段偏移 0；等于 node_count 表示无数据
+        let (left, right) = if low_half { (17u32, 1) } else { (1, 17u32) };
+        let mut out = Vec::new();
+        out.extend_from_slice(&left.to_be_bytes()[1..]);
+        out.extend_from_slice(&right.to_be_bytes()[1..]);
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(record);
+
+        out.extend_from_slice(b"\xab\xcd\xefMaxMind.com");
+        out.push(0xE0 | 9);
+        for (key, value) in [
+            ("binary_format_major_version", mmdb_u32(2)),
+            ("binary_format_minor_version", mmdb_u32(0)),
+            ("build_epoch", mmdb_u32(1_700_000_000)),
+            ("database_type", mmdb_str(database_type)),
+            ("description", vec![0xE0]),
+            ("ip_version", mmdb_u32(4)),
+            ("languages", vec![0x00, 0x04]),
+            ("node_count", mmdb_u32(1)),
+            ("record_size", mmdb_u32(24)),
+        ] {
+            out.extend(mmdb_str(key));
+            out.extend(value);
+        }
+        out
+    }
+
+    #[test]
+    fn test_verify_database() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-verify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("db.mmdb");
+
+        // ASN 库没有国家字段，按 ASN 结构检查
+        let mut asn = vec![0xE1];
+        asn.extend(mmdb_str("autonomous_system_number"));
+        asn.extend(mmdb_u32(15169));
+        fs::write(&path, build_mmdb("GeoLite2-ASN", &asn, true)).unwrap();
+        let (database_type, build_epoch) = verify_database(&path).unwrap();
+        assert_eq!(database_type, "GeoLite2-ASN");
+        assert_eq!(build_epoch, 1_700_000_000);
+
+        // 声明为 ASN 但记录里没有 ASN 字段
+        let mut empty = vec![0xE1];
+        empty.extend(mmdb_str("network"));
+        empty.extend(mmdb_str("8.0.0.0/1"));
+        fs::write(&path, build_mmdb("GeoLite2-ASN", &empty, true)).unwrap();
+        assert!(verify_database(&path).is_err());
+
+        // 只收录部分网段的国家库，8.8.8.8 不在库中也能通过
+        let mut country = vec![0xE1];
+        country.extend(mmdb_str("country"));
+        country.push(0xE1);
+        country.extend(mmdb_str("iso_code"));
+        country.extend(mmdb_str("CN"));
+        fs::write(&path, build_mmdb("GeoIP2-Country", &country, false)).unwrap();
+        assert!(verify_database(&path).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_install_keeps_backup() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-install-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("db.mmdb");
+        let temp = dir.join("db.mmdb.download");
+
+        fs::write(&target, b"old").unwrap();
+        fs::write(&temp, b"new").unwrap();
+        install(&temp, &target).unwrap();
+
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert_eq!(fs::read(backup_path(&target)).unwrap(), b"old");
+        assert!(!temp.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -27,12 +27,21 @@
 //!
 //! # 探测本地代理的出口 IP
 //! sudo proxy-audit scan --probe-exit --geo-db /path/to/GeoLite2-Country.mmdb
+//!
+//! # 识别本地监听端口的协议（SOCKS5/HTTP/Mixed/Clash API）
+//! sudo proxy-audit listeners
+//!
+//! # 查看正在使用的 GeoIP 数据库，手动查询 IP
+//! proxy-audit geo info
+//! proxy-audit geo lookup 8.8.8.8 1.0.0.0/24
 //! ```
 
+use std::io::BufRead;
+use std::path::Path;
 use std::process;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
 
 // 导入我们的模块
 use std::env;
@@ -43,10 +52,17 @@ mod proxy;
 mod rule;
 mod scan;
 
-/// 默认 GeoIP 数据库下载地址 (GitHub Mirror)
-/// 用户可以 fork 自己的仓库并在构建时修改此常量
-const DEFAULT_GEOIP_MIRROR: &str =
-    "https://raw.githubusercontent.com/P3TERX/GeoLite.mmdb/download/GeoLite2-Country.mmdb";
+/// 默认 GeoIP 数据库下载地址 (GitHub Mirror 及其 CDN 镜像，按顺序尝试)
+/// 用户可以 fork 自己的仓库并在构建时修改此常量，
+/// 或者通过 --mirror 参数、配置目录下的 mirrors.txt 覆盖
+const DEFAULT_GEOIP_MIRRORS: [&str; 3] = [
+    "https://raw.githubusercontent.com/P3TERX/GeoLite.mmdb/download/GeoLite2-Country.mmdb",
+    "https://cdn.jsdelivr.net/gh/P3TERX/GeoLite.mmdb@download/GeoLite2-Country.mmdb",
+    "https://fastly.jsdelivr.net/gh/P3TERX/GeoLite.mmdb@download/GeoLite2-Country.mmdb",
+];
+
+/// 自定义镜像列表文件名（位于配置目录下，每行一个 URL）
+const MIRRORS_FILE: &str = "mirrors.txt";
 
 // ========================================
 // CLI 参数定义
@@ -76,14 +92,31 @@ enum Commands {
         #[arg(long, short = 'a')]
         all: bool,
 
-        /// 指定 GeoIP 数据库路径 (可选，默认使用桌面路径)
-        #[arg(long, value_name = "PATH")]
-        geo_db: Option<String>,
+        #[command(flatten)]
+        geo: GeoDbArgs,
 
         /// 是否禁用出口探测 (默认开启)
         #[arg(long)]
         no_probe: bool,
 
+        /// 忽略出口缓存，强制重新探测
+        #[arg(long)]
+        refresh_exit: bool,
+
+        /// 显示每个进程的连接目的地分布（按国家统计）
+        #[arg(long, short = 'd')]
+        destinations: bool,
+
+        /// JSON 格式输出
+        #[arg(long)]
+        json: bool,
+
+        /// 显示调试信息
+        #[arg(long)]
+        debug: bool,
+    },
+    /// 列出本地监听端口并识别其协议
+    Listeners {
         /// JSON 格式输出
         #[arg(long)]
         json: bool,
@@ -92,18 +125,59 @@ enum Commands {
         #[arg(long)]
         debug: bool,
     },
-    /// 实时流量监控 (Top like TUI)
-    Top,
+    /// 实时流量监控 (Top like TUI)，选中进程后按 d/p/r 设为 DIRECT/PROXY/REJECT
+    Top {
+        /// 分配策略时启用的规则格式 (不指定时只渲染到 rules.toml 中已启用的格式)
+        #[arg(long, short = 'f', value_enum)]
+        format: Option<rule::OutputFormat>,
+    },
     /// 更新 GeoIP 数据库
     #[command(name = "update-geo")]
     UpdateGeo {
-        /// 指定下载 URL (可选，默认使用 GitHub Mirror)
-        #[arg(long)]
-        url: Option<String>,
+        /// 下载镜像 URL，可重复指定，按顺序尝试 (默认使用 mirrors.txt 或内置镜像列表)
+        #[arg(long = "mirror", visible_alias = "url", value_name = "URL")]
+        mirrors: Vec<String>,
+
+        /// 通过指定代理下载，如 socks5h://127.0.0.1:7890 (最先尝试，失败后继续尝试系统代理、本地代理和直连)
+        #[arg(long, value_name = "URL")]
+        proxy: Option<String>,
+
+        /// 只直连下载，不使用系统代理和本地代理
+        #[arg(long, conflicts_with = "proxy")]
+        no_proxy: bool,
 
-        /// 强制更新 (即使文件已存在)
+        /// 强制重新下载 (不使用 ETag/Last-Modified 条件请求)
         #[arg(long)]
         force: bool,
+
+        /// 只在数据库构建时间早于指定时长时才下载，如 7d、12h、2w (适合每日定时任务)
+        #[arg(long, value_name = "AGE", conflicts_with = "force")]
+        if_older_than: Option<String>,
+
+        /// 从 MaxMind 官方下载 (需要账号 ID 和 License Key)
+        #[arg(long, conflicts_with = "mirrors")]
+        maxmind: bool,
+
+        /// MaxMind 数据库版本，保存为配置目录下的 <EDITION>.mmdb
+        #[arg(long, default_value = "GeoLite2-Country", requires = "maxmind")]
+        edition: String,
+
+        /// MaxMind 账号 ID (默认读取环境变量 MAXMIND_ACCOUNT_ID)
+        #[arg(long, value_name = "ID", requires = "maxmind")]
+        account_id: Option<String>,
+
+        /// MaxMind License Key (默认读取环境变量 MAXMIND_LICENSE_KEY)
+        #[arg(long, value_name = "KEY", requires = "maxmind")]
+        license_key: Option<String>,
+
+        /// 回滚到上一次更新前的数据库
+        #[arg(long, conflicts_with_all = ["mirrors", "force", "if_older_than", "proxy", "no_proxy"])]
+        rollback: bool,
+    },
+    /// GeoIP 数据库信息与查询
+    Geo {
+        #[command(subcommand)]
+        action: GeoAction,
     },
     /// 规则生成和管理
     Rule {
@@ -112,6 +186,63 @@ enum Commands {
     },
 }
 
+/// GeoIP 数据库选项（scan 与 geo 子命令共用）
+#[derive(Args)]
+struct GeoDbArgs {
+    /// 指定 GeoIP 数据库路径 (可选，默认依次查找配置目录、开发路径、内置数据库)
+    #[arg(long, value_name = "PATH")]
+    geo_db: Option<String>,
+
+    /// 指定 ASN 数据库路径 (可选，默认使用配置目录下的 GeoLite2-ASN.mmdb)
+    #[arg(long, value_name = "PATH")]
+    asn_db: Option<String>,
+
+    /// 地名语言，如 zh-CN、ja (缺少该语言时回退到英文)
+    #[arg(long, value_name = "LANG", default_value = geo::mmdb::DEFAULT_LANGUAGE)]
+    lang: String,
+
+    /// 自定义网段标签文件 (可选，默认使用配置目录下的 labels.conf)
+    #[arg(long, value_name = "PATH")]
+    labels: Option<String>,
+}
+
+impl From<GeoDbArgs> for geo::source::GeoDbOptions {
+    fn from(args: GeoDbArgs) -> Self {
+        Self {
+            geo_db: args.geo_db,
+            asn_db: args.asn_db,
+            lang: args.lang,
+            labels: args.labels,
+        }
+    }
+}
+
+/// GeoIP 子命令
+#[derive(Subcommand)]
+enum GeoAction {
+    /// 显示实际使用的数据库来源、元数据和新旧程度
+    Info {
+        #[command(flatten)]
+        geo: GeoDbArgs,
+
+        /// JSON 格式输出
+        #[arg(long)]
+        json: bool,
+    },
+    /// 查询 IP 或 CIDR 的国家/城市/ASN (不带参数时从标准输入逐行读取)
+    Lookup {
+        /// 要查询的 IP 或 CIDR
+        targets: Vec<String>,
+
+        #[command(flatten)]
+        geo: GeoDbArgs,
+
+        /// JSON 格式输出
+        #[arg(long)]
+        json: bool,
+    },
+}
+
 /// 规则子命令
 #[derive(Subcommand)]
 enum RuleAction {
@@ -120,26 +251,82 @@ enum RuleAction {
         /// 规则格式
         #[arg(long, short = 'f', value_enum, default_value = "clash")]
         format: rule::OutputFormat,
+
+        /// Clash rule-provider 文件格式: yaml 或 text (text 需要 mihomo，大规则集加载更快)
+        #[arg(long, value_enum)]
+        clash_format: Option<rule::formatter::ClashProviderFormat>,
+
+        /// Clash rule-provider 拆分方式: classical 或 split (按 domain / ipcidr / classical 拆分)
+        #[arg(long, value_enum)]
+        clash_layout: Option<rule::formatter::ClashLayout>,
+
+        /// sing-box 规则集格式: source (.json) 或 binary (.srs，不含域名规则)
+        #[arg(long, value_enum)]
+        sing_box_format: Option<rule::formatter::SingBoxRuleSetFormat>,
+
+        /// sing-box 规则集版本 (1-3)，版本 2 / 3 分别需要 sing-box 1.10 / 1.11 以上
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=3))]
+        sing_box_version: Option<u8>,
     },
     /// 扫描进程连接并追加规则到文件
     Add {
-        /// 目标进程 PID
-        #[arg(long, short = 'p')]
-        pid: i32,
+        #[command(flatten)]
+        target: RuleTargetArgs,
+
+        /// 为扫描结果中指定代理模式的所有进程添加规则 (添加前列出进程并确认)
+        #[arg(long, group = "target", requires = "mode")]
+        from_scan: bool,
+
+        /// 与 --from-scan 一起使用：要匹配的代理模式
+        #[arg(long, value_enum, conflicts_with_all = ["pid", "name", "path"])]
+        mode: Option<scan::ProxyMode>,
+
+        /// 与 --from-scan 一起使用：不询问直接添加
+        #[arg(long, short = 'y', conflicts_with_all = ["pid", "name", "path"])]
+        yes: bool,
+
+        /// 匹配方式 (用于 --pid / --from-scan): name (PROCESS-NAME)、path (PROCESS-PATH，仅 Clash Meta/mihomo 与 sing-box)、
+        /// destination (按进程当前连接的目的地生成 IP-CIDR 规则)
+        #[arg(long, value_enum, default_value = "name", conflicts_with_all = ["name", "path"])]
+        by: rule::MatchBy,
+
+        /// 与 --by destination 一起使用：把地址合并为 /24 (IPv6 /64) 覆盖网段
+        #[arg(long)]
+        collapse: bool,
 
         /// 规则策略 (DIRECT/PROXY/REJECT)
         #[arg(long, default_value = "DIRECT")]
         policy: String,
 
-        /// 规则格式
-        #[arg(long, short = 'f', value_enum, default_value = "clash")]
-        format: rule::OutputFormat,
+        /// 规则格式 (指定时在 rules.toml 中启用；规则渲染到所有已启用的格式)
+        #[arg(long, short = 'f', value_enum)]
+        format: Option<rule::OutputFormat>,
+
+        /// 已启用的格式中有无法表达该规则的 (如 Surge 没有 PROCESS-PATH) 时跳过这些格式，而不是报错
+        #[arg(long)]
+        skip_unsupported: bool,
+
+        /// 备注 (保存在 rules.toml 中)
+        #[arg(long)]
+        note: Option<String>,
+
+        /// 写入后通知代理客户端重新加载规则 (Clash/mihomo、sing-box、Surge)
+        #[arg(long)]
+        reload: bool,
     },
     /// 仅打印规则到标准输出（不写入文件）
     Print {
-        /// 目标进程 PID
-        #[arg(long, short = 'p')]
-        pid: i32,
+        #[command(flatten)]
+        target: RuleTargetArgs,
+
+        /// 匹配方式 (用于 --pid / --from-scan): name (PROCESS-NAME)、path (PROCESS-PATH，仅 Clash Meta/mihomo 与 sing-box)、
+        /// destination (按进程当前连接的目的地生成 IP-CIDR 规则)
+        #[arg(long, value_enum, default_value = "name", conflicts_with_all = ["name", "path"])]
+        by: rule::MatchBy,
+
+        /// 与 --by destination 一起使用：把地址合并为 /24 (IPv6 /64) 覆盖网段
+        #[arg(long)]
+        collapse: bool,
 
         /// 规则策略 (DIRECT/PROXY/REJECT)
         #[arg(long, default_value = "DIRECT")]
@@ -149,6 +336,102 @@ enum RuleAction {
         #[arg(long, short = 'f', value_enum, default_value = "clash")]
         format: rule::OutputFormat,
     },
+    /// 列出 rules.toml 中的规则
+    List {
+        /// 只显示该格式能够表达的规则
+        #[arg(long, short = 'f', value_enum)]
+        format: Option<rule::OutputFormat>,
+
+        /// 只显示指定策略 (DIRECT/PROXY/REJECT)
+        #[arg(long)]
+        policy: Option<String>,
+
+        /// 只显示值中包含该文本的规则 (不区分大小写)
+        #[arg(long, value_name = "TEXT")]
+        filter: Option<String>,
+
+        /// JSON 格式输出
+        #[arg(long)]
+        json: bool,
+    },
+    /// 删除规则 (并重新渲染所有启用的格式)
+    Remove {
+        #[command(flatten)]
+        selector: RuleSelectorArgs,
+
+        /// 写入后通知代理客户端重新加载规则 (Clash/mihomo、sing-box、Surge)
+        #[arg(long)]
+        reload: bool,
+    },
+    /// 把规则移动到另一个策略
+    Move {
+        #[command(flatten)]
+        selector: RuleSelectorArgs,
+
+        /// 目标策略 (DIRECT/PROXY/REJECT)
+        #[arg(long)]
+        to: String,
+
+        /// 写入后通知代理客户端重新加载规则 (Clash/mihomo、sing-box、Surge)
+        #[arg(long)]
+        reload: bool,
+    },
+}
+
+/// 规则目标参数（rule add / print 共用，多选一）
+#[derive(Args)]
+#[group(id = "target", required = true, multiple = false)]
+struct RuleTargetArgs {
+    /// 目标进程 PID
+    #[arg(long, short = 'p')]
+    pid: Option<i32>,
+
+    /// 进程名 (不需要进程正在运行)；含通配符 (* ? [...]) 时匹配运行中的进程
+    #[arg(long)]
+    name: Option<String>,
+
+    /// 可执行文件或 .app 路径，生成 PROCESS-PATH 规则；含通配符时匹配文件系统
+    #[arg(long)]
+    path: Option<String>,
+}
+
+impl RuleTargetArgs {
+    /// 转换为规则目标，使用 --from-scan 时为 None
+    fn target(self) -> Option<rule::RuleTarget> {
+        match (self.pid, self.name, self.path) {
+            (Some(pid), _, _) => Some(rule::RuleTarget::Pid(pid)),
+            (_, Some(name), _) => Some(rule::RuleTarget::Name(name)),
+            (_, _, Some(path)) => Some(rule::RuleTarget::Path(path)),
+            _ => None,
+        }
+    }
+}
+
+/// 规则选择参数（rule remove / move 共用，三选一）
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct RuleSelectorArgs {
+    /// 按进程名选择 (同时匹配文件名相同的 PROCESS-PATH 规则)
+    #[arg(long)]
+    name: Option<String>,
+
+    /// 按进程完整路径选择
+    #[arg(long)]
+    path: Option<String>,
+
+    /// 按规则值选择，如 1.2.3.4/32、example.com
+    #[arg(long)]
+    value: Option<String>,
+}
+
+impl From<RuleSelectorArgs> for rule::RuleSelector {
+    fn from(args: RuleSelectorArgs) -> Self {
+        match (args.name, args.path, args.value) {
+            (Some(name), _, _) => rule::RuleSelector::Name(name),
+            (_, Some(path), _) => rule::RuleSelector::Path(path),
+            (_, _, value) => rule::RuleSelector::Value(value.unwrap_or_default()),
+        }
+    }
 }
 
 // ========================================
@@ -169,49 +452,66 @@ fn main() {
         Commands::Scan {
             pid,
             all,
-            geo_db,
+            geo,
             no_probe,
+            refresh_exit,
+            destinations,
             json,
             debug,
         } => {
-            // 路径优先级: 1. CLI 参数  2. 环境变量 (TODO)  3. 默认配置路径  4. (Dev) 桌面路径
-            // 5. 最终 Fallback: 嵌入在二进制文件中的数据库
-
-            // 尝试查找外部文件
-            let dev_path = format!("{}/Desktop/backend/net_check/proxy-audit/GeoLite2-Country_20251212/GeoLite2-Country.mmdb", home);
-
-            let file_path = if let Some(p) = geo_db {
-                Some(p)
-            } else if std::path::Path::new(&default_geo_path).exists() {
-                Some(default_geo_path)
-            } else if std::path::Path::new(&dev_path).exists() {
-                Some(dev_path)
-            } else {
-                None
-            };
+            let geo_instance = geo::source::open_geodb(&geo.into(), Path::new(&config_dir), &home)
+                .map(|loaded| loaded.db);
 
-            // 加载数据库实例
-            let geo_instance = if let Some(path) = file_path {
-                match geo::GeoDb::open(&path) {
-                    Ok(db) => Some(db),
-                    Err(e) => {
-                        eprintln!("Warning: Failed to open GeoDB at {}: {}", path, e);
-                        // 尝试使用嵌入版本
-                        load_embedded_geodb()
-                    }
-                }
+            let output = ScanOutput { json, destinations };
+            run_scan(
+                geo_instance,
+                !no_probe,
+                refresh_exit,
+                !all,
+                output,
+                pid,
+                debug,
+            )
+        }
+        Commands::Listeners { json, debug } => run_listeners(json, debug),
+        Commands::UpdateGeo {
+            mirrors,
+            proxy,
+            no_proxy,
+            force,
+            if_older_than,
+            maxmind,
+            edition,
+            account_id,
+            license_key,
+            rollback,
+        } => {
+            // MaxMind 下载按版本保存，City/ASN 版本会被 scan 自动识别
+            let target_path = if maxmind {
+                format!("{}/{}.mmdb", config_dir, edition)
             } else {
-                // 没有外部文件，直接使用嵌入版本
-                load_embedded_geodb()
+                default_geo_path.clone()
             };
-
-            run_scan(geo_instance, !no_probe, !all, json, pid, debug)
-        }
-        Commands::UpdateGeo { url, force } => {
-            run_update_geo(&config_dir, &default_geo_path, url, force)
+            if rollback {
+                rollback_geo(&target_path)
+            } else {
+                let maxmind = maxmind.then_some(MaxMindAccount {
+                    edition,
+                    account_id,
+                    license_key,
+                });
+                let sources = UpdateSources {
+                    mirrors,
+                    maxmind,
+                    proxy,
+                    no_proxy,
+                };
+                run_update_geo(&config_dir, &target_path, sources, force, if_older_than)
+            }
         }
+        Commands::Geo { action } => run_geo_command(action, &config_dir, &home),
         Commands::Rule { action } => run_rule_command(action),
-        Commands::Top => monitor::ui::run_tui(),
+        Commands::Top { format } => monitor::ui::run_tui(format),
     };
 
     // 处理错误
@@ -221,59 +521,409 @@ fn main() {
     }
 }
 
-/// 加载嵌入的 GeoIP 数据库
-fn load_embedded_geodb() -> Option<geo::GeoDb> {
-    // 编译时嵌入文件
-    const GEO_DB_BYTES: &[u8] =
-        include_bytes!("../GeoLite2-Country_20251212/GeoLite2-Country.mmdb");
-
-    match geo::GeoDb::from_bytes(GEO_DB_BYTES) {
-        Ok(db) => Some(db),
-        Err(e) => {
-            eprintln!("Warning: Failed to load embedded GeoDB: {}", e);
-            None
-        }
-    }
+/// update-geo 的下载来源选项
+struct UpdateSources {
+    /// --mirror 指定的镜像
+    mirrors: Vec<String>,
+    /// --maxmind 官方下载
+    maxmind: Option<MaxMindAccount>,
+    /// --proxy 指定的代理
+    proxy: Option<String>,
+    /// 只直连
+    no_proxy: bool,
 }
 
 /// 执行 GeoIP 数据库更新
 fn run_update_geo(
     config_dir: &str,
     target_path: &str,
-    url: Option<String>,
+    sources: UpdateSources,
     force: bool,
+    if_older_than: Option<String>,
 ) -> Result<()> {
-    use std::fs;
-    use std::io::Write;
-    use std::path::Path;
+    use geo::update::{self, DownloadSource, UpdateOutcome};
 
-    if !force && Path::new(target_path).exists() {
-        println!("GeoIP database already exists at: {}", target_path);
-        println!("Use --force to overwrite.");
-        return Ok(());
+    let target = Path::new(target_path);
+
+    // 数据库还不够旧时直接返回，不访问网络
+    if let Some(max_age) = if_older_than {
+        let max_age = update::parse_age(&max_age)?;
+        if let Some(age) = update::database_age(target) {
+            if age < max_age {
+                println!(
+                    "GeoIP database is {} days old, skipping update: {}",
+                    age.as_secs() / 86_400,
+                    target_path
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    let download_sources = match sources.maxmind {
+        Some(account) => vec![account.into_source()?],
+        None => resolve_mirrors(config_dir, sources.mirrors)
+            .into_iter()
+            .map(DownloadSource::Mirror)
+            .collect(),
+    };
+    let routes = detect_download_routes(sources.proxy, sources.no_proxy);
+
+    println!("Checking GeoIP database...");
+    println!("To:      {}", target_path);
+    let source_names: Vec<String> = download_sources.iter().map(|s| s.to_string()).collect();
+    println!("Sources: {}", source_names.join(", "));
+    let route_names: Vec<String> = routes.iter().map(|r| r.to_string()).collect();
+    println!("Routes:  {}", route_names.join(" -> "));
+
+    let report = update::update_database(&download_sources, &routes, target, force)?;
+    println!("Succeeded via {} from {}", report.route, report.source);
+
+    match report.outcome {
+        UpdateOutcome::NotModified => {
+            println!("GeoIP database is already up to date.");
+        }
+        UpdateOutcome::Updated {
+            size,
+            database_type,
+            build_epoch,
+            checksum_verified,
+        } => {
+            println!(
+                "Successfully updated GeoIP database: {} ({:.1} MB, build epoch {})",
+                database_type,
+                size as f64 / 1024.0 / 1024.0,
+                build_epoch
+            );
+            if !checksum_verified {
+                println!(
+                    "Note: mirror publishes no checksum, verified by opening the database only"
+                );
+            }
+            let backup = update::backup_path(target);
+            if backup.exists() {
+                println!(
+                    "Previous version kept at {} (restore with --rollback)",
+                    backup.display()
+                );
+            }
+        }
     }
+    Ok(())
+}
 
-    // 确保目录存在
-    fs::create_dir_all(config_dir)?;
+/// 回滚到上一次更新前的数据库
+fn rollback_geo(target_path: &str) -> Result<()> {
+    geo::update::rollback_database(Path::new(target_path))?;
+    println!("Restored previous GeoIP database: {}", target_path);
+    Ok(())
+}
 
-    let download_url = url.as_deref().unwrap_or(DEFAULT_GEOIP_MIRROR);
+/// --maxmind 的账号参数
+struct MaxMindAccount {
+    /// 数据库版本
+    edition: String,
+    /// --account-id
+    account_id: Option<String>,
+    /// --license-key
+    license_key: Option<String>,
+}
 
-    println!("Downloading GeoIP database...");
-    println!("From: {}", download_url);
-    println!("To:   {}", target_path);
+impl MaxMindAccount {
+    /// 补全环境变量中的账号信息，转换为下载来源
+    fn into_source(self) -> Result<geo::update::DownloadSource> {
+        let account_id = self
+            .account_id
+            .or_else(|| std::env::var("MAXMIND_ACCOUNT_ID").ok())
+            .context("MaxMind download needs --account-id or MAXMIND_ACCOUNT_ID")?;
+        let license_key = self
+            .license_key
+            .or_else(|| std::env::var("MAXMIND_LICENSE_KEY").ok())
+            .context("MaxMind download needs --license-key or MAXMIND_LICENSE_KEY")?;
+        Ok(geo::update::DownloadSource::MaxMind {
+            edition: self.edition,
+            account_id,
+            license_key,
+        })
+    }
+}
 
-    // 使用 reqwest 下载
-    // 注意：reqwest blocking feature 必须开启
-    let response = reqwest::blocking::get(download_url)?;
-    let content = response.bytes()?;
+/// 镜像列表: --mirror 参数 > 配置目录下的 mirrors.txt > 内置列表
+fn resolve_mirrors(config_dir: &str, cli_mirrors: Vec<String>) -> Vec<String> {
+    if !cli_mirrors.is_empty() {
+        return cli_mirrors;
+    }
 
-    let mut file = fs::File::create(target_path)?;
-    file.write_all(&content)?;
+    let file_mirrors: Vec<String> =
+        std::fs::read_to_string(Path::new(config_dir).join(MIRRORS_FILE))
+            .map(|content| {
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+    if !file_mirrors.is_empty() {
+        return file_mirrors;
+    }
 
-    println!("Successfully updated GeoIP database!");
+    DEFAULT_GEOIP_MIRRORS
+        .iter()
+        .map(|m| m.to_string())
+        .collect()
+}
+
+/// 按优先级收集下载线路: --proxy 指定的代理 > 系统代理 > 本地代理监听端口 > 直连
+///
+/// 指定的代理失败时继续尝试自动检测到的线路。
+fn detect_download_routes(
+    explicit: Option<String>,
+    no_proxy: bool,
+) -> Vec<geo::update::DownloadRoute> {
+    use geo::update::{dedup_routes, proxy_url, DownloadRoute};
+
+    if no_proxy {
+        return vec![DownloadRoute::Direct];
+    }
+    let mut routes: Vec<_> = explicit.into_iter().map(DownloadRoute::Explicit).collect();
+
+    // 系统代理：SOCKS 优先（域名交给代理端解析），其次 HTTPS / HTTP
+    let system = proxy::read_system_proxy().unwrap_or_default();
+    if let Some(socks) = &system.socks {
+        routes.push(DownloadRoute::SystemProxy(proxy_url(
+            "socks5",
+            &socks.host,
+            socks.port,
+        )));
+    }
+    for server in [&system.https, &system.http].into_iter().flatten() {
+        routes.push(DownloadRoute::SystemProxy(proxy_url(
+            "http",
+            &server.host,
+            server.port,
+        )));
+    }
+
+    // 本地监听端口上识别出的代理（如未开启系统代理的 Clash 混合端口）
+    let listeners = scan::ScanContext::new(None, false, false, false, None, false)
+        .and_then(|mut ctx| scan::scan_listeners(&mut ctx))
+        .unwrap_or_default();
+    for listener in listeners {
+        let scheme = match listener.protocol {
+            proxy::ListenerProtocol::Socks5 => "socks5",
+            proxy::ListenerProtocol::Http | proxy::ListenerProtocol::Mixed => "http",
+            _ => continue,
+        };
+        routes.push(DownloadRoute::LocalListener(proxy_url(
+            scheme,
+            "127.0.0.1",
+            listener.port,
+        )));
+    }
+
+    routes.push(DownloadRoute::Direct);
+    dedup_routes(routes)
+}
+
+// ========================================
+// GeoIP 子命令实现
+// ========================================
+
+/// geo info 的输出内容
+#[derive(serde::Serialize)]
+struct GeoInfo {
+    /// 数据库来源
+    source: String,
+    /// 外部文件路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    /// 数据库类型
+    database_type: String,
+    /// 构建时间（Unix 时间戳）
+    build_epoch: u64,
+    /// 构建日期（UTC）
+    build_date: String,
+    /// 构建至今的天数
+    age_days: u64,
+    /// 是否已过期
+    stale: bool,
+    /// IP 版本（4 或 6）
+    ip_version: u16,
+    /// 搜索树节点数
+    node_count: u32,
+    /// 支持的地名语言
+    languages: Vec<String>,
+    /// ASN 数据库路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asn_path: Option<String>,
+    /// ASN 数据库类型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asn_database_type: Option<String>,
+    /// 网段标签文件路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    labels_path: Option<String>,
+    /// 网段标签条目数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label_count: Option<usize>,
+}
+
+/// geo lookup 的单行结果
+#[derive(serde::Serialize)]
+struct GeoLookupRow {
+    /// 用户输入的 IP 或 CIDR
+    target: String,
+    /// 实际查询的地址（CIDR 取网络地址）
+    ip: std::net::IpAddr,
+    /// 查询结果
+    #[serde(flatten)]
+    geo: Option<geo::GeoResult>,
+}
+
+/// 执行 GeoIP 命令
+fn run_geo_command(action: GeoAction, config_dir: &str, home: &str) -> Result<()> {
+    match action {
+        GeoAction::Info { geo, json } => {
+            let loaded = open_geodb_or_bail(geo, config_dir, home)?;
+            run_geo_info(&loaded, json)
+        }
+        GeoAction::Lookup { targets, geo, json } => {
+            let loaded = open_geodb_or_bail(geo, config_dir, home)?;
+            run_geo_lookup(&loaded.db, targets, json)
+        }
+    }
+}
+
+/// 加载数据库，全部不可用时报错
+fn open_geodb_or_bail(
+    args: GeoDbArgs,
+    config_dir: &str,
+    home: &str,
+) -> Result<geo::source::LoadedGeoDb> {
+    geo::source::open_geodb(&args.into(), Path::new(config_dir), home).ok_or_else(|| {
+        anyhow::anyhow!(
+            "No usable GeoIP database found (run `proxy-audit update-geo` or pass --geo-db)"
+        )
+    })
+}
+
+/// 打印数据库来源和元数据
+fn run_geo_info(loaded: &geo::source::LoadedGeoDb, json_output: bool) -> Result<()> {
+    let metadata = loaded.db.metadata();
+    let age_days = geo::source::age_days(metadata.build_epoch);
+    let info = GeoInfo {
+        source: loaded.source.to_string(),
+        path: loaded.source.path().map(|p| p.display().to_string()),
+        database_type: metadata.database_type.clone(),
+        build_epoch: metadata.build_epoch,
+        build_date: geo::source::format_date(metadata.build_epoch),
+        age_days,
+        stale: age_days > geo::source::STALE_AFTER_DAYS,
+        ip_version: metadata.ip_version,
+        node_count: metadata.node_count,
+        languages: metadata.languages.clone(),
+        asn_path: loaded.asn_path.as_ref().map(|p| p.display().to_string()),
+        asn_database_type: loaded.db.asn_metadata().map(|m| m.database_type.clone()),
+        labels_path: loaded.labels.as_ref().map(|(p, _)| p.display().to_string()),
+        label_count: loaded.labels.as_ref().map(|(_, count)| *count),
+    };
+
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    println!("Source:        {}", info.source);
+    println!("Database type: {}", info.database_type);
+    println!(
+        "Build date:    {} ({} days ago)",
+        info.build_date, info.age_days
+    );
+    println!("IP version:    {}", info.ip_version);
+    println!("Node count:    {}", info.node_count);
+    println!("Languages:     {}", info.languages.join(", "));
+    if let (Some(path), Some(db_type)) = (&info.asn_path, &info.asn_database_type) {
+        println!("ASN database:  {} ({})", path, db_type);
+    }
+    if let (Some(path), Some(count)) = (&info.labels_path, info.label_count) {
+        println!("Labels:        {} ({} ranges)", path, count);
+    }
+    if info.stale {
+        println!(
+            "\nWarning: database is older than {} days, run `proxy-audit update-geo` to refresh",
+            geo::source::STALE_AFTER_DAYS
+        );
+    }
+    Ok(())
+}
+
+/// 批量查询 IP/CIDR
+fn run_geo_lookup(db: &geo::GeoDb, targets: Vec<String>, json_output: bool) -> Result<()> {
+    let mut rows = Vec::new();
+    if targets.is_empty() {
+        // 从标准输入读取，跳过空行和 # 注释，无法解析的行给出警告
+        for line in std::io::stdin().lock().lines() {
+            let line = line?;
+            let target = line.trim();
+            if target.is_empty() || target.starts_with('#') {
+                continue;
+            }
+            match parse_lookup_target(target) {
+                Some(ip) => rows.push(lookup_row(db, target, ip)),
+                None => eprintln!("Warning: skipping invalid IP/CIDR: {}", target),
+            }
+        }
+    } else {
+        for target in &targets {
+            let ip = parse_lookup_target(target)
+                .ok_or_else(|| anyhow::anyhow!("Invalid IP or CIDR: {}", target))?;
+            rows.push(lookup_row(db, target, ip));
+        }
+    }
+
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<40}\t{:<25}\t{:<25}\t{:<30}",
+        "TARGET", "COUNTRY", "CITY", "ASN"
+    );
+    for row in &rows {
+        let geo = row.geo.as_ref();
+        println!(
+            "{:<40}\t{:<25}\t{:<25}\t{:<30}",
+            row.target,
+            geo.map(|g| g.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            geo.and_then(|g| g.place())
+                .unwrap_or_else(|| "-".to_string()),
+            geo.and_then(|g| g.asn_label())
+                .unwrap_or_else(|| "-".to_string())
+        );
+    }
     Ok(())
 }
 
+/// 解析 IP 或 CIDR，CIDR 取网络地址
+fn parse_lookup_target(target: &str) -> Option<std::net::IpAddr> {
+    target
+        .parse()
+        .ok()
+        .or_else(|| target.parse::<ipnet::IpNet>().ok().map(|net| net.network()))
+}
+
+/// 查询单个地址
+fn lookup_row(db: &geo::GeoDb, target: &str, ip: std::net::IpAddr) -> GeoLookupRow {
+    GeoLookupRow {
+        target: target.to_string(),
+        ip,
+        geo: db.lookup(ip),
+    }
+}
+
 // ========================================
 // 规则命令实现
 // ========================================
@@ -287,32 +937,259 @@ fn run_rule_command(action: RuleAction) -> anyhow::Result<()> {
     let manager = rule::RuleFileManager::new()?;
 
     match action {
-        RuleAction::Init { format } => {
-            let guide = manager.init(format)?;
+        RuleAction::Init {
+            format,
+            clash_format,
+            clash_layout,
+            sing_box_format,
+            sing_box_version,
+        } => {
+            let guide = manager.init(
+                format,
+                clash_format,
+                clash_layout,
+                sing_box_format,
+                sing_box_version,
+            )?;
             println!("{}", guide);
         }
         RuleAction::Add {
-            pid,
+            target,
+            from_scan,
+            mode,
+            yes,
+            by,
+            collapse,
             policy,
             format,
+            skip_unsupported,
+            note,
+            reload,
         } => {
             let policy_enum = parse_policy(&policy)?;
-            manager.add_rule(pid, policy_enum, format)?;
-            println!("Rule added successfully.");
+            let outcome = if from_scan {
+                let mode = mode.context("--from-scan requires --mode")?;
+                let rules = scan_rules(&manager, mode, by, collapse, policy_enum)?;
+                if rules.is_empty() || !(yes || confirm("Add these rules?")?) {
+                    return Ok(());
+                }
+                manager.add_rules(&rules, format, skip_unsupported, note)?
+            } else {
+                let target = target.target().context("A rule target is required")?;
+                manager.add_rule(
+                    &target,
+                    by,
+                    collapse,
+                    policy_enum,
+                    format,
+                    skip_unsupported,
+                    note,
+                )?
+            };
+            print_add_outcome(&manager, &outcome);
+            if reload && !outcome.changed.is_empty() {
+                manager.reload()?;
+            }
         }
         RuleAction::Print {
-            pid,
+            target,
+            by,
+            collapse,
             policy,
             format,
         } => {
             let policy_enum = parse_policy(&policy)?;
-            let rule = manager.generate_rule(pid, policy_enum, format)?;
+            let target = target.target().context("A rule target is required")?;
+            let rule = manager.generate_rule(&target, by, collapse, policy_enum, format)?;
             println!("{}", rule);
         }
+        RuleAction::List {
+            format,
+            policy,
+            filter,
+            json,
+        } => {
+            let policy = policy.as_deref().map(parse_policy).transpose()?;
+            let filter = filter.map(|f| f.to_lowercase());
+            let formatter = format.map(rule::formatter::create_formatter);
+            let rules: Vec<_> = manager
+                .load_store()?
+                .rules
+                .into_iter()
+                .filter(|s| policy.is_none_or(|p| s.rule.policy == p))
+                .filter(|s| {
+                    filter
+                        .as_ref()
+                        .is_none_or(|f| s.rule.value.to_lowercase().contains(f))
+                })
+                .filter(|s| {
+                    formatter
+                        .as_ref()
+                        .is_none_or(|f| f.format_rule(&s.rule).is_some())
+                })
+                .collect();
+            print_rules(&rules, json)?;
+        }
+        RuleAction::Remove { selector, reload } => {
+            let selector = rule::RuleSelector::from(selector);
+            let removed = manager.remove_rules(&selector)?;
+            if removed.is_empty() {
+                anyhow::bail!("No rule matches {}", selector);
+            }
+            for s in &removed {
+                println!(
+                    "Removed {} {} ({})",
+                    s.rule.rule_type, s.rule.value, s.rule.policy
+                );
+            }
+            if reload {
+                manager.reload()?;
+            }
+        }
+        RuleAction::Move {
+            selector,
+            to,
+            reload,
+        } => {
+            let to = parse_policy(&to)?;
+            let selector = rule::RuleSelector::from(selector);
+            let moved = manager.move_rules(&selector, to)?;
+            if moved.is_empty() {
+                // 规则可能已经在目标策略中
+                let already = manager
+                    .load_store()?
+                    .rules
+                    .iter()
+                    .any(|s| selector.matches(&s.rule));
+                if !already {
+                    anyhow::bail!("No rule matches {}", selector);
+                }
+                println!("Rule is already in {}.", to);
+            }
+            for s in &moved {
+                println!(
+                    "Moved {} {} from {} to {}",
+                    s.rule.rule_type, s.rule.value, s.rule.policy, to
+                );
+            }
+            if reload && !moved.is_empty() {
+                manager.reload()?;
+            }
+        }
     }
     Ok(())
 }
 
+/// 扫描所有进程，为指定代理模式的进程生成规则（rule add --from-scan）
+///
+/// 列出匹配的进程和将要添加的规则；已退出或无法读取的进程给出警告后跳过。
+fn scan_rules(
+    manager: &rule::RuleFileManager,
+    mode: scan::ProxyMode,
+    by: rule::MatchBy,
+    collapse: bool,
+    policy: rule::RulePolicy,
+) -> Result<Vec<rule::formatter::Rule>> {
+    let mut ctx = scan::ScanContext::new(None, false, false, false, None, false)?;
+    let own_pid = process::id() as i32;
+    let processes: Vec<_> = scan::scan_all_processes(&mut ctx)?
+        .into_iter()
+        .filter(|p| p.mode == mode && p.pid != own_pid)
+        .collect();
+    if processes.is_empty() {
+        println!("No process in {} mode.", mode);
+        return Ok(Vec::new());
+    }
+
+    println!("Found {} process(es) in {} mode:", processes.len(), mode);
+    let mut rules: Vec<rule::formatter::Rule> = Vec::new();
+    for p in &processes {
+        println!("  {:<8} {}", p.pid, p.name);
+        match manager.build_rules(&rule::RuleTarget::Pid(p.pid), by, collapse, policy) {
+            Ok(built) => {
+                for r in built {
+                    if !rules
+                        .iter()
+                        .any(|e| e.rule_type == r.rule_type && e.value == r.value)
+                    {
+                        rules.push(r);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Warning: skipping PID {}: {:#}", p.pid, e),
+        }
+    }
+
+    println!("\nRules to add ({}):", policy);
+    for r in &rules {
+        println!("  {} {}", r.rule_type, r.value);
+    }
+    Ok(rules)
+}
+
+/// 询问确认，只有输入 y / yes 时返回 true
+fn confirm(prompt: &str) -> Result<bool> {
+    use std::io::Write;
+
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// 打印 rule add 的结果
+fn print_add_outcome(manager: &rule::RuleFileManager, outcome: &rule::AddOutcome) {
+    for (value, from, to) in &outcome.moved {
+        println!("Moved {} from {} to {}", value, from, to);
+    }
+    for note in &outcome.notes {
+        println!("{}", note);
+    }
+    if let Some(message) = outcome.unchanged_message() {
+        println!("{}", message);
+        return;
+    }
+    let policies: std::collections::BTreeSet<String> =
+        outcome.added.iter().map(|r| r.policy.to_string()).collect();
+    println!(
+        "Added {} rule(s) to {} ({} rules)",
+        outcome.added.len(),
+        manager.store_path().display(),
+        policies.into_iter().collect::<Vec<_>>().join(", ")
+    );
+    println!("Rule added successfully.");
+}
+
+/// 打印规则列表（表格或 JSON）
+fn print_rules(rules: &[rule::store::StoredRule], json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(rules)?);
+        return Ok(());
+    }
+    if rules.is_empty() {
+        println!("No rules found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<8} {:<14} {:<40} {:<30}",
+        "POLICY", "TYPE", "VALUE", "NOTE"
+    );
+    println!("{}", "-".repeat(90));
+    for s in rules {
+        println!(
+            "{:<8} {:<14} {:<40} {}",
+            s.rule.policy.to_string(),
+            s.rule.rule_type.to_string(),
+            s.rule.value,
+            s.note.as_deref().unwrap_or("")
+        );
+    }
+    println!("\nTotal: {} rule(s)", rules.len());
+    Ok(())
+}
+
 /// 解析策略字符串为 RulePolicy 枚举
 fn parse_policy(policy: &str) -> anyhow::Result<rule::RulePolicy> {
     match policy.to_uppercase().as_str() {
@@ -327,20 +1204,36 @@ fn parse_policy(policy: &str) -> anyhow::Result<rule::RulePolicy> {
 // 扫描命令实现
 // ========================================
 
+/// 扫描结果的输出选项
+struct ScanOutput {
+    /// JSON 格式输出
+    json: bool,
+    /// 表格模式下显示连接目的地分布
+    destinations: bool,
+}
+
 /// 执行扫描命令
 fn run_scan(
     geo_db: Option<geo::GeoDb>,
     probe_exit: bool,
+    refresh_exit: bool,
     only_routed: bool,
-    json_output: bool,
+    output: ScanOutput,
     target_pid: Option<i32>,
     debug: bool,
 ) -> Result<()> {
     // 1. 创建扫描上下文
-    let mut ctx = scan::ScanContext::new(geo_db, probe_exit, only_routed, target_pid, debug)?;
+    let mut ctx = scan::ScanContext::new(
+        geo_db,
+        probe_exit,
+        refresh_exit,
+        only_routed,
+        target_pid,
+        debug,
+    )?;
 
     // 2. 输出头部信息（非 JSON 模式）
-    if !json_output {
+    if !output.json {
         // 显示默认路由接口
         println!("Default route iface: {}", ctx.default_iface);
         if ctx.is_vpn {
@@ -372,15 +1265,15 @@ fn run_scan(
     let results = scan::scan_all_processes(&mut ctx)?;
 
     // 4. 输出结果
-    if json_output {
+    if output.json {
         // JSON 格式输出
         let json = serde_json::to_string_pretty(&results)?;
         println!("{}", json);
     } else {
         // 3. 打印表头
         println!(
-            "{:<8}\t{:<30}\t{:<15}\t{:<30}\t{:<15}",
-            "PID", "NAME", "MODE", "PROXY", "REGION"
+            "{:<8}\t{:<30}\t{:<15}\t{:<30}\t{:<15}\t{:<30}",
+            "PID", "NAME", "MODE", "PROXY", "REGION", "ISP"
         );
 
         // 4. 打印每行数据
@@ -425,14 +1318,52 @@ fn run_scan(
                 proxy_display
             };
 
+            // 出口与直连相同的代理加上标记
+            let region = match (&res.country, &res.city) {
+                (Some(country), Some(city)) => format!("{} {}", country, city),
+                (Some(country), None) => country.clone(),
+                (None, _) => "-".to_string(),
+            };
+            let region = if res.exit_is_direct {
+                format!("{} (=DIRECT)", region)
+            } else {
+                region
+            };
+
             println!(
-                "{:<8}\t{:<30}\t{:<15}\t{:<30}\t{:<15}",
+                "{:<8}\t{:<30}\t{:<15}\t{:<30}\t{:<15}\t{:<30}",
                 res.pid,
                 &res.name,
                 mode_str,
                 &proxy_final,
-                res.country.as_deref().unwrap_or("-")
+                region,
+                res.isp.as_deref().unwrap_or("-")
             );
+
+            // 目的地分布，例如 "-> 12 sockets CN/AS4134, 3 sockets US"
+            if output.destinations && !res.destinations.is_empty() {
+                let groups: Vec<String> = res.destinations.iter().map(|g| g.to_string()).collect();
+                println!("\t-> {}", groups.join(", "));
+            }
+        }
+
+        // 直连出口对比
+        if let Some(direct_ip) = ctx.direct_exit {
+            println!("\nDirect exit IP: {}", direct_ip);
+
+            let mut flagged: Vec<&str> = results
+                .iter()
+                .filter(|r| r.exit_is_direct)
+                .filter_map(|r| r.proxy.as_deref())
+                .collect();
+            flagged.sort();
+            flagged.dedup();
+            for proxy in flagged {
+                println!(
+                    "Warning: {} exits via the direct IP (proxy broken or matched a DIRECT rule)",
+                    proxy
+                );
+            }
         }
 
         println!("\nTotal: {} processes scanned", results.len());
@@ -444,9 +1375,46 @@ fn run_scan(
     Ok(())
 }
 
+// ========================================
+// 监听端口命令实现
+// ========================================
+
+/// 执行 listeners 命令
+fn run_listeners(json_output: bool, debug: bool) -> Result<()> {
+    // 只需要监听端口映射，不加载 GeoIP、不探测出口
+    let mut ctx = scan::ScanContext::new(None, false, false, false, None, debug)?;
+    let listeners = scan::scan_listeners(&mut ctx)?;
+
+    if json_output {
+        let json = serde_json::to_string_pretty(&listeners)?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    println!(
+        "{:<8}\t{:<8}\t{:<30}\t{:<12}\t{:<30}",
+        "PORT", "PID", "NAME", "PROTOCOL", "CLIENT"
+    );
+    for l in &listeners {
+        println!(
+            "{:<8}\t{:<8}\t{:<30}\t{:<12}\t{:<30}",
+            l.port,
+            l.pid,
+            truncate(&l.name, 30),
+            l.protocol.to_string(),
+            l.client.as_deref().unwrap_or("-")
+        );
+    }
+    println!("\nTotal: {} loopback listeners", listeners.len());
+
+    Ok(())
+}
+
 fn truncate(s: &str, max_width: usize) -> String {
-    if s.len() > max_width {
-        format!("{}..", &s[..max_width - 2])
+    // 按字符截断，避免在多字节字符（如中文进程名）中间切开
+    if s.chars().count() > max_width {
+        let head: String = s.chars().take(max_width - 2).collect();
+        format!("{}..", head)
     } else {
         s.to_string()
     }
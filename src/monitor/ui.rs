@@ -10,7 +10,9 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use super::nettop::{parse_nettop_csv_line, NettopCollector, ProcessTraffic};
-use super::tracker::TrafficTracker;
+use super::tracker::{ProcessStats, TrafficTracker};
+use crate::rule::store::RuleStore;
+use crate::rule::{MatchBy, OutputFormat, RuleFileManager, RulePolicy, RuleSelector, RuleTarget};
 
 enum AppEvent {
     Input(event::KeyEvent),
@@ -18,7 +20,7 @@ enum AppEvent {
     TrafficUpdate(Vec<ProcessTraffic>),
 }
 
-pub fn run_tui() -> Result<()> {
+pub fn run_tui(format: Option<OutputFormat>) -> Result<()> {
     // 1. Setup Terminal
     enable_raw_mode()?;
     let mut stdout = std::io::stdout();
@@ -91,9 +93,12 @@ pub fn run_tui() -> Result<()> {
     let mut sort_by = SortBy::RateDown;
     let _filter_pid: Option<i32> = None; // TODO: Support CLI arg
 
+    // 策略分配：选中行按 PID 记录，排序变化时选中的进程不变
+    let mut policies = PolicyState::new(format);
+
     loop {
         // Draw
-        terminal.draw(|f| ui(f, &tracker, sort_by))?;
+        terminal.draw(|f| ui(f, &tracker, sort_by, &policies))?;
 
         // Handle Events
         if event::poll(Duration::from_millis(100))? {
@@ -108,6 +113,20 @@ pub fn run_tui() -> Result<()> {
                             SortBy::Pid => SortBy::RateDown,
                         };
                     }
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        policies.select(&sorted_stats(&tracker, sort_by), -1)
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        policies.select(&sorted_stats(&tracker, sort_by), 1)
+                    }
+                    KeyCode::Char(c @ ('d' | 'p' | 'r')) => {
+                        let policy = match c {
+                            'd' => RulePolicy::Direct,
+                            'p' => RulePolicy::Proxy,
+                            _ => RulePolicy::Reject,
+                        };
+                        policies.assign(&tracker, policy);
+                    }
                     _ => {}
                 }
             }
@@ -139,13 +158,157 @@ enum SortBy {
     Pid,
 }
 
-fn ui(f: &mut Frame, tracker: &TrafficTracker, sort_by: SortBy) {
+/// 按当前排序方式排列的进程列表
+fn sorted_stats(tracker: &TrafficTracker, sort_by: SortBy) -> Vec<&ProcessStats> {
+    let mut stats = tracker.get_all_stats();
+
+    // Filtering (Show only active or all?)
+    // stats.retain(|s| s.rate_in > 0.0 || s.rate_out > 0.0);
+
+    stats.sort_by(|a, b| match sort_by {
+        SortBy::RateDown => b.rate_in.partial_cmp(&a.rate_in).unwrap(),
+        SortBy::RateUp => b.rate_out.partial_cmp(&a.rate_out).unwrap(),
+        SortBy::Pid => a.pid.cmp(&b.pid),
+    });
+    stats
+}
+
+/// 进程策略分配状态（选中的进程、rules.toml 中的规则、状态栏）
+struct PolicyState {
+    /// 分配策略时启用的规则格式，None 时只渲染到已启用的格式
+    format: Option<OutputFormat>,
+    /// 选中进程的 PID
+    selected: Option<i32>,
+    /// 规则主存储，每次分配后重新加载
+    store: RuleStore,
+    /// 状态栏文本
+    status: String,
+}
+
+impl PolicyState {
+    fn new(format: Option<OutputFormat>) -> Self {
+        let mut state = Self {
+            format,
+            selected: None,
+            store: RuleStore::default(),
+            status: String::new(),
+        };
+        let notes = state.reload_store();
+        if !notes.is_empty() {
+            state.status = notes.join(" | ");
+        }
+        state
+    }
+
+    /// 重新加载主存储，返回导入已有规则文件时的提示（TUI 中不能直接打印）
+    fn reload_store(&mut self) -> Vec<String> {
+        match RuleFileManager::new().and_then(|m| m.load_store_with_notes()) {
+            Ok((store, notes)) => {
+                self.store = store;
+                notes
+            }
+            Err(e) => {
+                self.status = format!("Failed to load rules: {:#}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// 进程名命中的规则策略（PROCESS-NAME，或文件名相同的 PROCESS-PATH）
+    fn policy_of(&self, name: &str) -> Option<RulePolicy> {
+        let selector = RuleSelector::Name(name.to_string());
+        self.store
+            .rules
+            .iter()
+            .find(|s| selector.matches(&s.rule))
+            .map(|s| s.rule.policy)
+    }
+
+    /// 状态栏显示的规则格式：指定的格式，或者 rules.toml 中已启用的格式
+    fn format_label(&self) -> String {
+        match self.format {
+            Some(format) => format.to_string(),
+            None if self.store.formats.is_empty() => "no format enabled".to_string(),
+            None => {
+                let formats: Vec<String> =
+                    self.store.formats.iter().map(|f| f.to_string()).collect();
+                formats.join(",")
+            }
+        }
+    }
+
+    /// 上下移动选中行，没有选中时选中第一行
+    fn select(&mut self, stats: &[&ProcessStats], delta: isize) {
+        if stats.is_empty() {
+            return;
+        }
+        let index = match self
+            .selected
+            .and_then(|pid| stats.iter().position(|s| s.pid == pid))
+        {
+            Some(i) => (i as isize + delta).clamp(0, stats.len() as isize - 1) as usize,
+            None => 0,
+        };
+        self.selected = Some(stats[index].pid);
+    }
+
+    /// 为选中的进程添加 PROCESS-NAME 规则（与 `rule add --pid` 相同）
+    ///
+    /// 结果和错误（如没有已启用的格式、格式无法表达进程规则）都显示在状态栏。
+    fn assign(&mut self, tracker: &TrafficTracker, policy: RulePolicy) {
+        let Some(process) = self
+            .selected
+            .and_then(|pid| tracker.get_all_stats().into_iter().find(|s| s.pid == pid))
+        else {
+            self.status = "Select a process first (Up/Down)".to_string();
+            return;
+        };
+
+        // 不等待其他进程的规则更新：等待会阻塞界面，提示也会打乱备用屏幕
+        let result = RuleFileManager::new()
+            .map(RuleFileManager::without_waiting)
+            .and_then(|manager| {
+                let target = RuleTarget::Pid(process.pid);
+                let rules = manager.build_rules(&target, MatchBy::Name, false, policy)?;
+                manager.add_rules(&rules, self.format, false, None)
+            });
+        self.status = match result {
+            Ok(outcome) => {
+                let mut status = if let Some(message) = outcome.unchanged_message() {
+                    message
+                } else {
+                    let files: Vec<String> = outcome
+                        .changed
+                        .iter()
+                        .filter_map(|p| p.file_name())
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .collect();
+                    format!(
+                        "{} -> {}: updated {}",
+                        process.name,
+                        policy,
+                        files.join(", ")
+                    )
+                };
+                for note in &outcome.notes {
+                    status.push_str(" | ");
+                    status.push_str(note);
+                }
+                status
+            }
+            Err(e) => format!("Failed to assign {}: {:#}", process.name, e),
+        };
+        self.reload_store();
+    }
+}
+
+fn ui(f: &mut Frame, tracker: &TrafficTracker, sort_by: SortBy, policies: &PolicyState) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3), // Header
             Constraint::Min(0),    // Table
-            Constraint::Length(3), // Footer
+            Constraint::Length(4), // Footer + Status
         ])
         .split(f.area());
 
@@ -155,17 +318,7 @@ fn ui(f: &mut Frame, tracker: &TrafficTracker, sort_by: SortBy) {
     f.render_widget(title, chunks[0]);
 
     // Data preparation
-    let mut stats: Vec<_> = tracker.get_all_stats().into_iter().collect();
-
-    // Filtering (Show only active or all?)
-    // stats.retain(|s| s.rate_in > 0.0 || s.rate_out > 0.0);
-
-    // Sorting
-    stats.sort_by(|a, b| match sort_by {
-        SortBy::RateDown => b.rate_in.partial_cmp(&a.rate_in).unwrap(),
-        SortBy::RateUp => b.rate_out.partial_cmp(&a.rate_out).unwrap(),
-        SortBy::Pid => a.pid.cmp(&b.pid),
-    });
+    let stats = sorted_stats(tracker, sort_by);
 
     // Formatting Rows
     let rows: Vec<Row> = stats
@@ -184,6 +337,13 @@ fn ui(f: &mut Frame, tracker: &TrafficTracker, sort_by: SortBy) {
                 Style::default()
             };
 
+            let (policy, policy_style) = match policies.policy_of(&s.name) {
+                Some(RulePolicy::Direct) => ("DIRECT", Style::default().fg(Color::Cyan)),
+                Some(RulePolicy::Proxy) => ("PROXY", Style::default().fg(Color::Green)),
+                Some(RulePolicy::Reject) => ("REJECT", Style::default().fg(Color::Red)),
+                None => ("-", Style::default()),
+            };
+
             Row::new(vec![
                 Cell::from(s.pid.to_string()),
                 Cell::from(name.to_string()),
@@ -192,9 +352,15 @@ fn ui(f: &mut Frame, tracker: &TrafficTracker, sort_by: SortBy) {
                 Cell::from(format_bytes(s.total_bytes_out)),
                 Cell::from(format_bytes(s.total_bytes_in)),
                 Cell::from(s.interface.clone()).style(iface_style),
+                Cell::from(policy).style(policy_style),
             ])
         })
         .collect();
+    let mut table_state = TableState::default().with_selected(
+        policies
+            .selected
+            .and_then(|pid| stats.iter().position(|s| s.pid == pid)),
+    );
 
     // Table
     let table = Table::new(
@@ -207,17 +373,19 @@ fn ui(f: &mut Frame, tracker: &TrafficTracker, sort_by: SortBy) {
             Constraint::Length(12), // Total Up
             Constraint::Length(12), // Total Down
             Constraint::Length(8),  // Interface
+            Constraint::Length(8),  // Policy
         ],
     )
     .header(
         Row::new(vec![
-            "PID", "NAME", "UP/s", "DOWN/s", "TOT UP", "TOT DOWN", "IFACE",
+            "PID", "NAME", "UP/s", "DOWN/s", "TOT UP", "TOT DOWN", "IFACE", "POLICY",
         ])
         .style(Style::default().add_modifier(Modifier::BOLD)),
     )
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
     .block(Block::default().borders(Borders::ALL).title("Processes"));
 
-    f.render_widget(table, chunks[1]);
+    f.render_stateful_widget(table, chunks[1], &mut table_state);
 
     // Footer
     let sort_str = match sort_by {
@@ -225,7 +393,12 @@ fn ui(f: &mut Frame, tracker: &TrafficTracker, sort_by: SortBy) {
         SortBy::RateUp => "UP Speed",
         SortBy::Pid => "PID",
     };
-    let footer_text = format!("Sort: {} (s) | Quit (q)", sort_str);
+    let footer_text = format!(
+        "Sort: {} (s) | Select (Up/Down) | DIRECT (d) PROXY (p) REJECT (r) -> {} | Quit (q)\n{}",
+        sort_str,
+        policies.format_label(),
+        policies.status
+    );
     let footer = Paragraph::new(footer_text).block(Block::default().borders(Borders::ALL));
     f.render_widget(footer, chunks[2]);
 }
@@ -252,3 +425,60 @@ fn format_bytes(bytes: u64) -> String {
         format!("{:.1} GB", b / 1024.0 / 1024.0 / 1024.0)
     }
 }
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::formatter::{Rule, RuleType};
+
+    fn traffic(pid: i32, name: &str, bytes: u64) -> ProcessTraffic {
+        ProcessTraffic {
+            pid,
+            name: name.to_string(),
+            interface: "en0".to_string(),
+            state: String::new(),
+            total_bytes_in: bytes,
+            total_bytes_out: bytes,
+        }
+    }
+
+    #[test]
+    fn test_select_and_policy() {
+        let mut tracker = TrafficTracker::new();
+        tracker.update(&[traffic(20, "Telegram", 10), traffic(10, "curl", 20)]);
+        let stats = sorted_stats(&tracker, SortBy::Pid);
+
+        let mut state = PolicyState {
+            format: None,
+            selected: None,
+            store: RuleStore::default(),
+            status: String::new(),
+        };
+        assert_eq!(state.format_label(), "no format enabled");
+        state.select(&stats, 1);
+        assert_eq!(state.selected, Some(10));
+        state.select(&stats, 1);
+        state.select(&stats, 1);
+        assert_eq!(state.selected, Some(20));
+        state.select(&stats, -1);
+        assert_eq!(state.selected, Some(10));
+
+        state.store.upsert(
+            Rule {
+                rule_type: RuleType::ProcessPath,
+                value: "/Applications/Telegram.app/Contents/MacOS/Telegram".to_string(),
+                policy: RulePolicy::Proxy,
+            },
+            None,
+        );
+        assert_eq!(state.policy_of("Telegram"), Some(RulePolicy::Proxy));
+        assert_eq!(state.policy_of("curl"), None);
+
+        state.store.enable(OutputFormat::Surge);
+        state.store.enable(OutputFormat::Clash);
+        assert_eq!(state.format_label(), "surge,clash");
+    }
+}
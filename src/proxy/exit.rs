@@ -0,0 +1,434 @@
+//! # 代理出口探测
+//!
+//! 这个模块负责探测本地代理的出口 IP，并把结果缓存到磁盘：
+//! - 扫描阶段先收集所有不同的出口路径，再用有界线程池并发探测
+//! - 结果写入 `~/.config/proxy-audit/exit-cache.json`，在 TTL 内重复扫描无需再次探测
+//!
+//! ## 出口路径
+//! - `Proxy`: 通过本地代理端口 (SOCKS5/HTTP) 请求
+//! - `DefaultRoute`: 不使用代理，走默认路由（TUN 模式下即 TUN 的出口）
+//! - `Direct`: 绑定物理接口直连，绕过 TUN，作为对照的"直连出口"
+//!
+//! ## 使用示例
+//! ```rust,ignore
+//! let mut cache = ExitCache::load_default();
+//! let routes = [ExitRoute::Proxy(ip, 7890), ExitRoute::Direct(None)];
+//! let exits = probe_routes(&routes, &mut cache, false);
+//! cache.save()?;
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+
+// ========================================
+// 常量定义
+// ========================================
+
+/// 成功探测结果的有效期
+const EXIT_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// 探测失败结果的有效期（较短，代理恢复后能尽快重新探测）
+const EXIT_CACHE_FAILURE_TTL: Duration = Duration::from_secs(60);
+
+/// 并发探测的最大线程数
+const MAX_CONCURRENT_PROBES: usize = 8;
+
+/// 缓存文件名
+const EXIT_CACHE_FILE: &str = "exit-cache.json";
+
+/// 直连探测使用的 IP 字面量端点（Cloudflare trace）
+///
+/// 使用 IP 而不是域名：TUN 的 fake-ip DNS 会把域名解析到 198.18.x.x，
+/// 绑定物理接口后将无法连接。
+const DIRECT_PROBE_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)), 80);
+
+/// `IP_BOUND_IF` 套接字选项（来自 macOS netinet/in.h）
+/// libc crate 只在 Apple 目标上导出，这里手动定义
+const IP_BOUND_IF: libc::c_int = 25;
+
+// ========================================
+// 出口路径
+// ========================================
+
+/// 一条需要探测出口 IP 的网络路径
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExitRoute {
+    /// 通过本地代理端口
+    Proxy(IpAddr, u16),
+    /// 不使用代理，走系统默认路由
+    DefaultRoute,
+    /// 绑定到指定物理接口直连（None 表示不绑定）
+    Direct(Option<String>),
+}
+
+impl ExitRoute {
+    /// 缓存键
+    pub fn cache_key(&self) -> String {
+        match self {
+            ExitRoute::Proxy(ip, port) => format!("{}:{}", ip, port),
+            ExitRoute::DefaultRoute => "default-route".to_string(),
+            ExitRoute::Direct(Some(iface)) => format!("direct@{}", iface),
+            ExitRoute::Direct(None) => "direct".to_string(),
+        }
+    }
+
+    /// 实际执行探测
+    fn probe(&self) -> Option<IpAddr> {
+        match self {
+            ExitRoute::Proxy(ip, port) => probe_exit_ip(*ip, *port),
+            ExitRoute::DefaultRoute => probe_default_route_exit(),
+            ExitRoute::Direct(iface) => probe_direct_exit(iface.as_deref()),
+        }
+    }
+}
+
+impl std::fmt::Display for ExitRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitRoute::Proxy(ip, port) => write!(f, "{}:{}", ip, port),
+            ExitRoute::DefaultRoute => write!(f, "default route"),
+            ExitRoute::Direct(Some(iface)) => write!(f, "direct via {}", iface),
+            ExitRoute::Direct(None) => write!(f, "direct"),
+        }
+    }
+}
+
+// ========================================
+// 出口缓存
+// ========================================
+
+/// 单个代理端点的缓存记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// 出口 IP（探测失败时为 None）
+    exit_ip: Option<IpAddr>,
+    /// 探测时间（Unix 秒）
+    probed_at: u64,
+}
+
+/// 持久化的出口 IP 缓存 (Proxy -> Exit IP)
+pub struct ExitCache {
+    /// 缓存文件路径（None 表示只在内存中使用）
+    path: Option<PathBuf>,
+    /// 缓存内容，键为 "ip:port"
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl ExitCache {
+    /// 从默认路径加载缓存
+    ///
+    /// 文件不存在或损坏时返回空缓存，不影响扫描。
+    pub fn load_default() -> Self {
+        let path = std::env::var("HOME").ok().map(|home| {
+            PathBuf::from(format!("{}/.config/proxy-audit", home)).join(EXIT_CACHE_FILE)
+        });
+
+        let entries = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self { path, entries }
+    }
+
+    /// 读取未过期的缓存记录
+    ///
+    /// 外层 Option 表示是否命中，内层 Option 表示当时是否探测成功。
+    fn get(&self, key: &str) -> Option<Option<IpAddr>> {
+        let entry = self.entries.get(key)?;
+        let ttl = if entry.exit_ip.is_some() {
+            EXIT_CACHE_TTL
+        } else {
+            EXIT_CACHE_FAILURE_TTL
+        };
+
+        if now_secs().saturating_sub(entry.probed_at) > ttl.as_secs() {
+            return None;
+        }
+        Some(entry.exit_ip)
+    }
+
+    /// 写入一条探测结果
+    fn insert(&mut self, key: String, exit_ip: Option<IpAddr>) {
+        self.entries.insert(
+            key,
+            CacheEntry {
+                exit_ip,
+                probed_at: now_secs(),
+            },
+        );
+    }
+
+    /// 保存缓存到磁盘
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write exit cache: {}", path.display()))
+    }
+}
+
+// ========================================
+// 公开 API 函数
+// ========================================
+
+/// 并发探测一组出口路径的出口 IP
+///
+/// ## 参数
+/// - `routes`: 出口路径列表（允许重复，内部会去重）
+/// - `cache`: 出口缓存，命中的路径不再探测，新结果会写回
+/// - `refresh`: 为 true 时忽略缓存，强制重新探测
+///
+/// ## 返回
+/// 出口路径 -> 出口 IP 的映射（探测失败的路径值为 None）
+pub fn probe_routes(
+    routes: &[ExitRoute],
+    cache: &mut ExitCache,
+    refresh: bool,
+) -> HashMap<ExitRoute, Option<IpAddr>> {
+    let mut results = HashMap::new();
+    let mut pending = Vec::new();
+    let mut seen = HashSet::new();
+
+    // 1. 去重并查询缓存
+    for route in routes {
+        if !seen.insert(route.clone()) {
+            continue;
+        }
+        match cache.get(&route.cache_key()).filter(|_| !refresh) {
+            Some(cached) => {
+                results.insert(route.clone(), cached);
+            }
+            None => pending.push(route.clone()),
+        }
+    }
+
+    if pending.is_empty() {
+        return results;
+    }
+
+    // 2. 在有界线程池中并发探测，避免一次性打开过多连接
+    let probe_all = || -> Vec<(ExitRoute, Option<IpAddr>)> {
+        pending
+            .par_iter()
+            .map(|route| (route.clone(), route.probe()))
+            .collect()
+    };
+    let probed = match rayon::ThreadPoolBuilder::new()
+        .num_threads(MAX_CONCURRENT_PROBES.min(pending.len()))
+        .build()
+    {
+        Ok(pool) => pool.install(probe_all),
+        Err(_) => probe_all(),
+    };
+
+    // 3. 写回缓存
+    for (route, exit_ip) in probed {
+        cache.insert(route.cache_key(), exit_ip);
+        results.insert(route, exit_ip);
+    }
+
+    results
+}
+
+/// 探测本地代理的出口 IP
+///
+/// 尝试通过代理访问 https://api.ipify.org?format=json
+/// 返回解析到的出口 IP
+pub fn probe_exit_ip(proxy_ip: IpAddr, proxy_port: u16) -> Option<IpAddr> {
+    let addr = format!("{}:{}", proxy_ip, proxy_port);
+
+    // 先尝试 SOCKS5 代理
+    let socks_proxy = format!("socks5h://{}", addr);
+    if let Some(ip) = try_probe_with_proxy(&socks_proxy) {
+        return Some(ip);
+    }
+
+    // 再尝试 HTTP 代理
+    let http_proxy = format!("http://{}", addr);
+    if let Some(ip) = try_probe_with_proxy(&http_proxy) {
+        return Some(ip);
+    }
+
+    None
+}
+
+/// 不使用任何代理、走系统默认路由探测出口 IP
+///
+/// TUN 模式下默认路由指向 utun，这就是 TUN 流量的真实出口，
+/// 不再借用系统 SOCKS 代理来推测。
+pub fn probe_default_route_exit() -> Option<IpAddr> {
+    let client = reqwest::blocking::Client::builder()
+        .no_proxy()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .ok()?;
+    fetch_ipify(&client)
+}
+
+/// 直连探测出口 IP（可选绑定物理接口以绕过 TUN）
+///
+/// ## 实现说明
+/// reqwest 无法在 connect 之前设置套接字选项，所以这里用 socket2 手动建立连接：
+/// 1. 如果指定了接口，设置 `IP_BOUND_IF` 把连接绑定到该接口
+/// 2. 向 1.1.1.1:80 发送 `GET /cdn-cgi/trace`
+/// 3. 解析响应中的 `ip=` 行
+pub fn probe_direct_exit(iface: Option<&str>) -> Option<IpAddr> {
+    let timeout = Duration::from_secs(5);
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).ok()?;
+
+    if let Some(iface) = iface {
+        let name = std::ffi::CString::new(iface).ok()?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            return None;
+        }
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IP,
+                IP_BOUND_IF,
+                &index as *const _ as *const libc::c_void,
+                std::mem::size_of_val(&index) as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return None;
+        }
+    }
+
+    socket
+        .connect_timeout(&DIRECT_PROBE_ADDR.into(), timeout)
+        .ok()?;
+    let mut stream: TcpStream = socket.into();
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    stream.set_write_timeout(Some(timeout)).ok()?;
+
+    let request = "GET /cdn-cgi/trace HTTP/1.1\r\nHost: 1.1.1.1\r\nConnection: close\r\n\r\n";
+    stream.write_all(request.as_bytes()).ok()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    parse_trace_ip(&response)
+}
+
+// ========================================
+// 内部辅助函数
+// ========================================
+
+/// 尝试通过指定代理探测出口 IP
+fn try_probe_with_proxy(proxy_url: &str) -> Option<IpAddr> {
+    // 构建带代理的 HTTP 客户端
+    let proxy = reqwest::Proxy::all(proxy_url).ok()?;
+    let client = reqwest::blocking::Client::builder()
+        .proxy(proxy)
+        .timeout(Duration::from_secs(5))
+        .build()
+        .ok()?;
+    fetch_ipify(&client)
+}
+
+/// 请求 ipify 获取出口 IP
+fn fetch_ipify(client: &reqwest::blocking::Client) -> Option<IpAddr> {
+    // 请求 IP 检测服务
+    #[derive(serde::Deserialize)]
+    struct IpifyResponse {
+        ip: String,
+    }
+
+    let resp: IpifyResponse = client
+        .get("https://api.ipify.org?format=json")
+        .send()
+        .ok()?
+        .json()
+        .ok()?;
+
+    resp.ip.parse().ok()
+}
+
+/// 解析 Cloudflare trace 响应中的 `ip=` 行
+fn parse_trace_ip(response: &str) -> Option<IpAddr> {
+    response
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("ip="))
+        .and_then(|ip| ip.parse().ok())
+}
+
+/// 当前 Unix 时间（秒）
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_ttl() {
+        let mut cache = ExitCache {
+            path: None,
+            entries: HashMap::new(),
+        };
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+        cache.insert("127.0.0.1:7890".to_string(), Some(ip));
+        assert_eq!(cache.get("127.0.0.1:7890"), Some(Some(ip)));
+
+        // 过期的失败记录不应命中
+        cache.entries.insert(
+            "127.0.0.1:1080".to_string(),
+            CacheEntry {
+                exit_ip: None,
+                probed_at: now_secs() - EXIT_CACHE_FAILURE_TTL.as_secs() - 1,
+            },
+        );
+        assert_eq!(cache.get("127.0.0.1:1080"), None);
+    }
+
+    #[test]
+    fn test_probe_routes_uses_cache() {
+        let mut cache = ExitCache {
+            path: None,
+            entries: HashMap::new(),
+        };
+        let route = ExitRoute::Proxy("127.0.0.1".parse().unwrap(), 7890);
+        let exit: IpAddr = "203.0.113.7".parse().unwrap();
+        cache.insert(route.cache_key(), Some(exit));
+
+        // 重复路径只应出现一次，且直接使用缓存结果
+        let results = probe_routes(&[route.clone(), route.clone()], &mut cache, false);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results.get(&route), Some(&Some(exit)));
+    }
+
+    #[test]
+    fn test_parse_trace_ip() {
+        let response = "HTTP/1.1 200 OK\r\n\r\nfl=123\nh=1.1.1.1\nip=198.51.100.20\nts=1\n";
+        assert_eq!(
+            parse_trace_ip(response),
+            Some("198.51.100.20".parse().unwrap())
+        );
+    }
+}
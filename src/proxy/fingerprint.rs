@@ -0,0 +1,483 @@
+//! # 本地监听端口协议识别
+//!
+//! 这个模块对本机回环地址上的监听端口做离线握手，判断它说的是什么协议：
+//! - SOCKS5: 发送方法协商报文 `05 01 00`
+//! - SOCKS4: 发送指向 127.0.0.1:1 的 CONNECT 请求
+//! - HTTP 代理: 发送指向本模块临时监听端口的 `CONNECT` 请求
+//! - Clash 控制器: 发送 `GET /version` REST 请求
+//!
+//! 所有握手的目标都是本机，不会访问外网。
+//!
+//! ## 使用示例
+//! ```rust,ignore
+//! let protocol = fingerprint(7890, Duration::from_millis(300));
+//! println!("{}", protocol); // 输出: MIXED
+//! ```
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use serde::Serialize;
+
+// ========================================
+// 协议类型
+// ========================================
+
+/// 监听端口识别出的协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ListenerProtocol {
+    /// SOCKS5 代理
+    Socks5,
+    /// SOCKS4/4a 代理
+    Socks4,
+    /// HTTP 代理（支持 CONNECT）
+    Http,
+    /// 同时支持 HTTP 与 SOCKS5 的混合端口（如 Clash mixed-port）
+    Mixed,
+    /// Clash / mihomo 外部控制器 (RESTful API)
+    ClashController,
+    /// 普通 HTTP 服务（不支持代理）
+    HttpServer,
+    /// 无法识别
+    Unknown,
+}
+
+impl ListenerProtocol {
+    /// 是否为可以转发流量的代理协议
+    pub fn is_proxy(&self) -> bool {
+        matches!(
+            self,
+            ListenerProtocol::Socks5
+                | ListenerProtocol::Socks4
+                | ListenerProtocol::Http
+                | ListenerProtocol::Mixed
+        )
+    }
+}
+
+impl std::fmt::Display for ListenerProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenerProtocol::Socks5 => write!(f, "SOCKS5"),
+            ListenerProtocol::Socks4 => write!(f, "SOCKS4"),
+            ListenerProtocol::Http => write!(f, "HTTP"),
+            ListenerProtocol::Mixed => write!(f, "MIXED"),
+            ListenerProtocol::ClashController => write!(f, "CLASH_API"),
+            ListenerProtocol::HttpServer => write!(f, "HTTP_SERVER"),
+            ListenerProtocol::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+// ========================================
+// 握手结果
+// ========================================
+
+/// 各项握手的原始结果
+#[derive(Debug, Default, Clone, Copy)]
+struct ProbeOutcome {
+    /// SOCKS5 方法协商得到合法应答
+    socks5: bool,
+    /// SOCKS4 请求得到合法应答
+    socks4: bool,
+    /// CONNECT 请求的响应
+    connect: Option<ConnectReply>,
+    /// `GET /version` 返回了 Clash 风格的响应
+    clash_api: bool,
+}
+
+/// CONNECT 请求的响应
+#[derive(Debug, Default, Clone, Copy)]
+struct ConnectReply {
+    /// HTTP 状态码
+    status: u16,
+    /// 带有 `Proxy-Authenticate` 头
+    proxy_authenticate: bool,
+    /// 带有代理才会加的头（`Proxy-*`、`Via`、`X-Squid-Error`）
+    proxy_headers: bool,
+    /// 带有 `X-Squid-Error` 头
+    squid_error: bool,
+}
+
+// ========================================
+// 公开 API 函数
+// ========================================
+
+/// 识别本地端口的协议
+///
+/// ## 参数
+/// - `port`: 监听在回环地址上的端口
+/// - `timeout`: 单次握手的连接/读写超时
+///
+/// ## 实现说明
+/// 依次尝试 127.0.0.1 和 ::1，每种握手都使用独立的 TCP 连接，
+/// 避免一次失败的握手污染下一次的连接状态。
+pub fn fingerprint(port: u16, timeout: Duration) -> ListenerProtocol {
+    let candidates = [
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(Ipv6Addr::LOCALHOST),
+    ];
+
+    for ip in candidates {
+        let addr = SocketAddr::new(ip, port);
+        // 先确认端口可连接，否则换下一个地址
+        if TcpStream::connect_timeout(&addr, timeout).is_err() {
+            continue;
+        }
+
+        let outcome = ProbeOutcome {
+            socks5: probe_socks5(&addr, timeout),
+            socks4: probe_socks4(&addr, timeout),
+            connect: probe_http_connect(&addr, timeout),
+            clash_api: probe_clash_api(&addr, timeout),
+        };
+        return classify(&outcome);
+    }
+
+    ListenerProtocol::Unknown
+}
+
+/// 根据进程名和端口猜测代理客户端
+///
+/// 优先使用进程名匹配；进程名无法识别时，再根据常见默认端口猜测。
+pub fn guess_client(process_name: &str, port: u16, protocol: ListenerProtocol) -> Option<String> {
+    let lower = process_name.to_lowercase();
+
+    // 进程名关键字 -> 客户端名称（顺序敏感：更具体的关键字放在前面）
+    const KNOWN_PROCESSES: &[(&str, &str)] = &[
+        ("clashx", "ClashX"),
+        ("clash-verge", "Clash Verge"),
+        ("verge-mihomo", "Clash Verge"),
+        ("mihomo", "mihomo"),
+        ("clash", "Clash"),
+        ("surge", "Surge"),
+        ("sing-box", "sing-box"),
+        ("sfm", "sing-box"),
+        ("v2rayu", "V2RayU"),
+        ("v2rayn", "v2rayN"),
+        ("v2ray", "V2Ray"),
+        ("xray", "Xray"),
+        ("shadowrocket", "Shadowrocket"),
+        ("stash", "Stash"),
+        ("loon", "Loon"),
+        ("quantumult", "Quantumult X"),
+        ("hysteria", "Hysteria"),
+        ("trojan", "Trojan"),
+        ("nekoray", "NekoRay"),
+        ("ss-local", "Shadowsocks"),
+        ("shadowsocks", "Shadowsocks"),
+    ];
+
+    for (keyword, client) in KNOWN_PROCESSES {
+        if lower.contains(keyword) {
+            return Some(client.to_string());
+        }
+    }
+
+    // 进程名无法识别，按默认端口猜测
+    let by_port = match (port, protocol) {
+        (9090, ListenerProtocol::ClashController) => "Clash (default controller port)",
+        (7890..=7893, p) if p.is_proxy() => "Clash (default port)",
+        (6152 | 6153, p) if p.is_proxy() => "Surge (default port)",
+        (10808 | 10809, p) if p.is_proxy() => "v2rayN (default port)",
+        (2080, p) if p.is_proxy() => "sing-box (default port)",
+        (1080, p) if p.is_proxy() => "SOCKS (default port)",
+        _ => return None,
+    };
+    Some(by_port.to_string())
+}
+
+// ========================================
+// 内部辅助函数
+// ========================================
+
+/// 根据握手结果判定协议
+fn classify(outcome: &ProbeOutcome) -> ListenerProtocol {
+    let http_proxy = is_proxy_connect_reply(outcome);
+
+    if outcome.socks5 && http_proxy {
+        return ListenerProtocol::Mixed;
+    }
+    if outcome.socks5 {
+        return ListenerProtocol::Socks5;
+    }
+    if http_proxy {
+        return ListenerProtocol::Http;
+    }
+    if outcome.socks4 {
+        return ListenerProtocol::Socks4;
+    }
+    if outcome.clash_api {
+        return ListenerProtocol::ClashController;
+    }
+    if outcome.connect.is_some() {
+        return ListenerProtocol::HttpServer;
+    }
+    ListenerProtocol::Unknown
+}
+
+/// 判断 CONNECT 请求的响应是否来自代理
+///
+/// 认可以下响应：
+/// - 200：隧道已建立
+/// - 407 且带 `Proxy-Authenticate` 头：要求代理认证
+/// - 403 且带 `X-Squid-Error` 头：squid 默认只允许 CONNECT 到 443 端口
+/// - 5xx 且带 `Proxy-*` / `Via` / `X-Squid-Error` 头：代理拨号失败（502/503/504）
+///
+/// 普通 Web 服务器对 CONNECT 会返回 400/403/404/405/501 等，不带这些头时不能作为代理的证据。
+fn is_proxy_connect_reply(outcome: &ProbeOutcome) -> bool {
+    let Some(reply) = outcome.connect else {
+        return false;
+    };
+    match reply.status {
+        200 => true,
+        407 => reply.proxy_authenticate,
+        403 => reply.squid_error,
+        500..=599 => reply.proxy_headers,
+        _ => false,
+    }
+}
+
+/// 建立一个带超时的连接
+fn connect(addr: &SocketAddr, timeout: Duration) -> Option<TcpStream> {
+    let stream = TcpStream::connect_timeout(addr, timeout).ok()?;
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    stream.set_write_timeout(Some(timeout)).ok()?;
+    Some(stream)
+}
+
+/// SOCKS5 方法协商：VER=5, NMETHODS=1, METHOD=0 (无认证)
+///
+/// 合法应答为 `05 XX`，其中 XX 为服务端选中的方法（0xFF 表示无可用方法，
+/// 但依然说明对端是 SOCKS5）。
+fn probe_socks5(addr: &SocketAddr, timeout: Duration) -> bool {
+    let Some(mut stream) = connect(addr, timeout) else {
+        return false;
+    };
+    if stream.write_all(&[0x05, 0x01, 0x00]).is_err() {
+        return false;
+    }
+    let mut reply = [0u8; 2];
+    if stream.read_exact(&mut reply).is_err() {
+        return false;
+    }
+    reply[0] == 0x05 && matches!(reply[1], 0x00 | 0x01 | 0x02 | 0xFF)
+}
+
+/// SOCKS4 CONNECT 到 127.0.0.1:1（通常无人监听，代理会返回拒绝）
+///
+/// 合法应答为 `00 5A..5D` 加 6 字节地址。
+fn probe_socks4(addr: &SocketAddr, timeout: Duration) -> bool {
+    let Some(mut stream) = connect(addr, timeout) else {
+        return false;
+    };
+    // VER=4, CMD=1, PORT=1, IP=127.0.0.1, USERID="" (NUL)
+    let request = [0x04, 0x01, 0x00, 0x01, 127, 0, 0, 1, 0x00];
+    if stream.write_all(&request).is_err() {
+        return false;
+    }
+    let mut reply = [0u8; 8];
+    if stream.read_exact(&mut reply).is_err() {
+        return false;
+    }
+    reply[0] == 0x00 && (0x5A..=0x5D).contains(&reply[1])
+}
+
+/// 发送 HTTP CONNECT 并解析响应
+///
+/// 目标是临时打开的本地监听端口（内核完成握手，不需要 accept），
+/// 先拨号再应答的代理可以连通并返回 200；无法打开时退回 127.0.0.1:1。
+fn probe_http_connect(addr: &SocketAddr, timeout: Duration) -> Option<ConnectReply> {
+    let sink = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).ok();
+    let target = sink
+        .as_ref()
+        .and_then(|l| l.local_addr().ok())
+        .unwrap_or_else(|| SocketAddr::from((Ipv4Addr::LOCALHOST, 1)));
+    let request = format!(
+        "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\nConnection: close\r\n\r\n",
+        target
+    );
+    let response = http_exchange(addr, timeout, &request)?;
+    parse_connect_reply(&response)
+}
+
+/// 解析 CONNECT 响应的状态码和代理相关的头
+fn parse_connect_reply(response: &str) -> Option<ConnectReply> {
+    let mut reply = ConnectReply {
+        status: parse_status_code(response)?,
+        ..Default::default()
+    };
+    let headers = response.lines().skip(1).take_while(|line| !line.is_empty());
+    for (name, _) in headers.filter_map(|line| line.split_once(':')) {
+        let name = name.trim().to_ascii_lowercase();
+        reply.proxy_authenticate |= name == "proxy-authenticate";
+        reply.squid_error |= name == "x-squid-error";
+        reply.proxy_headers |=
+            name.starts_with("proxy-") || name == "via" || name == "x-squid-error";
+    }
+    Some(reply)
+}
+
+/// 发送 `GET /version`，判断是否为 Clash 外部控制器
+///
+/// 控制器返回 `{"version": "..."}`；设置了 secret 时返回 401 和
+/// `{"message":"Unauthorized"}`，两种情况都视为控制器。
+fn probe_clash_api(addr: &SocketAddr, timeout: Duration) -> bool {
+    let request = format!(
+        "GET /version HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        addr
+    );
+    let Some(response) = http_exchange(addr, timeout, &request) else {
+        return false;
+    };
+    match parse_status_code(&response) {
+        Some(200) => response.contains("\"version\""),
+        Some(401) => response.contains("Unauthorized"),
+        _ => false,
+    }
+}
+
+/// 发送一段 HTTP 请求并读取响应的开头部分
+fn http_exchange(addr: &SocketAddr, timeout: Duration, request: &str) -> Option<String> {
+    let mut stream = connect(addr, timeout)?;
+    stream.write_all(request.as_bytes()).ok()?;
+
+    // 只需要状态行和少量 body，读满缓冲区或超时即可
+    let mut buf = vec![0u8; 1024];
+    let mut len = 0;
+    while len < buf.len() {
+        match stream.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(_) => break,
+        }
+    }
+
+    if len == 0 {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// 解析 `HTTP/1.x NNN ...` 状态行
+fn parse_status_code(response: &str) -> Option<u16> {
+    let status_line = response.lines().next()?;
+    if !status_line.starts_with("HTTP/") {
+        return None;
+    }
+    status_line.split_whitespace().nth(1)?.parse().ok()
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_classify() {
+        let mixed = ProbeOutcome {
+            socks5: true,
+            connect: parse_connect_reply("HTTP/1.1 200 Connection established\r\n\r\n"),
+            ..Default::default()
+        };
+        assert_eq!(classify(&mixed), ListenerProtocol::Mixed);
+
+        // 需要认证的 HTTP 代理必须带 Proxy-Authenticate 头
+        let auth = ProbeOutcome {
+            connect: parse_connect_reply(
+                "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic\r\n\r\n",
+            ),
+            ..Default::default()
+        };
+        assert_eq!(classify(&auth), ListenerProtocol::Http);
+
+        // 普通 Web 服务器对 CONNECT 返回 403 / 405 / 407 / 502 (无代理头) 不算代理
+        for status in [403, 405, 407, 502] {
+            let web = ProbeOutcome {
+                connect: parse_connect_reply(&format!(
+                    "HTTP/1.1 {} Error\r\nServer: nginx\r\n\r\n",
+                    status
+                )),
+                ..Default::default()
+            };
+            assert_eq!(classify(&web), ListenerProtocol::HttpServer);
+        }
+
+        let controller = ProbeOutcome {
+            connect: parse_connect_reply("HTTP/1.1 404 Not Found\r\n\r\n"),
+            clash_api: true,
+            ..Default::default()
+        };
+        assert_eq!(classify(&controller), ListenerProtocol::ClashController);
+    }
+
+    #[test]
+    fn test_proxy_dial_failure() {
+        // 代理连不上目标时返回 5xx，靠代理特有的头识别
+        let squid = "HTTP/1.1 503 Service Unavailable\r\nServer: squid/5.7\r\n\
+                     X-Squid-Error: ERR_CONNECT_FAIL 111\r\nVia: 1.1 localhost (squid/5.7)\r\n\r\n";
+        let gateway = "HTTP/1.1 502 Bad Gateway\r\nProxy-Agent: Privoxy\r\n\r\n";
+        for response in [squid, gateway] {
+            let outcome = ProbeOutcome {
+                connect: parse_connect_reply(response),
+                ..Default::default()
+            };
+            assert_eq!(classify(&outcome), ListenerProtocol::Http);
+        }
+
+        // squid 拒绝 CONNECT 到非 443 端口
+        let denied = ProbeOutcome {
+            connect: parse_connect_reply(
+                "HTTP/1.1 403 Forbidden\r\nX-Squid-Error: ERR_ACCESS_DENIED 0\r\n\r\n",
+            ),
+            ..Default::default()
+        };
+        assert_eq!(classify(&denied), ListenerProtocol::Http);
+
+        // 头只出现在 body 中不算
+        let body = ProbeOutcome {
+            connect: parse_connect_reply("HTTP/1.1 503 Busy\r\n\r\nVia: nowhere"),
+            ..Default::default()
+        };
+        assert_eq!(classify(&body), ListenerProtocol::HttpServer);
+    }
+
+    #[test]
+    fn test_guess_client() {
+        assert_eq!(
+            guess_client("ClashX Pro", 7890, ListenerProtocol::Mixed).as_deref(),
+            Some("ClashX")
+        );
+        assert_eq!(
+            guess_client("unknown", 6152, ListenerProtocol::Http).as_deref(),
+            Some("Surge (default port)")
+        );
+        assert_eq!(
+            guess_client("nginx", 8080, ListenerProtocol::HttpServer),
+            None
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_socks5_stub() {
+        // 本地 SOCKS5 桩服务：对方法协商回复 05 00，其余请求直接关闭
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut stream = stream;
+                let mut buf = [0u8; 3];
+                if stream.read_exact(&mut buf).is_ok() && buf == [0x05, 0x01, 0x00] {
+                    let _ = stream.write_all(&[0x05, 0x00]);
+                }
+            }
+        });
+
+        let protocol = fingerprint(port, Duration::from_millis(300));
+        assert_eq!(protocol, ListenerProtocol::Socks5);
+    }
+}
@@ -2,7 +2,10 @@
 //!
 //! 这个模块负责检测系统代理配置和识别代理连接。
 
+pub mod exit;
+pub mod fingerprint;
 pub mod scutil;
 
 // 重新导出常用类型
+pub use fingerprint::{fingerprint, guess_client, ListenerProtocol};
 pub use scutil::{get_default_route_interface, read_system_proxy, ProxyServer, SystemProxy};
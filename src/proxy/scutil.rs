@@ -310,6 +310,51 @@ pub fn is_vpn_interface(iface: &str) -> bool {
     iface.starts_with("utun")
 }
 
+/// 获取物理网络接口名称（绕过 VPN/TUN 的出口）
+///
+/// ## 用途
+/// 默认路由被 TUN 接管时，直连探测需要绑定到物理接口才能得到真实的直连出口
+///
+/// ## 实现说明
+/// 执行 `scutil --nwi`，按优先级顺序返回第一个非隧道接口
+///
+/// 输出格式示例：
+/// ```text
+/// IPv4 network interface information
+///      utun4 : flags      : 0x5 (IPv4,DNS)
+///            address    : 198.18.0.1
+///        en0 : flags      : 0x5 (IPv4,DNS)
+///            address    : 192.168.1.10
+/// ```
+pub fn get_physical_interface() -> Option<String> {
+    let output = Command::new("scutil").arg("--nwi").output().ok()?;
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    parse_nwi_physical_interface(&output_str)
+}
+
+/// 从 `scutil --nwi` 输出中找出第一个非隧道接口
+fn parse_nwi_physical_interface(output: &str) -> Option<String> {
+    for line in output.lines() {
+        // 接口行格式: "     en0 : flags      : 0x5 (IPv4,DNS)"
+        let Some((iface, rest)) = line.trim().split_once(" : ") else {
+            continue;
+        };
+        // 注意末尾的 "REACH : flags 0x..." 汇总行没有第二个冒号，需要排除
+        let is_iface_line = rest
+            .split_once(':')
+            .is_some_and(|(key, _)| key.trim() == "flags");
+        if !is_iface_line {
+            continue;
+        }
+        let is_tunnel =
+            is_vpn_interface(iface) || iface.starts_with("ipsec") || iface.starts_with("ppp");
+        if !is_tunnel {
+            return Some(iface.to_string());
+        }
+    }
+    None
+}
+
 // ========================================
 // 测试模块
 // ========================================
@@ -349,6 +394,24 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_parse_nwi_physical_interface() {
+        let output = r#"Network information
+
+IPv4 network interface information
+     utun4 : flags      : 0x5 (IPv4,DNS)
+           address    : 198.18.0.1
+           VPN server : 127.0.0.1
+           reach      : 0x00000003 (Reachable,Transient Connection)
+       en0 : flags      : 0x5 (IPv4,DNS)
+           address    : 192.168.1.10
+           reach      : 0x00000002 (Reachable)
+
+   REACH : flags 0x00000003 (Reachable,Transient Connection)
+"#;
+        assert_eq!(parse_nwi_physical_interface(output).as_deref(), Some("en0"));
+    }
+
     #[test]
     fn test_is_vpn_interface() {
         assert!(is_vpn_interface("utun0"));
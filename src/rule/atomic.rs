@@ -0,0 +1,185 @@
+//! # 规则文件的原子更新
+//!
+//! 一次 `rule add` / `remove` / `move` 会同时改写 `rules.toml` 和所有启用格式的规则文件：
+//! 1. 整个“读取 - 修改 - 写入”过程持有配置目录下 `.rules.lock` 的 flock 排他锁，
+//!    并发执行的命令会排队，不会互相覆盖
+//! 2. 每个文件先写入同目录的临时文件并 fsync，再用 rename 原子替换，
+//!    监视规则文件的代理客户端不会读到写了一半的文件
+//! 3. 任何一个文件写入失败时，把已经替换的文件恢复为原内容（新建的文件被删除）
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// 锁文件名（位于配置目录下）
+pub const LOCK_FILE: &str = ".rules.lock";
+
+/// 规则更新锁，drop 时释放
+pub struct UpdateLock {
+    /// 持有 flock 的文件，关闭即解锁
+    _file: File,
+}
+
+impl UpdateLock {
+    /// 获取排他锁，其他进程持有锁时等待
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = open_lock_file(path)?;
+        if flock(&file, libc::LOCK_EX | libc::LOCK_NB).is_err() {
+            eprintln!("Waiting for another proxy-audit rule update to finish...");
+            flock(&file, libc::LOCK_EX)
+                .with_context(|| format!("Failed to lock {}", path.display()))?;
+        }
+        Ok(Self { _file: file })
+    }
+
+    /// 尝试获取排他锁，不等待也不输出；其他进程持有锁时返回 None（TUI 使用）
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let file = open_lock_file(path)?;
+        match flock(&file, libc::LOCK_EX | libc::LOCK_NB) {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to lock {}", path.display())),
+        }
+    }
+}
+
+/// 打开（必要时创建）锁文件
+fn open_lock_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open lock file {}", path.display()))
+}
+
+fn flock(file: &File, operation: libc::c_int) -> std::io::Result<()> {
+    let ret = unsafe { libc::flock(file.as_raw_fd(), operation) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 原子写入单个文件：同目录临时文件 + fsync + rename
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let temp = temp_path(path);
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// 同目录下的临时文件路径，如 `.rules-direct.yaml.tmp-1234`
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp-{}", name, std::process::id()))
+}
+
+/// 整体写入一组文件，任何一个失败时回滚已写入的文件
+pub fn write_all(files: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+    // 已替换的文件及其原内容（None 表示原来不存在）
+    let mut written: Vec<(&Path, Option<Vec<u8>>)> = Vec::new();
+
+    for (path, content) in files {
+        let original = match fs::read(path) {
+            Ok(bytes) => Some(bytes),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                let err =
+                    anyhow::Error::new(e).context(format!("Failed to read {}", path.display()));
+                return Err(rollback(written, err));
+            }
+        };
+        if let Err(err) = write_atomic(path, content) {
+            return Err(rollback(written, err));
+        }
+        written.push((path, original));
+    }
+    Ok(())
+}
+
+/// 按相反顺序恢复已写入的文件，恢复失败的文件附加在错误信息中
+fn rollback(written: Vec<(&Path, Option<Vec<u8>>)>, err: anyhow::Error) -> anyhow::Error {
+    let mut failed = Vec::new();
+    for (path, original) in written.into_iter().rev() {
+        let restored = match original {
+            Some(bytes) => write_atomic(path, &bytes),
+            None => fs::remove_file(path).map_err(anyhow::Error::from),
+        };
+        if restored.is_err() {
+            failed.push(path.display().to_string());
+        }
+    }
+    if failed.is_empty() {
+        err.context("Rule files were left unchanged")
+    } else {
+        err.context(format!(
+            "Rollback failed, please check: {}",
+            failed.join(", ")
+        ))
+    }
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_all_rolls_back() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("rules-direct.yaml");
+        let created = dir.join("rules-proxy.yaml");
+        fs::write(&existing, b"old").unwrap();
+
+        let files = vec![
+            (existing.clone(), b"new".to_vec()),
+            (created.clone(), b"new".to_vec()),
+            // 父目录不存在，写入失败
+            (dir.join("missing/rules-reject.yaml"), b"new".to_vec()),
+        ];
+        let err = write_all(&files).unwrap_err();
+        assert!(format!("{:#}", err).contains("left unchanged"));
+        assert_eq!(fs::read(&existing).unwrap(), b"old");
+        assert!(!created.exists());
+
+        write_all(&files[..2]).unwrap();
+        assert_eq!(fs::read(&existing).unwrap(), b"new");
+        assert_eq!(fs::read(&created).unwrap(), b"new");
+        // 没有残留的临时文件
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("proxy-audit-lock-{}", std::process::id()));
+        let lock = UpdateLock::acquire(&path).unwrap();
+        // 同一进程内另一个文件描述符也无法获得 flock
+        let other = File::open(&path).unwrap();
+        assert!(flock(&other, libc::LOCK_EX | libc::LOCK_NB).is_err());
+        assert!(UpdateLock::try_acquire(&path).unwrap().is_none());
+        drop(lock);
+        assert!(flock(&other, libc::LOCK_EX | libc::LOCK_NB).is_ok());
+        drop(other);
+        assert!(UpdateLock::try_acquire(&path).unwrap().is_some());
+        fs::remove_file(&path).unwrap();
+    }
+}
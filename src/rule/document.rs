@@ -0,0 +1,824 @@
+//! # 规则文件的无损解析与合并
+//!
+//! 规则文件由 `rules.toml` 渲染生成，但用户也会手动往里面添加规则和注释
+//! （`DOMAIN-KEYWORD`、`GEOIP`、`DST-PORT` 等本工具不管理的类型）。
+//! 重新渲染时不再整体覆盖，而是把新内容合并到已有文件中：
+//! - 注释、空行和无法识别的规则原样保留，位置不变
+//! - 仍在主存储中的规则保留原文和位置
+//! - 之前由主存储管理、现在被删除或移走的规则被移除
+//! - 新增的规则追加到对应列表的末尾
+//!
+//! 已有文件无法解析（JSON 语法错误、YAML 的行内列表等）时返回错误，调用方不会覆盖它。
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+
+use super::formatter::{Rule, RulePolicy, RuleType};
+use super::manager::parse_net;
+use super::srs;
+
+/// 规则文件的语法
+#[derive(Clone, Copy)]
+pub enum Syntax {
+    /// 每行一条规则（Surge 系 .list、Clash text provider）
+    List(ItemParser),
+    /// YAML 顶层键下的 `- item` 列表（Clash / Stash provider、Egern 规则集）
+    Yaml(ItemParser),
+    /// sing-box JSON 源文件
+    Json,
+    /// sing-box 二进制规则集，不包含额外内容
+    Binary,
+}
+
+/// 解析单个列表条目：`key` 为所在的 YAML 键（纯列表为 None），无法识别时返回 None
+pub type ItemParser = fn(key: Option<&str>, item: &str) -> Option<(RuleType, String)>;
+
+// ========================================
+// 条目解析器
+// ========================================
+
+/// Clash classical / Surge 风格的 `TYPE,VALUE[,...]`（YAML 中只识别 `payload` 下的条目）
+pub fn classical_item(key: Option<&str>, item: &str) -> Option<(RuleType, String)> {
+    if key.is_some_and(|k| k != "payload") {
+        return None;
+    }
+    let mut parts = item.split(',').map(str::trim);
+    let rule_type = match parts.next()? {
+        "IP-CIDR" => RuleType::IpCidr,
+        "IP-CIDR6" => RuleType::IpCidr6,
+        "DOMAIN-SUFFIX" => RuleType::DomainSuffix,
+        "DOMAIN" => RuleType::Domain,
+        "PROCESS-NAME" => RuleType::ProcessName,
+        "PROCESS-PATH" => RuleType::ProcessPath,
+        _ => return None,
+    };
+    let value = parts.next().filter(|v| !v.is_empty())?;
+    Some((rule_type, value.to_string()))
+}
+
+/// Quantumult X 的 `host-suffix, example.com`
+pub fn qx_item(_key: Option<&str>, item: &str) -> Option<(RuleType, String)> {
+    let mut parts = item.split(',').map(str::trim);
+    let rule_type = match parts.next()?.to_lowercase().as_str() {
+        "ip-cidr" => RuleType::IpCidr,
+        "ip6-cidr" => RuleType::IpCidr6,
+        "host-suffix" => RuleType::DomainSuffix,
+        "host" => RuleType::Domain,
+        _ => return None,
+    };
+    let value = parts.next().filter(|v| !v.is_empty())?;
+    Some((rule_type, value.to_string()))
+}
+
+/// Clash `behavior: domain`：`+.example.com` 为后缀，其余通配写法不识别
+pub fn domain_item(key: Option<&str>, item: &str) -> Option<(RuleType, String)> {
+    if key.is_some_and(|k| k != "payload") {
+        return None;
+    }
+    if let Some(suffix) = item.strip_prefix("+.") {
+        return Some((RuleType::DomainSuffix, suffix.to_string()));
+    }
+    if item.is_empty() || item.contains(['*', '+']) || item.starts_with('.') {
+        return None;
+    }
+    Some((RuleType::Domain, item.to_string()))
+}
+
+/// Clash `behavior: ipcidr`：每行一个网段
+pub fn ipcidr_item(key: Option<&str>, item: &str) -> Option<(RuleType, String)> {
+    if key.is_some_and(|k| k != "payload") {
+        return None;
+    }
+    let rule_type = match parse_net(item)? {
+        ipnet::IpNet::V4(_) => RuleType::IpCidr,
+        ipnet::IpNet::V6(_) => RuleType::IpCidr6,
+    };
+    Some((rule_type, item.to_string()))
+}
+
+/// Egern 规则集：键决定规则类型，其他键 (domain_keyword_set 等) 不识别
+pub fn egern_item(key: Option<&str>, item: &str) -> Option<(RuleType, String)> {
+    let rule_type = match key? {
+        "domain_set" => RuleType::Domain,
+        "domain_suffix_set" => RuleType::DomainSuffix,
+        "ip_cidr_set" => RuleType::IpCidr,
+        "ip_cidr6_set" => RuleType::IpCidr6,
+        _ => return None,
+    };
+    (!item.is_empty()).then(|| (rule_type, item.to_string()))
+}
+
+/// sing-box 规则对象中本工具识别的键
+fn singbox_key(key: &str, value: &str) -> Option<RuleType> {
+    match key {
+        "ip_cidr" if value.contains(':') => Some(RuleType::IpCidr6),
+        "ip_cidr" => Some(RuleType::IpCidr),
+        "domain" => Some(RuleType::Domain),
+        "domain_suffix" => Some(RuleType::DomainSuffix),
+        "process_name" => Some(RuleType::ProcessName),
+        "process_path" => Some(RuleType::ProcessPath),
+        _ => None,
+    }
+}
+
+// ========================================
+// 公开 API
+// ========================================
+
+/// 两条规则是否相同（IP 与 /32、/128 网段视为相同）
+pub fn same_rule(a: &Rule, b: &Rule) -> bool {
+    a.rule_type == b.rule_type
+        && (a.value == b.value
+            || matches!(
+                (parse_net(&a.value), parse_net(&b.value)),
+                (Some(x), Some(y)) if x == y
+            ))
+}
+
+/// 内容是否为 Quantumult X 语法的规则列表
+///
+/// 旧版本的 QX 规则文件与 Surge 同名（`rules-*.list`），只能按内容区分：
+/// QX 的类型是小写的 `host-suffix`、`ip-cidr`（可带策略，如 `host, example.com, proxy`），
+/// Surge 的类型总是大写。
+pub fn is_qx_list(content: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(content) else {
+        return false;
+    };
+    text.lines().map(str::trim).any(|line| {
+        line.starts_with(|c: char| c.is_ascii_lowercase()) && qx_item(None, line).is_some()
+    })
+}
+
+/// 读取文件中本工具能识别的规则（导入主存储用）
+pub fn parse_rules(syntax: Syntax, content: &[u8], policy: RulePolicy) -> Result<Vec<Rule>> {
+    let rule = |(rule_type, value): (RuleType, String)| Rule {
+        rule_type,
+        value,
+        policy,
+    };
+    match syntax {
+        // 同一个 sing-box 文件名下也可能是之前生成的 .srs
+        Syntax::Json | Syntax::Binary if srs::is_srs(content) => srs::decode(content, policy),
+        Syntax::Binary => bail!("not a sing-box binary rule set"),
+        Syntax::Json => {
+            let doc = parse_json(content)?;
+            Ok(json_rules(&doc)?
+                .iter()
+                .filter_map(managed_values)
+                .flatten()
+                .map(rule)
+                .collect())
+        }
+        Syntax::List(parser) | Syntax::Yaml(parser) => {
+            let lines = parse_lines(syntax, to_str(content)?, parser)?;
+            Ok(lines
+                .into_iter()
+                .filter_map(|line| match line {
+                    Line::Item { rule, .. } => rule,
+                    _ => None,
+                })
+                .map(rule)
+                .collect())
+        }
+    }
+}
+
+/// 把新生成的文件内容合并到已有文件中
+///
+/// `retired` 判断一条规则之前是否由主存储管理：这样的规则不在新内容中时说明已被删除或移走，
+/// 会从文件中移除；否则视为用户手动添加的规则，原样保留。
+pub fn merge(
+    syntax: Syntax,
+    existing: &[u8],
+    generated: &[u8],
+    policy: RulePolicy,
+    retired: impl Fn(&Rule) -> bool,
+) -> Result<Vec<u8>> {
+    let is_retired = |(rule_type, value): &(RuleType, String)| {
+        retired(&Rule {
+            rule_type: rule_type.clone(),
+            value: value.clone(),
+            policy,
+        })
+    };
+    match syntax {
+        Syntax::Binary => {
+            // 二进制规则集无法保存额外内容，只需确认原文件是可识别的规则集
+            srs::decode(existing, policy)?;
+            Ok(generated.to_vec())
+        }
+        Syntax::Json => merge_json(existing, generated, is_retired),
+        Syntax::List(parser) | Syntax::Yaml(parser) => {
+            let existing = parse_lines(syntax, to_str(existing)?, parser)?;
+            let generated = parse_lines(syntax, to_str(generated)?, parser)?;
+            let merged = merge_lines(existing, generated, is_retired);
+            let mut content = String::new();
+            for line in merged {
+                content.push_str(line.text());
+                content.push('\n');
+            }
+            Ok(content.into_bytes())
+        }
+    }
+}
+
+// ========================================
+// 行格式（列表 / YAML）
+// ========================================
+
+/// 文件中的一行
+#[derive(Debug, Clone)]
+enum Line {
+    /// 注释、空行、标量等，原样保留
+    Raw(String),
+    /// YAML 顶层键，如 `payload:` 或 `payload: []`
+    Key { name: String, text: String },
+    /// 列表条目；`rule` 为 None 表示本工具不识别的规则
+    Item {
+        key: Option<String>,
+        rule: Option<(RuleType, String)>,
+        text: String,
+    },
+}
+
+impl Line {
+    fn text(&self) -> &str {
+        match self {
+            Line::Raw(text) | Line::Key { text, .. } | Line::Item { text, .. } => text,
+        }
+    }
+}
+
+fn to_str(content: &[u8]) -> Result<&str> {
+    std::str::from_utf8(content).context("file is not valid UTF-8")
+}
+
+/// 按行解析文件
+fn parse_lines(syntax: Syntax, content: &str, parser: ItemParser) -> Result<Vec<Line>> {
+    let yaml = matches!(syntax, Syntax::Yaml(_));
+    let mut lines = Vec::new();
+    let mut current: Option<String> = None;
+
+    for (index, text) in content.lines().enumerate() {
+        let trimmed = text.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || (!yaml && trimmed.starts_with("//")) {
+            lines.push(Line::Raw(text.to_string()));
+            continue;
+        }
+
+        if !yaml {
+            lines.push(Line::Item {
+                key: None,
+                rule: parser(None, trimmed),
+                text: text.to_string(),
+            });
+            continue;
+        }
+
+        if text.trim_start_matches(' ').starts_with('\t') {
+            bail!(
+                "line {}: tabs are not allowed in YAML indentation",
+                index + 1
+            );
+        }
+
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            let Some(key) = &current else {
+                bail!("line {}: list item outside of a list", index + 1);
+            };
+            lines.push(Line::Item {
+                key: Some(key.clone()),
+                rule: yaml_scalar(item).and_then(|item| parser(Some(key), &item)),
+                text: text.to_string(),
+            });
+            continue;
+        }
+
+        if text.starts_with(' ') {
+            // 嵌套内容（本工具不会生成），原样保留
+            lines.push(Line::Raw(text.to_string()));
+            continue;
+        }
+
+        let Some((name, rest)) = trimmed.split_once(':') else {
+            bail!("line {}: expected `key:` or a list item", index + 1);
+        };
+        let name = name.trim().to_string();
+        let rest = strip_yaml_comment(rest).trim();
+        if rest.is_empty() || rest == "[]" {
+            current = Some(name.clone());
+            lines.push(Line::Key {
+                name,
+                text: text.to_string(),
+            });
+        } else if rest.starts_with('[') {
+            bail!(
+                "line {}: flow-style list for '{}' cannot be edited, use one `- item` per line",
+                index + 1,
+                name
+            );
+        } else {
+            current = None;
+            lines.push(Line::Raw(text.to_string()));
+        }
+    }
+    Ok(lines)
+}
+
+/// 去掉 YAML 标量的引号和行尾注释，无法处理的写法返回 None
+fn yaml_scalar(item: &str) -> Option<String> {
+    let item = item.trim();
+    for quote in ['\'', '"'] {
+        if let Some(rest) = item.strip_prefix(quote) {
+            let end = rest.find(quote)?;
+            return Some(rest[..end].to_string());
+        }
+    }
+    Some(strip_yaml_comment(item).trim().to_string())
+}
+
+/// 去掉 ` #` 开始的行尾注释
+fn strip_yaml_comment(s: &str) -> &str {
+    s.find(" #").map_or(s, |i| &s[..i])
+}
+
+/// 合并两份按行解析的文件
+fn merge_lines(
+    existing: Vec<Line>,
+    generated: Vec<Line>,
+    retired: impl Fn(&(RuleType, String)) -> bool,
+) -> Vec<Line> {
+    let mut pending: Vec<Line> = generated
+        .into_iter()
+        .filter(|line| matches!(line, Line::Item { rule: Some(_), .. }))
+        .collect();
+
+    // 1. 保留原有的行，去掉已被删除或移走的规则
+    let mut merged = Vec::new();
+    let mut shrunk: Vec<String> = Vec::new();
+    for line in existing {
+        if let Line::Item {
+            key,
+            rule: Some(rule),
+            ..
+        } = &line
+        {
+            let found = pending.iter().position(|p| match p {
+                Line::Item { rule: Some(r), .. } => same_entry(r, rule),
+                _ => false,
+            });
+            match found {
+                Some(i) => {
+                    pending.remove(i);
+                }
+                None if retired(rule) => {
+                    shrunk.extend(key.clone());
+                    continue;
+                }
+                None => {}
+            }
+        }
+        merged.push(line);
+    }
+
+    // 2. 新规则追加到同一个键的最后一个条目之后
+    for line in pending {
+        let Line::Item { key, .. } = &line else {
+            continue;
+        };
+        let Some(key) = key.clone() else {
+            merged.push(line);
+            continue;
+        };
+        let last_item = merged
+            .iter()
+            .rposition(|l| matches!(l, Line::Item { key: Some(k), .. } if *k == key));
+        let key_line = merged
+            .iter()
+            .position(|l| matches!(l, Line::Key { name, .. } if *name == key));
+        match (last_item, key_line) {
+            (Some(i), _) => merged.insert(i + 1, line),
+            (None, Some(i)) => {
+                // `key: []` 改为块列表
+                merged[i] = Line::Key {
+                    name: key.clone(),
+                    text: format!("{}:", key),
+                };
+                merged.insert(i + 1, line);
+            }
+            (None, None) => {
+                merged.push(Line::Key {
+                    name: key.clone(),
+                    text: format!("{}:", key),
+                });
+                merged.push(line);
+            }
+        }
+    }
+
+    // 3. 条目被删光的键写为 `key: []`，避免留下值为 null 的键
+    let empty: Vec<usize> = merged
+        .iter()
+        .enumerate()
+        .filter(|(i, line)| match line {
+            Line::Key { name, .. } if shrunk.contains(name) => !merged[i + 1..]
+                .iter()
+                .take_while(|l| !matches!(l, Line::Key { .. }))
+                .any(|l| match l {
+                    Line::Item { .. } => true,
+                    Line::Raw(text) => text.starts_with(' ') && !text.trim().starts_with('#'),
+                    Line::Key { .. } => false,
+                }),
+            _ => false,
+        })
+        .map(|(i, _)| i)
+        .collect();
+    for i in empty {
+        if let Line::Key { name, .. } = &merged[i] {
+            merged[i] = Line::Key {
+                name: name.clone(),
+                text: format!("{}: []", name),
+            };
+        }
+    }
+    merged
+}
+
+/// 两个条目是否为同一条规则
+fn same_entry(a: &(RuleType, String), b: &(RuleType, String)) -> bool {
+    let rule = |(rule_type, value): &(RuleType, String)| Rule {
+        rule_type: rule_type.clone(),
+        value: value.clone(),
+        policy: RulePolicy::default(),
+    };
+    same_rule(&rule(a), &rule(b))
+}
+
+// ========================================
+// sing-box JSON
+// ========================================
+
+fn parse_json(content: &[u8]) -> Result<Map<String, Value>> {
+    match serde_json::from_slice(content).context("invalid JSON")? {
+        Value::Object(obj) => Ok(obj),
+        _ => bail!("expected a JSON object with a `rules` array"),
+    }
+}
+
+fn json_rules(obj: &Map<String, Value>) -> Result<&Vec<Value>> {
+    obj.get("rules")
+        .and_then(Value::as_array)
+        .context("missing `rules` array")
+}
+
+/// 本工具管理的规则对象：只有一个可识别的键，值为字符串数组
+///
+/// 多个键的对象在 sing-box 中是“与”的关系，无法拆成单条规则，整体原样保留。
+fn managed_values(rule: &Value) -> Option<Vec<(RuleType, String)>> {
+    let obj = rule.as_object()?;
+    if obj.len() != 1 {
+        return None;
+    }
+    let (key, values) = obj.iter().next()?;
+    values
+        .as_array()?
+        .iter()
+        .map(|v| {
+            let value = v.as_str()?;
+            Some((singbox_key(key, value)?, value.to_string()))
+        })
+        .collect()
+}
+
+fn merge_json(
+    existing: &[u8],
+    generated: &[u8],
+    retired: impl Fn(&(RuleType, String)) -> bool,
+) -> Result<Vec<u8>> {
+    let mut doc = parse_json(existing)?;
+    let fresh = parse_json(generated)?;
+    let mut pending: Vec<(String, Value)> = json_rules(&fresh)?
+        .iter()
+        .filter_map(|rule| rule.as_object()?.iter().next())
+        .flat_map(|(key, values)| {
+            values
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(move |v| (key.clone(), v.clone()))
+        })
+        .collect();
+
+    let mut rules = Vec::new();
+    for rule in json_rules(&doc)? {
+        let Some(entries) = managed_values(rule) else {
+            rules.push(rule.clone());
+            continue;
+        };
+        let key = rule.as_object().and_then(|o| o.keys().next()).cloned();
+        let kept: Vec<Value> = entries
+            .iter()
+            .filter(|entry| {
+                let found = pending.iter().position(|(k, v)| {
+                    Some(k) == key.as_ref()
+                        && v.as_str().is_some_and(|v| {
+                            singbox_key(k, v)
+                                .is_some_and(|t| same_entry(&(t, v.to_string()), entry))
+                        })
+                });
+                match found {
+                    Some(i) => {
+                        pending.remove(i);
+                        true
+                    }
+                    None => !retired(entry),
+                }
+            })
+            .map(|(_, value)| Value::String(value.clone()))
+            .collect();
+        if let (Some(key), false) = (key, kept.is_empty()) {
+            let mut obj = Map::new();
+            obj.insert(key, Value::Array(kept));
+            rules.push(Value::Object(obj));
+        }
+    }
+    for (key, value) in pending {
+        let mut obj = Map::new();
+        obj.insert(key, Value::Array(vec![value]));
+        rules.push(Value::Object(obj));
+    }
+
+    if let Some(version) = fresh.get("version") {
+        doc.insert("version".to_string(), version.clone());
+    }
+    doc.insert("rules".to_string(), Value::Array(rules));
+    Ok(render_json(&doc).into_bytes())
+}
+
+/// 输出与生成的文件相同的排版：每条规则一行
+fn render_json(doc: &Map<String, Value>) -> String {
+    let mut fields = Vec::new();
+    for (key, value) in doc {
+        let value = match (key.as_str(), value) {
+            ("rules", Value::Array(rules)) if !rules.is_empty() => {
+                let lines: Vec<String> = rules
+                    .iter()
+                    .map(|rule| format!("    {}", render_rule(rule)))
+                    .collect();
+                format!("[\n{}\n  ]", lines.join(",\n"))
+            }
+            _ => value.to_string(),
+        };
+        fields.push(format!("  {}: {}", Value::String(key.clone()), value));
+    }
+    format!("{{\n{}\n}}", fields.join(",\n"))
+}
+
+/// 单键规则写为 `{ "key": ["a", "b"] }`，其他对象紧凑输出
+fn render_rule(rule: &Value) -> String {
+    match rule.as_object() {
+        Some(obj) if obj.len() == 1 => {
+            let (key, value) = obj.iter().next().expect("one entry");
+            match value.as_array() {
+                Some(values) => {
+                    let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                    format!(
+                        "{{ {}: [{}] }}",
+                        Value::String(key.clone()),
+                        values.join(", ")
+                    )
+                }
+                None => rule.to_string(),
+            }
+        }
+        _ => rule.to_string(),
+    }
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(rule_type: RuleType, value: &str) -> Rule {
+        Rule {
+            rule_type,
+            value: value.to_string(),
+            policy: RulePolicy::Direct,
+        }
+    }
+
+    #[test]
+    fn test_merge_list_keeps_unknown_lines() {
+        let existing = "# proxy-audit generated rules\n\
+                        # my rules\n\
+                        DOMAIN-KEYWORD,google\n\
+                        PROCESS-NAME,Old\n\
+                        GEOIP,CN\n\
+                        PROCESS-NAME,Kept\n\
+                        DOMAIN-SUFFIX,hand.example\n";
+        let generated = "# proxy-audit generated rules\nPROCESS-NAME,Kept\nPROCESS-NAME,New\n";
+        let retired = |r: &Rule| r.value == "Old";
+        let merged = merge(
+            Syntax::List(classical_item),
+            existing.as_bytes(),
+            generated.as_bytes(),
+            RulePolicy::Direct,
+            retired,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "# proxy-audit generated rules\n\
+             # my rules\n\
+             DOMAIN-KEYWORD,google\n\
+             GEOIP,CN\n\
+             PROCESS-NAME,Kept\n\
+             DOMAIN-SUFFIX,hand.example\n\
+             PROCESS-NAME,New\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_yaml() {
+        let existing = "# proxy-audit generated rules\n\
+                        payload:\n  \
+                          - DST-PORT,22\n  \
+                          - PROCESS-NAME,Old # moved later\n\
+                        # trailing comment\n";
+        let generated = "# proxy-audit generated rules\npayload:\n  - IP-CIDR,1.2.3.4/32\n";
+        let merged = merge(
+            Syntax::Yaml(classical_item),
+            existing.as_bytes(),
+            generated.as_bytes(),
+            RulePolicy::Direct,
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "# proxy-audit generated rules\n\
+             payload:\n  \
+               - DST-PORT,22\n  \
+               - IP-CIDR,1.2.3.4/32\n\
+             # trailing comment\n"
+        );
+
+        // 删光后写为 `payload: []`，新规则加入 `key: []`
+        let emptied = merge(
+            Syntax::Yaml(classical_item),
+            b"payload:\n  - PROCESS-NAME,Old\n",
+            b"payload: []\n",
+            RulePolicy::Direct,
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(emptied, b"payload: []\n");
+        let egern = merge(
+            Syntax::Yaml(egern_item),
+            b"domain_set: []\ndomain_keyword_set:\n  - ads\n",
+            b"domain_set:\n  - a.com\nip_cidr_set:\n  - 1.2.3.0/24\n",
+            RulePolicy::Direct,
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(egern).unwrap(),
+            "domain_set:\n  - a.com\ndomain_keyword_set:\n  - ads\nip_cidr_set:\n  - 1.2.3.0/24\n"
+        );
+    }
+
+    #[test]
+    fn test_unparseable_files_are_rejected() {
+        let flow = b"payload: ['DOMAIN,a.com']\n";
+        assert!(merge(
+            Syntax::Yaml(classical_item),
+            flow,
+            b"payload: []\n",
+            RulePolicy::Direct,
+            |_| true
+        )
+        .is_err());
+        assert!(merge(
+            Syntax::Json,
+            b"{ \"rules\": [ ",
+            b"{\"version\": 1, \"rules\": []}",
+            RulePolicy::Direct,
+            |_| true
+        )
+        .is_err());
+        assert!(parse_rules(Syntax::Json, b"not json", RulePolicy::Direct).is_err());
+    }
+
+    #[test]
+    fn test_merge_json() {
+        let existing = r#"{
+  "version": 1,
+  "rules": [
+    { "process_name": ["Old", "Hand"] },
+    { "domain_keyword": ["ads"] },
+    { "ip_cidr": ["1.2.3.4/32"], "port": [443] }
+  ]
+}"#;
+        let generated = r#"{ "version": 2, "rules": [ { "process_path": ["/bin/new"] } ] }"#;
+        let retired = |r: &Rule| r.value == "Old";
+        let merged = merge(
+            Syntax::Json,
+            existing.as_bytes(),
+            generated.as_bytes(),
+            RulePolicy::Direct,
+            retired,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            r#"{
+  "version": 2,
+  "rules": [
+    { "process_name": ["Hand"] },
+    { "domain_keyword": ["ads"] },
+    {"ip_cidr":["1.2.3.4/32"],"port":[443]},
+    { "process_path": ["/bin/new"] }
+  ]
+}"#
+        );
+
+        let rules = parse_rules(Syntax::Json, existing.as_bytes(), RulePolicy::Direct).unwrap();
+        let values: Vec<&str> = rules.iter().map(|r| r.value.as_str()).collect();
+        assert_eq!(values, ["Old", "Hand"]);
+    }
+
+    #[test]
+    fn test_parse_generated_files() {
+        use crate::rule::formatter::{create_formatter, OutputFormat};
+
+        let rules = [
+            Rule {
+                rule_type: RuleType::DomainSuffix,
+                value: "example.com".to_string(),
+                policy: RulePolicy::Proxy,
+            },
+            Rule {
+                rule_type: RuleType::IpCidr6,
+                value: "2001:db8::/32".to_string(),
+                policy: RulePolicy::Proxy,
+            },
+        ];
+        for format in [
+            OutputFormat::Clash,
+            OutputFormat::QuantumultX,
+            OutputFormat::SingBox,
+            OutputFormat::Egern,
+        ] {
+            let formatter = create_formatter(format);
+            let content = formatter.generate_file_content(&rules);
+            let parsed = parse_rules(
+                formatter.syntax(None),
+                content.as_bytes(),
+                RulePolicy::Proxy,
+            )
+            .unwrap();
+            let values: Vec<(RuleType, &str)> = parsed
+                .iter()
+                .map(|r| (r.rule_type.clone(), r.value.as_str()))
+                .collect();
+            assert_eq!(
+                values,
+                [
+                    (RuleType::DomainSuffix, "example.com"),
+                    (RuleType::IpCidr6, "2001:db8::/32")
+                ],
+                "{}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_item_parsers() {
+        assert_eq!(
+            domain_item(Some("payload"), "+.example.com"),
+            Some((RuleType::DomainSuffix, "example.com".to_string()))
+        );
+        assert_eq!(domain_item(Some("payload"), "*.example.com"), None);
+        assert_eq!(
+            ipcidr_item(None, "2001:db8::/32").map(|(t, _)| t),
+            Some(RuleType::IpCidr6)
+        );
+        assert_eq!(classical_item(Some("rules"), "DOMAIN,a.com"), None);
+        assert_eq!(
+            yaml_scalar("'+.example.com' # note").as_deref(),
+            Some("+.example.com")
+        );
+        assert!(same_rule(
+            &rule(RuleType::IpCidr, "1.2.3.4"),
+            &rule(RuleType::IpCidr, "1.2.3.4/32")
+        ));
+        assert!(is_qx_list(b"# comment\nhost-suffix, example.com, proxy\n"));
+        assert!(!is_qx_list(
+            b"DOMAIN-SUFFIX,example.com\nIP-CIDR,1.2.3.0/24\n"
+        ));
+    }
+}
@@ -1,11 +1,17 @@
 //! # 规则格式化器
 //!
 //! 定义规则数据结构和多平台格式化输出。
+//!
+//! 支持的客户端：Clash / mihomo、Surge、Quantumult X、sing-box、
+//! Loon、Shadowrocket、Stash、Egern。
 
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use super::document::{self, Syntax};
+use super::srs;
+
 /// 规则策略
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
@@ -35,7 +41,8 @@ impl Default for RulePolicy {
 }
 
 /// 输出格式
-#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum OutputFormat {
     /// Clash / Clash Meta
     Clash,
@@ -45,6 +52,14 @@ pub enum OutputFormat {
     QuantumultX,
     /// Sing-box
     SingBox,
+    /// Loon
+    Loon,
+    /// Shadowrocket
+    Shadowrocket,
+    /// Stash
+    Stash,
+    /// Egern
+    Egern,
 }
 
 impl fmt::Display for OutputFormat {
@@ -54,14 +69,31 @@ impl fmt::Display for OutputFormat {
             OutputFormat::Surge => write!(f, "surge"),
             OutputFormat::QuantumultX => write!(f, "quantumultx"),
             OutputFormat::SingBox => write!(f, "sing-box"),
+            OutputFormat::Loon => write!(f, "loon"),
+            OutputFormat::Shadowrocket => write!(f, "shadowrocket"),
+            OutputFormat::Stash => write!(f, "stash"),
+            OutputFormat::Egern => write!(f, "egern"),
         }
     }
 }
 
+/// 进程规则的匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MatchBy {
+    /// 按进程名匹配 (PROCESS-NAME)
+    #[default]
+    Name,
+    /// 按可执行文件完整路径匹配 (PROCESS-PATH)，避免多个 App 共用 "Electron" 等进程名时冲突
+    Path,
+    /// 按进程当前连接的目的地址匹配 (IP-CIDR)，适用于不支持进程规则的客户端
+    Destination,
+}
+
 /// 单条分流规则
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rule {
     /// 规则类型 (IP-CIDR, DOMAIN-SUFFIX 等)
+    #[serde(rename = "type")]
     pub rule_type: RuleType,
     /// 规则值 (IP 地址或域名)
     pub value: String,
@@ -69,22 +101,49 @@ pub struct Rule {
     pub policy: RulePolicy,
 }
 
-/// 规则类型
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 规则类型（序列化为 Clash 风格的名称，如 "IP-CIDR"、"PROCESS-NAME"）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum RuleType {
     /// IP-CIDR 规则
     IpCidr,
+    /// IPv6 IP-CIDR 规则
+    IpCidr6,
     /// 域名后缀规则
     DomainSuffix,
     /// 精确域名规则
     Domain,
     /// 进程名规则
     ProcessName,
+    /// 进程路径规则
+    ProcessPath,
+}
+
+impl fmt::Display for RuleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleType::IpCidr => write!(f, "IP-CIDR"),
+            RuleType::IpCidr6 => write!(f, "IP-CIDR6"),
+            RuleType::DomainSuffix => write!(f, "DOMAIN-SUFFIX"),
+            RuleType::Domain => write!(f, "DOMAIN"),
+            RuleType::ProcessName => write!(f, "PROCESS-NAME"),
+            RuleType::ProcessPath => write!(f, "PROCESS-PATH"),
+        }
+    }
+}
+
+/// 一个策略对应的单个规则文件
+#[derive(Debug, Clone)]
+pub struct RuleFile {
+    /// 文件名后缀，如 `rules-direct-domain.yaml` 中的 "domain"（None 为主文件）
+    pub suffix: Option<&'static str>,
+    /// 文件内容（sing-box .srs 为二进制）
+    pub content: Vec<u8>,
 }
 
 /// 规则格式化 trait
 pub trait Formatter {
-    /// 格式化单条规则
+    /// 格式化单条规则（该格式无法表达的规则类型返回 None）
     fn format_rule(&self, rule: &Rule) -> Option<String>;
 
     /// 格式化多条规则
@@ -96,12 +155,30 @@ pub trait Formatter {
             .join("\n")
     }
 
-    /// 获取规则文件扩展名
+    /// 获取规则文件扩展名（多个客户端共用 .list / .yaml 时带上客户端前缀以免冲突）
     fn file_extension(&self) -> &'static str;
 
     /// 生成规则文件内容（包含必要的文件头）
     fn generate_file_content(&self, rules: &[Rule]) -> String;
 
+    /// 生成一个策略对应的全部规则文件，默认只有一个文件
+    fn generate_files(&self, rules: &[Rule]) -> Vec<RuleFile> {
+        vec![RuleFile {
+            suffix: None,
+            content: self.generate_file_content(rules).into_bytes(),
+        }]
+    }
+
+    /// 规则文件的语法（用于无损读取和合并已有文件），默认为 Surge 风格的 .list
+    fn syntax(&self, _suffix: Option<&str>) -> Syntax {
+        Syntax::List(document::classical_item)
+    }
+
+    /// 引导配置中 rule-provider 的名称（用于 `--reload` 刷新），默认没有
+    fn provider_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     /// 生成引导配置（用于 init 命令）
     fn generate_guide(&self, rule_file_path: &str) -> String;
 }
@@ -110,84 +187,242 @@ pub trait Formatter {
 // Clash 格式化器
 // ========================================
 
-pub struct ClashFormatter;
+/// Clash rule-provider 的文件格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ClashProviderFormat {
+    /// YAML `payload:` 列表
+    #[default]
+    Yaml,
+    /// 纯文本，每行一条（mihomo 加载大规则集更快）
+    Text,
+}
 
-impl Formatter for ClashFormatter {
-    fn format_rule(&self, rule: &Rule) -> Option<String> {
-        let type_str = match rule.rule_type {
-            RuleType::IpCidr => "IP-CIDR",
-            RuleType::DomainSuffix => "DOMAIN-SUFFIX",
-            RuleType::Domain => "DOMAIN",
-            RuleType::ProcessName => "PROCESS-NAME",
-        };
+/// Clash rule-provider 的拆分方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ClashLayout {
+    /// 每个策略一个 classical provider
+    #[default]
+    Classical,
+    /// 每个策略拆分为 domain、ipcidr、classical 三个 provider
+    Split,
+}
 
-        // IP-CIDR 需要加 /32
-        let value = if matches!(rule.rule_type, RuleType::IpCidr) && !rule.value.contains('/') {
-            format!("{}/32", rule.value)
-        } else {
-            rule.value.clone()
-        };
+/// Clash 输出选项（保存在 rules.toml 的 `[clash]` 段）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClashOptions {
+    /// provider 文件格式
+    #[serde(default)]
+    pub format: ClashProviderFormat,
+    /// provider 拆分方式
+    #[serde(default)]
+    pub layout: ClashLayout,
+}
+
+pub struct ClashFormatter {
+    /// 输出选项
+    pub options: ClashOptions,
+}
+
+impl ClashFormatter {
+    /// 生成 provider 文件内容
+    fn payload(&self, items: &[String]) -> String {
+        let mut content = String::from("# proxy-audit generated rules\n");
+        match self.options.format {
+            ClashProviderFormat::Text => {
+                for item in items {
+                    content.push_str(item);
+                    content.push('\n');
+                }
+            }
+            ClashProviderFormat::Yaml if items.is_empty() => content.push_str("payload: []\n"),
+            ClashProviderFormat::Yaml => {
+                content.push_str("payload:\n");
+                for item in items {
+                    content.push_str(&format!("  - '{}'\n", item));
+                }
+            }
+        }
+        content
+    }
 
+    /// classical 规则行（不带列表前缀）
+    fn classical_line(rule: &Rule) -> String {
         // V2 Update: 不再输出策略 (Policy)，由外部 Rule Provider 决定
+        // IP-CIDR 需要加 /32 (IPv6 为 /128)
+        format!("{},{}", rule.rule_type, rule_value(rule))
+    }
+
+    /// 当前拆分方式下的 (behavior, 文件名后缀) 列表
+    fn providers(&self) -> Vec<(&'static str, Option<&'static str>)> {
+        match self.options.layout {
+            ClashLayout::Classical => vec![("classical", None)],
+            ClashLayout::Split => vec![
+                ("domain", Some("domain")),
+                ("ipcidr", Some("ipcidr")),
+                ("classical", None),
+            ],
+        }
+    }
+}
+
+impl Formatter for ClashFormatter {
+    fn format_rule(&self, rule: &Rule) -> Option<String> {
+        // PROCESS-PATH 仅 Clash Meta / mihomo 支持
         // Example: "  - PROCESS-NAME,Telegram"
-        Some(format!("  - {},{}", type_str, value))
+        let line = Self::classical_line(rule);
+        match self.options.format {
+            ClashProviderFormat::Yaml => Some(format!("  - {}", line)),
+            ClashProviderFormat::Text => Some(line),
+        }
     }
 
     fn file_extension(&self) -> &'static str {
-        "yaml"
+        match self.options.format {
+            ClashProviderFormat::Yaml => "yaml",
+            ClashProviderFormat::Text => "txt",
+        }
     }
 
     fn generate_file_content(&self, rules: &[Rule]) -> String {
-        if rules.is_empty() {
-            return String::from("# proxy-audit generated rules\npayload: []\n");
+        let lines: Vec<String> = rules.iter().map(Self::classical_line).collect();
+        match self.options.format {
+            // classical 规则行不加引号，与旧版本生成的文件保持一致
+            ClashProviderFormat::Yaml if !lines.is_empty() => {
+                let mut content = String::from("# proxy-audit generated rules\npayload:\n");
+                for line in lines {
+                    content.push_str(&format!("  - {}\n", line));
+                }
+                content
+            }
+            _ => self.payload(&lines),
+        }
+    }
+
+    fn generate_files(&self, rules: &[Rule]) -> Vec<RuleFile> {
+        if self.options.layout == ClashLayout::Classical {
+            return vec![RuleFile {
+                suffix: None,
+                content: self.generate_file_content(rules).into_bytes(),
+            }];
         }
 
-        let mut content = String::from("# proxy-audit generated rules\npayload:\n");
+        // domain: 精确域名原样写入，后缀写为 +.example.com；ipcidr: 只写网段
+        let mut domains = Vec::new();
+        let mut cidrs = Vec::new();
+        let mut classical = Vec::new();
         for rule in rules {
-            if let Some(line) = self.format_rule(rule) {
-                content.push_str(&line);
-                content.push('\n');
+            match rule.rule_type {
+                RuleType::Domain => domains.push(rule.value.clone()),
+                RuleType::DomainSuffix => domains.push(format!("+.{}", rule.value)),
+                RuleType::IpCidr | RuleType::IpCidr6 => cidrs.push(rule_value(rule)),
+                RuleType::ProcessName | RuleType::ProcessPath => classical.push(rule.clone()),
             }
         }
-        content
+        vec![
+            RuleFile {
+                suffix: Some("domain"),
+                content: self.payload(&domains).into_bytes(),
+            },
+            RuleFile {
+                suffix: Some("ipcidr"),
+                content: self.payload(&cidrs).into_bytes(),
+            },
+            RuleFile {
+                suffix: None,
+                content: self.generate_file_content(&classical).into_bytes(),
+            },
+        ]
+    }
+
+    fn syntax(&self, suffix: Option<&str>) -> Syntax {
+        let parser: document::ItemParser = match suffix {
+            Some("domain") => document::domain_item,
+            Some("ipcidr") => document::ipcidr_item,
+            _ => document::classical_item,
+        };
+        match self.options.format {
+            ClashProviderFormat::Yaml => Syntax::Yaml(parser),
+            ClashProviderFormat::Text => Syntax::List(parser),
+        }
+    }
+
+    fn provider_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for policy in ["direct", "proxy", "reject"] {
+            for (_, suffix) in self.providers() {
+                names.push(match suffix {
+                    Some(s) => format!("proxy-audit-{}-{}", policy, s),
+                    None => format!("proxy-audit-{}", policy),
+                });
+            }
+        }
+        names
     }
 
     fn generate_guide(&self, config_dir: &str) -> String {
+        let format = match self.options.format {
+            ClashProviderFormat::Yaml => "yaml",
+            ClashProviderFormat::Text => "text",
+        };
+
+        let mut providers = String::new();
+        let mut rules = String::new();
+        for policy in ["direct", "reject", "proxy"] {
+            let target = match policy {
+                "direct" => "DIRECT",
+                "reject" => "REJECT",
+                _ => "PROXY",
+            };
+            if policy == "proxy" {
+                rules.push_str("  # 注意: 请将 'PROXY' 替换为您配置文件中实际的 代理策略组名称 (例如 'Proxy', '节点选择', '🚀 节点选择' 等)\n");
+            }
+            for (behavior, suffix) in self.providers() {
+                let (name, file) = match suffix {
+                    Some(s) => (
+                        format!("proxy-audit-{}-{}", policy, s),
+                        format!("rules-{}-{}.{}", policy, s, self.file_extension()),
+                    ),
+                    None => (
+                        format!("proxy-audit-{}", policy),
+                        format!("rules-{}.{}", policy, self.file_extension()),
+                    ),
+                };
+                providers.push_str(&format!(
+                    "  {}:\n    type: file\n    behavior: {}\n    format: {}\n    path: \"{}/{}\"\n",
+                    name, behavior, format, config_dir, file
+                ));
+                // ipcidr 规则集不需要解析域名
+                let no_resolve = if behavior == "ipcidr" {
+                    ",no-resolve"
+                } else {
+                    ""
+                };
+                rules.push_str(&format!("  - RULE-SET,{},{}{}\n", name, target, no_resolve));
+            }
+        }
+
         format!(
             r#"# ============================================================
 # Clash 配置指南 (Policy-Based)
 # ============================================================
 # 请将以下内容添加到您的 Clash 配置文件中：
+# (format: text 与 behavior: domain / ipcidr 需要 Clash Meta / mihomo)
 
 # 1. 在 rule-providers 段添加：
 rule-providers:
-  proxy-audit-direct:
-    type: file
-    behavior: classical
-    path: "{}/rules-direct.yaml"
-  proxy-audit-proxy:
-    type: file
-    behavior: classical
-    path: "{}/rules-proxy.yaml"
-  proxy-audit-reject:
-    type: file
-    behavior: classical
-    path: "{}/rules-reject.yaml"
-
+{}
 # 2. 在 rules 段添加（放在其他规则之前）：
 rules:
-  - RULE-SET,proxy-audit-direct,DIRECT
-  - RULE-SET,proxy-audit-reject,REJECT
-  # 注意: 请将 'PROXY' 替换为您配置文件中实际的 代理策略组名称 (例如 'Proxy', '节点选择', '🚀 节点选择' 等)
-  - RULE-SET,proxy-audit-proxy,PROXY
-  # ... 您的其他规则 ...
+{}  # ... 您的其他规则 ...
 
 # ============================================================
 # 配置完成后，重载 Clash 配置即可生效。
 # 之后使用 `proxy-audit rule add` 添加规则会自动更新对应的文件。
 # ============================================================
 "#,
-            config_dir, config_dir, config_dir
+            providers, rules
         )
     }
 }
@@ -202,16 +437,14 @@ impl Formatter for SurgeFormatter {
     fn format_rule(&self, rule: &Rule) -> Option<String> {
         let type_str = match rule.rule_type {
             RuleType::IpCidr => "IP-CIDR",
+            RuleType::IpCidr6 => "IP-CIDR6",
             RuleType::DomainSuffix => "DOMAIN-SUFFIX",
             RuleType::Domain => "DOMAIN",
             RuleType::ProcessName => "PROCESS-NAME",
+            RuleType::ProcessPath => return None,
         };
 
-        let value = if matches!(rule.rule_type, RuleType::IpCidr) && !rule.value.contains('/') {
-            format!("{}/32", rule.value)
-        } else {
-            rule.value.clone()
-        };
+        let value = rule_value(rule);
 
         // V2 Update: 不再输出策略
         Some(format!("{},{}", type_str, value))
@@ -222,14 +455,7 @@ impl Formatter for SurgeFormatter {
     }
 
     fn generate_file_content(&self, rules: &[Rule]) -> String {
-        let mut content = String::from("# proxy-audit generated rules\n");
-        for rule in rules {
-            if let Some(line) = self.format_rule(rule) {
-                content.push_str(&line);
-                content.push('\n');
-            }
-        }
-        content
+        list_file_content(self, rules)
     }
 
     fn generate_guide(&self, config_dir: &str) -> String {
@@ -260,41 +486,31 @@ pub struct QuantumultXFormatter;
 
 impl Formatter for QuantumultXFormatter {
     fn format_rule(&self, rule: &Rule) -> Option<String> {
-        // QX 不支持进程名规则，跳过
-        if matches!(rule.rule_type, RuleType::ProcessName) {
-            return None;
-        }
-
+        // QX 不支持进程规则，跳过
         let type_str = match rule.rule_type {
             RuleType::IpCidr => "ip-cidr",
+            RuleType::IpCidr6 => "ip6-cidr",
             RuleType::DomainSuffix => "host-suffix",
             RuleType::Domain => "host",
-            RuleType::ProcessName => return None,
+            RuleType::ProcessName | RuleType::ProcessPath => return None,
         };
 
-        let value = if matches!(rule.rule_type, RuleType::IpCidr) && !rule.value.contains('/') {
-            format!("{}/32", rule.value)
-        } else {
-            rule.value.clone()
-        };
+        let value = rule_value(rule);
 
         // QX 支持本地 filter 引用，但也推荐不带策略
         Some(format!("{}, {}", type_str, value))
     }
 
     fn file_extension(&self) -> &'static str {
-        "list"
+        "qx.list"
     }
 
     fn generate_file_content(&self, rules: &[Rule]) -> String {
-        let mut content = String::from("# proxy-audit generated rules\n");
-        for rule in rules {
-            if let Some(line) = self.format_rule(rule) {
-                content.push_str(&line);
-                content.push('\n');
-            }
-        }
-        content
+        list_file_content(self, rules)
+    }
+
+    fn syntax(&self, _suffix: Option<&str>) -> Syntax {
+        Syntax::List(document::qx_item)
     }
 
     fn generate_guide(&self, rule_file_path: &str) -> String {
@@ -303,9 +519,9 @@ impl Formatter for QuantumultXFormatter {
 # Quantumult X 配置指南
 # ============================================================
 # 建议手动引用以下文件：
-# DIRECT: {}/rules-direct.list
-# PROXY:  {}/rules-proxy.list
-# REJECT: {}/rules-reject.list
+# DIRECT: {}/rules-direct.qx.list
+# PROXY:  {}/rules-proxy.qx.list
+# REJECT: {}/rules-reject.qx.list
 # ============================================================
 "#,
             rule_file_path, rule_file_path, rule_file_path
@@ -317,23 +533,59 @@ impl Formatter for QuantumultXFormatter {
 // Sing-box 格式化器
 // ========================================
 
-pub struct SingBoxFormatter;
+/// sing-box 规则集的文件格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SingBoxRuleSetFormat {
+    /// JSON 源文件 (.json)
+    #[default]
+    Source,
+    /// 编译后的二进制规则集 (.srs)
+    Binary,
+}
+
+/// sing-box 输出选项（保存在 rules.toml 的 `[sing-box]` 段）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SingBoxOptions {
+    /// 规则集文件格式
+    pub format: SingBoxRuleSetFormat,
+    /// 规则集版本 (1-3)，分别需要 sing-box 1.8 / 1.10 / 1.11 以上
+    pub version: u8,
+}
+
+impl Default for SingBoxOptions {
+    fn default() -> Self {
+        Self {
+            format: SingBoxRuleSetFormat::Source,
+            version: 1,
+        }
+    }
+}
+
+pub struct SingBoxFormatter {
+    /// 输出选项
+    pub options: SingBoxOptions,
+}
+
+impl SingBoxFormatter {
+    fn format_name(&self) -> &'static str {
+        match self.options.format {
+            SingBoxRuleSetFormat::Source => "source",
+            SingBoxRuleSetFormat::Binary => "binary",
+        }
+    }
+}
 
 impl Formatter for SingBoxFormatter {
     fn format_rule(&self, rule: &Rule) -> Option<String> {
         // Sing-box 使用 JSON 格式，这里返回单条规则的 JSON
         let (key, value) = match rule.rule_type {
-            RuleType::IpCidr => {
-                let v = if !rule.value.contains('/') {
-                    format!("{}/32", rule.value)
-                } else {
-                    rule.value.clone()
-                };
-                ("ip_cidr", v)
-            }
+            RuleType::IpCidr | RuleType::IpCidr6 => ("ip_cidr", rule_value(rule)),
             RuleType::DomainSuffix => ("domain_suffix", rule.value.clone()),
             RuleType::Domain => ("domain", rule.value.clone()),
             RuleType::ProcessName => ("process_name", rule.value.clone()),
+            RuleType::ProcessPath => ("process_path", rule.value.clone()),
         };
 
         // V2 Update: 不再输出 outbound
@@ -341,48 +593,538 @@ impl Formatter for SingBoxFormatter {
     }
 
     fn file_extension(&self) -> &'static str {
-        "json"
+        match self.options.format {
+            SingBoxRuleSetFormat::Source => "json",
+            SingBoxRuleSetFormat::Binary => "srs",
+        }
     }
 
     fn generate_file_content(&self, rules: &[Rule]) -> String {
         let rules_json: Vec<String> = rules.iter().filter_map(|r| self.format_rule(r)).collect();
         format!(
             r#"{{
-  "version": 1,
+  "version": {},
   "rules": [
 {}
   ]
 }}"#,
+            self.options.version,
             rules_json.join(",\n")
         )
     }
 
+    fn generate_files(&self, rules: &[Rule]) -> Vec<RuleFile> {
+        let content = match self.options.format {
+            SingBoxRuleSetFormat::Source => self.generate_file_content(rules).into_bytes(),
+            SingBoxRuleSetFormat::Binary => srs::encode(rules, self.options.version),
+        };
+        vec![RuleFile {
+            suffix: None,
+            content,
+        }]
+    }
+
+    fn syntax(&self, _suffix: Option<&str>) -> Syntax {
+        match self.options.format {
+            SingBoxRuleSetFormat::Source => Syntax::Json,
+            SingBoxRuleSetFormat::Binary => Syntax::Binary,
+        }
+    }
+
     fn generate_guide(&self, config_dir: &str) -> String {
+        let (ext, format) = (self.file_extension(), self.format_name());
         format!(
             r#"// ============================================================
 // Sing-box 配置指南
 // ============================================================
 // 1. route.rule_set:
-{{ "type": "local", "tag": "pa-direct", "path": "{}/rules-direct.json", "format": "source" }},
-{{ "type": "local", "tag": "pa-proxy",  "path": "{}/rules-proxy.json",  "format": "source" }},
-{{ "type": "local", "tag": "pa-reject", "path": "{}/rules-reject.json", "format": "source" }}
+{{ "type": "local", "tag": "pa-direct", "path": "{config_dir}/rules-direct.{ext}", "format": "{format}" }},
+{{ "type": "local", "tag": "pa-proxy",  "path": "{config_dir}/rules-proxy.{ext}",  "format": "{format}" }},
+{{ "type": "local", "tag": "pa-reject", "path": "{config_dir}/rules-reject.{ext}", "format": "{format}" }}
 
 // 2. route.rules:
 {{ "rule_set": "pa-direct", "outbound": "direct" }},
 {{ "rule_set": "pa-proxy",  "outbound": "proxy" }},
 {{ "rule_set": "pa-reject", "outbound": "block" }}
+"#
+        )
+    }
+}
+
+// ========================================
+// Loon 格式化器
+// ========================================
+
+/// Loon 规则集 (.list)，语法与 Surge 相同，但不支持进程规则
+pub struct LoonFormatter;
+
+impl Formatter for LoonFormatter {
+    fn format_rule(&self, rule: &Rule) -> Option<String> {
+        let type_str = match rule.rule_type {
+            RuleType::IpCidr => "IP-CIDR",
+            RuleType::IpCidr6 => "IP-CIDR6",
+            RuleType::DomainSuffix => "DOMAIN-SUFFIX",
+            RuleType::Domain => "DOMAIN",
+            RuleType::ProcessName | RuleType::ProcessPath => return None,
+        };
+        Some(format!("{},{}", type_str, rule_value(rule)))
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "loon.list"
+    }
+
+    fn generate_file_content(&self, rules: &[Rule]) -> String {
+        list_file_content(self, rules)
+    }
+
+    fn generate_guide(&self, config_dir: &str) -> String {
+        format!(
+            r#"# ============================================================
+# Loon 配置指南
+# ============================================================
+# Loon 只能引用远程规则集，请通过 HTTP 或 iCloud 共享以下文件：
+# DIRECT: {}/rules-direct.loon.list
+# PROXY:  {}/rules-proxy.loon.list
+# REJECT: {}/rules-reject.loon.list
+
+[Remote Rule]
+https://example.com/rules-direct.loon.list, policy=DIRECT, tag=proxy-audit-direct, enabled=true
+https://example.com/rules-proxy.loon.list, policy=PROXY, tag=proxy-audit-proxy, enabled=true
+https://example.com/rules-reject.loon.list, policy=REJECT, tag=proxy-audit-reject, enabled=true
+
+# 注意: Loon 不支持进程规则，请使用 `rule add --by destination` 生成 IP-CIDR 规则。
+# ============================================================
 "#,
             config_dir, config_dir, config_dir
         )
     }
 }
 
-/// 根据格式类型创建格式化器
+// ========================================
+// Shadowrocket 格式化器
+// ========================================
+
+/// Shadowrocket 规则集 (.list)，兼容 Surge 语法，不支持进程规则
+pub struct ShadowrocketFormatter;
+
+impl Formatter for ShadowrocketFormatter {
+    fn format_rule(&self, rule: &Rule) -> Option<String> {
+        let type_str = match rule.rule_type {
+            RuleType::IpCidr => "IP-CIDR",
+            RuleType::IpCidr6 => "IP-CIDR6",
+            RuleType::DomainSuffix => "DOMAIN-SUFFIX",
+            RuleType::Domain => "DOMAIN",
+            RuleType::ProcessName | RuleType::ProcessPath => return None,
+        };
+        Some(format!("{},{}", type_str, rule_value(rule)))
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "shadowrocket.list"
+    }
+
+    fn generate_file_content(&self, rules: &[Rule]) -> String {
+        list_file_content(self, rules)
+    }
+
+    fn generate_guide(&self, config_dir: &str) -> String {
+        format!(
+            r#"# ============================================================
+# Shadowrocket 配置指南
+# ============================================================
+# 请通过 HTTP 或 iCloud 共享以下文件，并在配置文件的 [Rule] 段引用：
+# DIRECT: {}/rules-direct.shadowrocket.list
+# PROXY:  {}/rules-proxy.shadowrocket.list
+# REJECT: {}/rules-reject.shadowrocket.list
+
+[Rule]
+RULE-SET,https://example.com/rules-direct.shadowrocket.list,DIRECT
+RULE-SET,https://example.com/rules-proxy.shadowrocket.list,PROXY
+RULE-SET,https://example.com/rules-reject.shadowrocket.list,REJECT
+
+# 注意: Shadowrocket 不支持进程规则，请使用 `rule add --by destination` 生成 IP-CIDR 规则。
+# ============================================================
+"#,
+            config_dir, config_dir, config_dir
+        )
+    }
+}
+
+// ========================================
+// Stash 格式化器
+// ========================================
+
+/// Stash 规则集，与 Clash 的 classical rule-provider 相同 (YAML payload)
+///
+/// macOS 版支持 PROCESS-NAME，不支持 PROCESS-PATH。
+pub struct StashFormatter;
+
+impl Formatter for StashFormatter {
+    fn format_rule(&self, rule: &Rule) -> Option<String> {
+        let type_str = match rule.rule_type {
+            RuleType::IpCidr => "IP-CIDR",
+            RuleType::IpCidr6 => "IP-CIDR6",
+            RuleType::DomainSuffix => "DOMAIN-SUFFIX",
+            RuleType::Domain => "DOMAIN",
+            RuleType::ProcessName => "PROCESS-NAME",
+            RuleType::ProcessPath => return None,
+        };
+        Some(format!("  - {},{}", type_str, rule_value(rule)))
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "stash.yaml"
+    }
+
+    fn generate_file_content(&self, rules: &[Rule]) -> String {
+        let lines: Vec<String> = rules.iter().filter_map(|r| self.format_rule(r)).collect();
+        if lines.is_empty() {
+            return String::from("# proxy-audit generated rules\npayload: []\n");
+        }
+        format!(
+            "# proxy-audit generated rules\npayload:\n{}\n",
+            lines.join("\n")
+        )
+    }
+
+    fn syntax(&self, _suffix: Option<&str>) -> Syntax {
+        Syntax::Yaml(document::classical_item)
+    }
+
+    fn generate_guide(&self, config_dir: &str) -> String {
+        format!(
+            r#"# ============================================================
+# Stash 配置指南
+# ============================================================
+# 请将以下内容添加到您的 Stash 配置文件中：
+
+rule-providers:
+  proxy-audit-direct:
+    behavior: classical
+    path: "{}/rules-direct.stash.yaml"
+  proxy-audit-proxy:
+    behavior: classical
+    path: "{}/rules-proxy.stash.yaml"
+  proxy-audit-reject:
+    behavior: classical
+    path: "{}/rules-reject.stash.yaml"
+
+rules:
+  - RULE-SET,proxy-audit-direct,DIRECT
+  - RULE-SET,proxy-audit-reject,REJECT
+  # 注意: 请将 'PROXY' 替换为您配置文件中实际的代理策略组名称
+  - RULE-SET,proxy-audit-proxy,PROXY
+
+# ============================================================
+"#,
+            config_dir, config_dir, config_dir
+        )
+    }
+}
+
+// ========================================
+// Egern 格式化器
+// ========================================
+
+/// Egern 规则集 (YAML)，按类型分组为 `domain_set`、`ip_cidr_set` 等列表，不支持进程规则
+pub struct EgernFormatter;
+
+impl EgernFormatter {
+    /// 规则类型对应的 Egern 键名
+    fn key(rule_type: &RuleType) -> Option<&'static str> {
+        match rule_type {
+            RuleType::Domain => Some("domain_set"),
+            RuleType::DomainSuffix => Some("domain_suffix_set"),
+            RuleType::IpCidr => Some("ip_cidr_set"),
+            RuleType::IpCidr6 => Some("ip_cidr6_set"),
+            RuleType::ProcessName | RuleType::ProcessPath => None,
+        }
+    }
+}
+
+impl Formatter for EgernFormatter {
+    fn format_rule(&self, rule: &Rule) -> Option<String> {
+        let key = Self::key(&rule.rule_type)?;
+        Some(format!("{}: [\"{}\"]", key, rule_value(rule)))
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "egern.yaml"
+    }
+
+    fn generate_file_content(&self, rules: &[Rule]) -> String {
+        let mut content = String::from("# proxy-audit generated rules\n");
+        let mut written = false;
+        for key in EGERN_KEYS {
+            let values: Vec<String> = rules
+                .iter()
+                .filter(|r| Self::key(&r.rule_type) == Some(key))
+                .map(rule_value)
+                .collect();
+            if values.is_empty() {
+                continue;
+            }
+            content.push_str(&format!("{}:\n", key));
+            for value in values {
+                content.push_str(&format!("  - {}\n", value));
+            }
+            written = true;
+        }
+        if !written {
+            content.push_str("domain_set: []\n");
+        }
+        content
+    }
+
+    fn syntax(&self, _suffix: Option<&str>) -> Syntax {
+        Syntax::Yaml(document::egern_item)
+    }
+
+    fn generate_guide(&self, config_dir: &str) -> String {
+        format!(
+            r#"# ============================================================
+# Egern 配置指南
+# ============================================================
+# 请通过 HTTP 或 iCloud 共享以下文件，并在配置文件的 rules 段引用：
+# DIRECT: {}/rules-direct.egern.yaml
+# PROXY:  {}/rules-proxy.egern.yaml
+# REJECT: {}/rules-reject.egern.yaml
+
+rules:
+  - rule_set:
+      match: https://example.com/rules-direct.egern.yaml
+      policy: DIRECT
+  - rule_set:
+      match: https://example.com/rules-reject.egern.yaml
+      policy: REJECT
+  - rule_set:
+      match: https://example.com/rules-proxy.egern.yaml
+      policy: PROXY
+
+# 注意: Egern 不支持进程规则，请使用 `rule add --by destination` 生成 IP-CIDR 规则。
+# ============================================================
+"#,
+            config_dir, config_dir, config_dir
+        )
+    }
+}
+
+/// Egern 规则集中本工具使用的键，按输出顺序排列
+pub const EGERN_KEYS: [&str; 4] = [
+    "domain_set",
+    "domain_suffix_set",
+    "ip_cidr_set",
+    "ip_cidr6_set",
+];
+
+/// 每行一条规则的 .list 文件内容（Surge、Quantumult X 及 Surge 系客户端共用）
+fn list_file_content(formatter: &dyn Formatter, rules: &[Rule]) -> String {
+    let mut content = String::from("# proxy-audit generated rules\n");
+    for rule in rules {
+        if let Some(line) = formatter.format_rule(rule) {
+            content.push_str(&line);
+            content.push('\n');
+        }
+    }
+    content
+}
+
+/// 规则值，单个 IP 补全为 /32 或 /128 网段
+fn rule_value(rule: &Rule) -> String {
+    match rule.rule_type {
+        RuleType::IpCidr if !rule.value.contains('/') => format!("{}/32", rule.value),
+        RuleType::IpCidr6 if !rule.value.contains('/') => format!("{}/128", rule.value),
+        _ => rule.value.clone(),
+    }
+}
+
+/// 根据格式类型创建格式化器（Clash、sing-box 使用默认选项）
 pub fn create_formatter(format: OutputFormat) -> Box<dyn Formatter> {
+    create_formatter_with(format, ClashOptions::default(), SingBoxOptions::default())
+}
+
+/// 根据格式类型和 Clash / sing-box 输出选项创建格式化器
+pub fn create_formatter_with(
+    format: OutputFormat,
+    clash: ClashOptions,
+    singbox: SingBoxOptions,
+) -> Box<dyn Formatter> {
     match format {
-        OutputFormat::Clash => Box::new(ClashFormatter),
+        OutputFormat::Clash => Box::new(ClashFormatter { options: clash }),
         OutputFormat::Surge => Box::new(SurgeFormatter),
         OutputFormat::QuantumultX => Box::new(QuantumultXFormatter),
-        OutputFormat::SingBox => Box::new(SingBoxFormatter),
+        OutputFormat::SingBox => Box::new(SingBoxFormatter { options: singbox }),
+        OutputFormat::Loon => Box::new(LoonFormatter),
+        OutputFormat::Shadowrocket => Box::new(ShadowrocketFormatter),
+        OutputFormat::Stash => Box::new(StashFormatter),
+        OutputFormat::Egern => Box::new(EgernFormatter),
+    }
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_path_rule() {
+        let rule = Rule {
+            rule_type: RuleType::ProcessPath,
+            value: "/Applications/Slack.app/Contents/MacOS/Slack".to_string(),
+            policy: RulePolicy::Proxy,
+        };
+        assert_eq!(
+            ClashFormatter {
+                options: ClashOptions::default()
+            }
+            .format_rule(&rule)
+            .as_deref(),
+            Some("  - PROCESS-PATH,/Applications/Slack.app/Contents/MacOS/Slack")
+        );
+        assert_eq!(
+            SingBoxFormatter {
+                options: SingBoxOptions::default()
+            }
+            .format_rule(&rule)
+            .as_deref(),
+            Some(r#"    { "process_path": ["/Applications/Slack.app/Contents/MacOS/Slack"] }"#)
+        );
+        assert_eq!(SurgeFormatter.format_rule(&rule), None);
+        assert_eq!(QuantumultXFormatter.format_rule(&rule), None);
+    }
+
+    #[test]
+    fn test_ip_cidr6_rule() {
+        let rule = Rule {
+            rule_type: RuleType::IpCidr6,
+            value: "2001:67c:4e8::1".to_string(),
+            policy: RulePolicy::Proxy,
+        };
+        assert_eq!(
+            SurgeFormatter.format_rule(&rule).as_deref(),
+            Some("IP-CIDR6,2001:67c:4e8::1/128")
+        );
+        assert_eq!(
+            QuantumultXFormatter.format_rule(&rule).as_deref(),
+            Some("ip6-cidr, 2001:67c:4e8::1/128")
+        );
+        assert_eq!(
+            SingBoxFormatter {
+                options: SingBoxOptions::default()
+            }
+            .format_rule(&rule)
+            .as_deref(),
+            Some(r#"    { "ip_cidr": ["2001:67c:4e8::1/128"] }"#)
+        );
+    }
+
+    #[test]
+    fn test_new_client_formats() {
+        let rules = [
+            Rule {
+                rule_type: RuleType::ProcessName,
+                value: "Telegram".to_string(),
+                policy: RulePolicy::Proxy,
+            },
+            Rule {
+                rule_type: RuleType::IpCidr,
+                value: "149.154.160.0/20".to_string(),
+                policy: RulePolicy::Proxy,
+            },
+        ];
+
+        // 不支持进程规则的客户端跳过 PROCESS-NAME
+        assert_eq!(
+            LoonFormatter.generate_file_content(&rules),
+            "# proxy-audit generated rules\nIP-CIDR,149.154.160.0/20\n"
+        );
+        assert_eq!(ShadowrocketFormatter.format_rule(&rules[0]), None);
+        assert_eq!(
+            StashFormatter.generate_file_content(&rules),
+            "# proxy-audit generated rules\npayload:\n  - PROCESS-NAME,Telegram\n  - IP-CIDR,149.154.160.0/20\n"
+        );
+        assert_eq!(
+            EgernFormatter.generate_file_content(&rules),
+            "# proxy-audit generated rules\nip_cidr_set:\n  - 149.154.160.0/20\n"
+        );
+        assert_eq!(
+            EgernFormatter.generate_file_content(&[]),
+            "# proxy-audit generated rules\ndomain_set: []\n"
+        );
+    }
+
+    #[test]
+    fn test_clash_split_text_providers() {
+        let formatter = ClashFormatter {
+            options: ClashOptions {
+                format: ClashProviderFormat::Text,
+                layout: ClashLayout::Split,
+            },
+        };
+        let rule = |rule_type, value: &str| Rule {
+            rule_type,
+            value: value.to_string(),
+            policy: RulePolicy::Direct,
+        };
+        let files = formatter.generate_files(&[
+            rule(RuleType::DomainSuffix, "apple.com"),
+            rule(RuleType::Domain, "example.com"),
+            rule(RuleType::IpCidr, "17.0.0.0/8"),
+            rule(RuleType::ProcessName, "Telegram"),
+        ]);
+        let contents: Vec<(Option<&str>, &str)> = files
+            .iter()
+            .map(|f| (f.suffix, std::str::from_utf8(&f.content).unwrap()))
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                (
+                    Some("domain"),
+                    "# proxy-audit generated rules\n+.apple.com\nexample.com\n"
+                ),
+                (
+                    Some("ipcidr"),
+                    "# proxy-audit generated rules\n17.0.0.0/8\n"
+                ),
+                (
+                    None,
+                    "# proxy-audit generated rules\nPROCESS-NAME,Telegram\n"
+                ),
+            ]
+        );
+        assert_eq!(formatter.file_extension(), "txt");
+
+        let guide = formatter.generate_guide("/cfg");
+        assert!(guide.contains(
+            "behavior: ipcidr\n    format: text\n    path: \"/cfg/rules-direct-ipcidr.txt\""
+        ));
+        assert!(guide.contains("- RULE-SET,proxy-audit-direct-ipcidr,DIRECT,no-resolve"));
+    }
+
+    #[test]
+    fn test_clash_yaml_domain_provider() {
+        let formatter = ClashFormatter {
+            options: ClashOptions {
+                format: ClashProviderFormat::Yaml,
+                layout: ClashLayout::Split,
+            },
+        };
+        let files = formatter.generate_files(&[Rule {
+            rule_type: RuleType::DomainSuffix,
+            value: "apple.com".to_string(),
+            policy: RulePolicy::Direct,
+        }]);
+        assert_eq!(
+            files[0].content,
+            b"# proxy-audit generated rules\npayload:\n  - '+.apple.com'\n"
+        );
+        assert_eq!(
+            files[1].content,
+            b"# proxy-audit generated rules\npayload: []\n"
+        );
     }
 }
@@ -2,17 +2,150 @@
 //!
 //! 负责规则文件的初始化、读取和追加操作。
 
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
 use std::fs;
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
+use clap::ValueEnum;
+use ipnet::IpNet;
 
-use super::formatter::{create_formatter, OutputFormat, Rule};
+use super::atomic::{self, UpdateLock, LOCK_FILE};
+use super::document;
+use super::formatter::{
+    create_formatter, create_formatter_with, ClashLayout, ClashOptions, ClashProviderFormat,
+    Formatter, MatchBy, OutputFormat, Rule, RulePolicy, RuleType, SingBoxOptions,
+    SingBoxRuleSetFormat,
+};
+use super::reload;
+use super::store::{RuleStore, StoredRule, STORE_FILE};
+
+/// 合并目的地时 IPv4 地址的覆盖网段前缀
+const COLLAPSE_PREFIX_V4: u8 = 24;
+
+/// 合并目的地时 IPv6 地址的覆盖网段前缀
+const COLLAPSE_PREFIX_V6: u8 = 64;
+
+/// 策略文件的固定顺序
+const POLICIES: [RulePolicy; 3] = [RulePolicy::Direct, RulePolicy::Proxy, RulePolicy::Reject];
+
+/// 导入已有规则文件时的格式顺序
+const ALL_FORMATS: [OutputFormat; 8] = [
+    OutputFormat::Clash,
+    OutputFormat::Surge,
+    OutputFormat::QuantumultX,
+    OutputFormat::SingBox,
+    OutputFormat::Loon,
+    OutputFormat::Shadowrocket,
+    OutputFormat::Stash,
+    OutputFormat::Egern,
+];
+
+/// 规则的目标进程（用于 add / print）
+#[derive(Debug, Clone)]
+pub enum RuleTarget {
+    /// 运行中的进程 PID
+    Pid(i32),
+    /// 进程名，不要求进程正在运行；含通配符时匹配运行中的进程
+    Name(String),
+    /// 可执行文件或 .app bundle 路径；含通配符时匹配文件系统中的可执行文件和 bundle
+    Path(String),
+}
+
+/// 规则选择器（用于 list / remove / move）
+#[derive(Debug, Clone)]
+pub enum RuleSelector {
+    /// 进程名：匹配 PROCESS-NAME，以及文件名相同的 PROCESS-PATH
+    Name(String),
+    /// 进程路径：匹配 PROCESS-PATH
+    Path(String),
+    /// 任意规则值：IP 与 /32、/128 网段视为相同
+    Value(String),
+}
+
+impl RuleSelector {
+    /// 规则是否被选中
+    pub fn matches(&self, rule: &Rule) -> bool {
+        match self {
+            RuleSelector::Name(name) => match rule.rule_type {
+                RuleType::ProcessName => &rule.value == name,
+                RuleType::ProcessPath => {
+                    Path::new(&rule.value).file_name() == Some(OsStr::new(name))
+                }
+                _ => false,
+            },
+            RuleSelector::Path(path) => {
+                rule.rule_type == RuleType::ProcessPath && &rule.value == path
+            }
+            RuleSelector::Value(value) => {
+                &rule.value == value
+                    || matches!(
+                        (parse_net(value), parse_net(&rule.value)),
+                        (Some(a), Some(b)) if a == b
+                    )
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RuleSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleSelector::Name(name) => write!(f, "name '{}'", name),
+            RuleSelector::Path(path) => write!(f, "path '{}'", path),
+            RuleSelector::Value(value) => write!(f, "value '{}'", value),
+        }
+    }
+}
+
+/// 添加规则的结果，由调用方决定如何展示（命令行打印，TUI 显示在状态栏）
+#[derive(Debug, Default)]
+pub struct AddOutcome {
+    /// 写入主存储的规则
+    pub added: Vec<Rule>,
+    /// 策略发生变化的规则：(规则值, 原策略, 新策略)
+    pub moved: Vec<(String, RulePolicy, RulePolicy)>,
+    /// 内容发生变化的文件
+    pub changed: Vec<PathBuf>,
+    /// 导入和渲染时的提示（如无法表达而跳过的规则）
+    pub notes: Vec<String>,
+}
+
+impl AddOutcome {
+    /// 没有文件变化时的提示（命令行和 TUI 状态栏共用）
+    pub fn unchanged_message(&self) -> Option<String> {
+        if !self.changed.is_empty() {
+            return None;
+        }
+        let policies: BTreeSet<String> = self.added.iter().map(|r| r.policy.to_string()).collect();
+        let policies = policies.into_iter().collect::<Vec<_>>().join("/");
+        Some(match self.added.as_slice() {
+            [rule] => format!("{} is already {}, no file changed", rule.value, policies),
+            rules => format!(
+                "{} rules are already {}, no file changed",
+                rules.len(),
+                policies
+            ),
+        })
+    }
+}
+
+/// 一次渲染的结果
+struct Rendered {
+    /// 实际写入的文件
+    changed: Vec<PathBuf>,
+    /// 需要提示用户的问题
+    notes: Vec<String>,
+}
 
 /// 规则文件管理器
 pub struct RuleFileManager {
     /// 配置目录
     config_dir: PathBuf,
+    /// 其他进程正在更新规则时是否等待（TUI 不等待，直接返回错误）
+    wait_for_lock: bool,
 }
 
 impl RuleFileManager {
@@ -26,14 +159,36 @@ impl RuleFileManager {
             fs::create_dir_all(&config_dir)?;
         }
 
-        Ok(Self { config_dir })
+        Ok(Self {
+            config_dir,
+            wait_for_lock: true,
+        })
     }
 
-    /// 获取指定格式和策略的规则文件路径
+    /// 其他进程正在更新规则时不等待，直接返回错误（TUI 使用，避免阻塞界面）
+    pub fn without_waiting(mut self) -> Self {
+        self.wait_for_lock = false;
+        self
+    }
+
+    /// 获取指定格式和策略的规则文件路径（默认输出选项）
     pub fn get_rule_file_path(
         &self,
         format: OutputFormat,
         policy: super::formatter::RulePolicy,
+    ) -> PathBuf {
+        self.rule_file_path(create_formatter(format).as_ref(), policy, None)
+    }
+
+    /// 获取格式化器输出的规则文件路径
+    ///
+    /// 如 rules-direct.yaml、rules-direct.qx.list，多种格式可以同时启用；
+    /// Clash 拆分 provider 时带上 behavior 后缀，如 rules-direct-domain.yaml。
+    fn rule_file_path(
+        &self,
+        formatter: &dyn Formatter,
+        policy: RulePolicy,
+        suffix: Option<&str>,
     ) -> PathBuf {
         let policy_str = match policy {
             super::formatter::RulePolicy::Direct => "direct",
@@ -41,34 +196,143 @@ impl RuleFileManager {
             super::formatter::RulePolicy::Reject => "reject",
         };
 
-        let filename = match format {
-            OutputFormat::Clash => format!("rules-{}.yaml", policy_str),
-            OutputFormat::Surge => format!("rules-{}.list", policy_str),
-            OutputFormat::QuantumultX => format!("rules-{}.list", policy_str),
-            OutputFormat::SingBox => format!("rules-{}.json", policy_str),
+        let extension = formatter.file_extension();
+        let filename = match suffix {
+            Some(suffix) => format!("rules-{}-{}.{}", policy_str, suffix, extension),
+            None => format!("rules-{}.{}", policy_str, extension),
         };
         self.config_dir.join(filename)
     }
 
-    /// 初始化规则文件
-    pub fn init(&self, format: OutputFormat) -> Result<String> {
-        let policies = [
-            super::formatter::RulePolicy::Direct,
-            super::formatter::RulePolicy::Proxy,
-            super::formatter::RulePolicy::Reject,
-        ];
+    /// 按主存储中的输出选项创建格式化器
+    fn store_formatter(&self, store: &RuleStore, format: OutputFormat) -> Box<dyn Formatter> {
+        create_formatter_with(format, store.clash, store.singbox)
+    }
 
-        let formatter = create_formatter(format);
+    /// 格式化器为三个策略输出的全部文件路径
+    fn output_paths(&self, formatter: &dyn Formatter) -> Vec<PathBuf> {
+        POLICIES
+            .iter()
+            .flat_map(|p| {
+                formatter
+                    .generate_files(&[])
+                    .into_iter()
+                    .map(move |file| self.rule_file_path(formatter, *p, file.suffix))
+            })
+            .collect()
+    }
 
-        for policy in policies {
-            let path = self.get_rule_file_path(format, policy);
-            // 如果文件不存在，创建空文件
-            if !path.exists() {
-                let empty_content = formatter.generate_file_content(&[]);
-                fs::write(&path, &empty_content)?;
-                println!("Created rule file: {}", path.display());
-            } else {
+    /// 切换输出选项后遗留的旧文件
+    ///
+    /// 如 Clash 从 split 改回 classical 后的 `rules-direct-domain.yaml`、从 yaml 改为 text 后的
+    /// `rules-direct.yaml`，以及 sing-box 从 JSON 改为 .srs 后的 `rules-direct.json`。
+    /// 这些文件不再更新，客户端仍然引用它们时会使用过期的规则。
+    fn stale_files(&self, store: &RuleStore, format: OutputFormat) -> Vec<PathBuf> {
+        let variants: Vec<Box<dyn Formatter>> = match format {
+            OutputFormat::Clash => ClashLayout::value_variants()
+                .iter()
+                .flat_map(|layout| {
+                    ClashProviderFormat::value_variants()
+                        .iter()
+                        .map(|clash_format| {
+                            let clash = ClashOptions {
+                                format: *clash_format,
+                                layout: *layout,
+                            };
+                            create_formatter_with(format, clash, store.singbox)
+                        })
+                })
+                .collect(),
+            OutputFormat::SingBox => SingBoxRuleSetFormat::value_variants()
+                .iter()
+                .map(|singbox_format| {
+                    let singbox = SingBoxOptions {
+                        format: *singbox_format,
+                        ..store.singbox
+                    };
+                    create_formatter_with(format, store.clash, singbox)
+                })
+                .collect(),
+            _ => return Vec::new(),
+        };
+
+        let current = self.output_paths(self.store_formatter(store, format).as_ref());
+        let mut stale: Vec<PathBuf> = variants
+            .iter()
+            .flat_map(|formatter| self.output_paths(formatter.as_ref()))
+            .filter(|path| !current.contains(path) && path.exists())
+            .collect();
+        stale.sort();
+        stale.dedup();
+        stale
+    }
+
+    /// 已存在的规则文件路径（默认输出选项）
+    ///
+    /// sing-box 的 .json 不存在时再查找之前生成的 .srs。
+    fn existing_rule_file(&self, format: OutputFormat, policy: RulePolicy) -> Option<PathBuf> {
+        let path = self.get_rule_file_path(format, policy);
+        if path.exists() {
+            return Some(path);
+        }
+        if format != OutputFormat::SingBox {
+            return None;
+        }
+        let binary = create_formatter_with(
+            format,
+            Default::default(),
+            SingBoxOptions {
+                format: SingBoxRuleSetFormat::Binary,
+                ..Default::default()
+            },
+        );
+        let path = self.rule_file_path(binary.as_ref(), policy, None);
+        path.exists().then_some(path)
+    }
+
+    /// 初始化规则文件
+    ///
+    /// 在主存储中启用该格式，并渲染出三个策略文件（已有规则一并写入）。
+    /// 指定了 Clash 的 provider 格式、拆分方式或 sing-box 的规则集格式、版本时同时更新到主存储。
+    pub fn init(
+        &self,
+        format: OutputFormat,
+        clash_format: Option<ClashProviderFormat>,
+        clash_layout: Option<ClashLayout>,
+        singbox_format: Option<SingBoxRuleSetFormat>,
+        singbox_version: Option<u8>,
+    ) -> Result<String> {
+        let _lock = self.lock()?;
+        let mut store = self.load_store()?;
+        if store.enable(format) {
+            println!(
+                "Enabled {} output in {}",
+                format,
+                self.store_path().display()
+            );
+        }
+        if let Some(clash_format) = clash_format {
+            store.clash.format = clash_format;
+        }
+        if let Some(clash_layout) = clash_layout {
+            store.clash.layout = clash_layout;
+        }
+        if let Some(singbox_format) = singbox_format {
+            store.singbox.format = singbox_format;
+        }
+        if let Some(singbox_version) = singbox_version {
+            store.singbox.version = singbox_version;
+        }
+
+        let formatter = self.store_formatter(&store, format);
+        let paths = self.output_paths(formatter.as_ref());
+        let existing: Vec<bool> = paths.iter().map(|p| p.exists()).collect();
+        print_notes(&self.save_and_render(&store)?.notes);
+        for (path, existed) in paths.iter().zip(existing) {
+            if existed {
                 println!("Rule file already exists: {}", path.display());
+            } else {
+                println!("Created rule file: {}", path.display());
             }
         }
 
@@ -76,269 +340,963 @@ impl RuleFileManager {
         Ok(formatter.generate_guide(&self.config_dir.to_string_lossy()))
     }
 
-    /// 添加单条进程规则（Process Name based）
+    /// 添加进程规则（按进程名、进程路径或连接目的地）
+    ///
+    /// 规则写入主存储 `rules.toml`，再渲染到所有启用的格式；指定的 `format` 会被自动启用。
+    /// `by` 与 `collapse` 仅对 PID 目标有效，`collapse` 把 `--by destination` 观察到的地址合并为覆盖网段。
+    #[allow(clippy::too_many_arguments)]
     pub fn add_rule(
         &self,
-        pid: i32,
-        policy: super::formatter::RulePolicy,
-        format: OutputFormat,
-    ) -> Result<()> {
-        // 1. 获取进程名 (via libproc)
-        let process_name = crate::macos::libproc::get_process_name(pid)
-            .context(format!("Failed to get process name for PID {}", pid))?;
+        target: &RuleTarget,
+        by: MatchBy,
+        collapse: bool,
+        policy: RulePolicy,
+        format: Option<OutputFormat>,
+        skip_unsupported: bool,
+        note: Option<String>,
+    ) -> Result<AddOutcome> {
+        // 1. 获取进程名/路径或连接目的地 (via libproc)
+        let new_rules = self.build_rules(target, by, collapse, policy)?;
 
-        if process_name.is_empty() {
-            anyhow::bail!("PID {} not found or has no name.", pid);
+        match (target, by) {
+            (RuleTarget::Pid(pid), MatchBy::Destination) => {
+                println!("Found {} destination(s) for PID {}", new_rules.len(), pid)
+            }
+            (RuleTarget::Pid(pid), _) => {
+                println!("Found process: {} (PID: {})", new_rules[0].value, pid)
+            }
+            _ => {
+                for rule in &new_rules {
+                    println!("Matched {} {}", rule.rule_type, rule.value);
+                }
+            }
         }
 
-        println!("Found process: {} (PID: {})", process_name, pid);
+        self.add_rules(&new_rules, format, skip_unsupported, note)
+    }
 
-        // 2. QX 不支持 ProcessName，需特殊处理
-        if format == OutputFormat::QuantumultX {
-            println!("Warning: Quantumult X does not support process-name rules via this tool.");
-            return Ok(());
+    /// 把已经生成的规则写入主存储并重新渲染，不向终端输出
+    ///
+    /// 指定 `format` 时自动启用该格式；不指定时只渲染到已启用的格式，没有已启用的格式时返回错误。
+    /// 任一已启用的格式无法表达新规则时返回错误；`skip_unsupported` 为 true 时
+    /// 只要有一个格式能表达即可，其余格式跳过该规则并在 `notes` 中提示。
+    /// 同一进程/目的地的旧规则被覆盖，不会同时出现在两个策略中。
+    pub fn add_rules(
+        &self,
+        new_rules: &[Rule],
+        format: Option<OutputFormat>,
+        skip_unsupported: bool,
+        note: Option<String>,
+    ) -> Result<AddOutcome> {
+        let _lock = self.lock()?;
+        let (mut store, mut notes) = self.load_store_with_notes()?;
+        if let Some(format) = format {
+            store.enable(format);
+        }
+        if store.formats.is_empty() {
+            anyhow::bail!(
+                "No rule format is enabled yet, run `proxy-audit rule init --format <FORMAT>` first"
+            );
         }
 
-        // 3. 加载所有策略文件并清理冲突
-        let policies = [
-            super::formatter::RulePolicy::Direct,
-            super::formatter::RulePolicy::Proxy,
-            super::formatter::RulePolicy::Reject,
-        ];
-
-        let formatter = create_formatter(format);
-
-        for p in policies {
-            let path = self.get_rule_file_path(format, p);
-            let mut rules = if path.exists() {
-                self.read_rules(format, p)?
-            } else {
-                Vec::new()
-            };
-
-            // 移除当前进程名的旧规则
-            let initial_len = rules.len();
-            rules.retain(|r| {
-                !(matches!(r.rule_type, super::formatter::RuleType::ProcessName)
-                    && r.value == process_name)
-            });
-
-            // 如果是目标策略，则添加新规则
-            if p == policy {
-                rules.push(Rule {
-                    rule_type: super::formatter::RuleType::ProcessName,
-                    value: process_name.clone(),
-                    policy: p,
-                });
+        // 由格式化器决定能否表达（如 Quantumult X 没有进程规则，Surge 没有 PROCESS-PATH）
+        let formatters: Vec<(OutputFormat, Box<dyn Formatter>)> = store
+            .formats
+            .iter()
+            .map(|f| (*f, self.store_formatter(&store, *f)))
+            .collect();
+        for rule in new_rules {
+            let unsupported: Vec<String> = formatters
+                .iter()
+                .filter(|(_, f)| f.format_rule(rule).is_none())
+                .map(|(format, _)| format.to_string())
+                .collect();
+            if unsupported.is_empty() {
+                continue;
             }
+            let message = unsupported_rule(unsupported.join("/"), &rule.rule_type);
+            if unsupported.len() == formatters.len() {
+                anyhow::bail!(message);
+            }
+            if !skip_unsupported {
+                anyhow::bail!(
+                    "{} (pass --skip-unsupported to write it only to the formats that can)",
+                    message
+                );
+            }
+        }
 
-            // 只有当内容发生变化（原有被删 或者 新增了）才写入
-            // 但为了简单，只要是目标文件肯定变了，非目标文件如果删了也变了
-            // 简单点：全部重写对应的文件
-            let content = formatter.generate_file_content(&rules);
-            fs::write(&path, content)?;
-
-            if p == policy {
-                println!("Added rule to {}", path.display());
-            } else if rules.len() < initial_len {
-                println!("Removed conflicting rule from {}", path.display());
+        let mut moved = Vec::new();
+        for rule in new_rules.iter().cloned() {
+            let (value, policy) = (rule.value.clone(), rule.policy);
+            if let Some(previous) = store.upsert(rule, note.clone()) {
+                if previous != policy {
+                    moved.push((value, previous, policy));
+                }
             }
         }
+        let rendered = self.save_and_render(&store)?;
+        notes.extend(rendered.notes);
 
-        Ok(())
+        Ok(AddOutcome {
+            added: new_rules.to_vec(),
+            moved,
+            changed: rendered.changed,
+            notes,
+        })
     }
 
-    /// 生成单条规则字符串（不写入文件）
+    /// 生成规则字符串（不写入文件）
     pub fn generate_rule(
         &self,
-        pid: i32,
+        target: &RuleTarget,
+        by: MatchBy,
+        collapse: bool,
         policy: super::formatter::RulePolicy,
         format: OutputFormat,
     ) -> Result<String> {
-        let process_name = crate::macos::libproc::get_process_name(pid)
-            .context(format!("Failed to get process name for PID {}", pid))?;
-
-        let rule = Rule {
-            rule_type: super::formatter::RuleType::ProcessName,
-            value: process_name,
-            policy,
-        };
+        let rules = self.build_rules(target, by, collapse, policy)?;
 
         let formatter = create_formatter(format);
-        formatter
-            .format_rule(&rule)
-            .context("This format does not support process-name rules.")
+        let mut lines = Vec::new();
+        for rule in &rules {
+            let line = formatter
+                .format_rule(rule)
+                .with_context(|| unsupported_rule(format, &rule.rule_type))?;
+            lines.push(line);
+        }
+        Ok(lines.join("\n"))
     }
 
-    // append_rules 已被 add_rule 的逻辑取代，这里移除它或者改为私有辅助（暂且移除）
+    /// 根据目标构建规则
+    ///
+    /// 进程名和路径目标直接生成 PROCESS-NAME / PROCESS-PATH 规则，不需要进程正在运行：
+    /// - 进程名含通配符时匹配运行中的进程，如 `--name 'Code Helper*'`
+    /// - 路径为 .app bundle 时使用 Info.plist 中的主程序，
+    ///   含通配符时匹配文件系统，如 `--path '/Applications/*.app'`
+    pub fn build_rules(
+        &self,
+        target: &RuleTarget,
+        by: MatchBy,
+        collapse: bool,
+        policy: RulePolicy,
+    ) -> Result<Vec<Rule>> {
+        let (rule_type, values) = match target {
+            RuleTarget::Pid(pid) => return self.process_rules(*pid, by, collapse, policy),
+            RuleTarget::Name(name) if is_glob(name) => {
+                (RuleType::ProcessName, running_process_names(name)?)
+            }
+            RuleTarget::Name(name) => (RuleType::ProcessName, vec![name.clone()]),
+            RuleTarget::Path(path) => (RuleType::ProcessPath, executable_paths(path)?),
+        };
+        Ok(values
+            .into_iter()
+            .map(|value| Rule {
+                rule_type: rule_type.clone(),
+                value,
+                policy,
+            })
+            .collect())
+    }
 
-    /// 读取现有规则
-    fn read_rules(
+    /// 根据 PID 构建规则
+    ///
+    /// `--by path` 使用可执行文件完整路径，多个 App 共用 "Electron"、"Helper" 等进程名时不会冲突；
+    /// `--by destination` 为进程当前的每个远端地址生成 IP-CIDR 规则。
+    fn process_rules(
         &self,
-        format: OutputFormat,
+        pid: i32,
+        by: MatchBy,
+        collapse: bool,
         policy: super::formatter::RulePolicy,
     ) -> Result<Vec<Rule>> {
-        let path = self.get_rule_file_path(format, policy);
-        if !path.exists() {
-            return Ok(Vec::new());
-        }
-        let content = fs::read_to_string(&path)?;
+        let (rule_type, value) = match by {
+            MatchBy::Name => {
+                let name = crate::macos::libproc::get_process_name(pid)
+                    .context(format!("Failed to get process name for PID {}", pid))?;
+                (RuleType::ProcessName, name)
+            }
+            MatchBy::Path => {
+                let path = crate::macos::libproc::get_process_path(pid)
+                    .context(format!("Failed to get process path for PID {}", pid))?;
+                (RuleType::ProcessPath, path)
+            }
+            MatchBy::Destination => return self.destination_rules(pid, collapse, policy),
+        };
 
-        // 根据格式解析规则
-        match format {
-            OutputFormat::Clash => self.parse_clash_rules(&content, policy),
-            OutputFormat::Surge => self.parse_surge_rules(&content, policy),
-            OutputFormat::QuantumultX => self.parse_qx_rules(&content, policy),
-            OutputFormat::SingBox => self.parse_singbox_rules(&content, policy),
+        if value.is_empty() {
+            anyhow::bail!(
+                "PID {} not found or has no {}.",
+                pid,
+                match by {
+                    MatchBy::Path => "path",
+                    _ => "name",
+                }
+            );
         }
+
+        Ok(vec![Rule {
+            rule_type,
+            value,
+            policy,
+        }])
     }
 
-    /// 解析 Clash 格式规则
-    fn parse_clash_rules(
+    /// 根据进程当前连接的远端地址生成 IP-CIDR / IP-CIDR6 规则
+    fn destination_rules(
         &self,
-        content: &str,
+        pid: i32,
+        collapse: bool,
         policy: super::formatter::RulePolicy,
     ) -> Result<Vec<Rule>> {
-        let mut rules = Vec::new();
+        let sockets = crate::macos::libproc::list_process_sockets(pid)
+            .context(format!("Failed to list sockets for PID {}", pid))?;
+
+        let remotes = sockets
+            .iter()
+            .filter(|s| !s.tcp_state.is_some_and(|state| state.is_listening()))
+            .map(|s| s.remote_addr);
+        let nets = destination_nets(remotes, collapse);
+        if nets.is_empty() {
+            anyhow::bail!(
+                "PID {} has no public remote connections (loopback, LAN and fake-IP addresses are skipped).",
+                pid
+            );
+        }
+
+        Ok(nets
+            .into_iter()
+            .map(|net| Rule {
+                rule_type: match net {
+                    IpNet::V4(_) => RuleType::IpCidr,
+                    IpNet::V6(_) => RuleType::IpCidr6,
+                },
+                value: net.to_string(),
+                policy,
+            })
+            .collect())
+    }
+
+    /// 主存储文件路径
+    pub fn store_path(&self) -> PathBuf {
+        self.config_dir.join(STORE_FILE)
+    }
+
+    /// 获取规则更新锁，覆盖整个“读取 - 修改 - 写入”过程
+    fn lock(&self) -> Result<UpdateLock> {
+        let path = self.config_dir.join(LOCK_FILE);
+        if self.wait_for_lock {
+            return UpdateLock::acquire(&path);
+        }
+        UpdateLock::try_acquire(&path)?
+            .context("rules are being updated by another process, try again later")
+    }
+
+    /// 加载主存储
+    ///
+    /// `rules.toml` 还不存在时，从各格式已有的规则文件导入（按 Clash、Surge、
+    /// Quantumult X、sing-box 的顺序，同一条规则以先读到的策略为准）。
+    pub fn load_store(&self) -> Result<RuleStore> {
+        let (store, notes) = self.load_store_with_notes()?;
+        print_notes(&notes);
+        Ok(store)
+    }
+
+    /// 加载主存储，导入已有文件时的提示作为返回值而不是打印（供 TUI 使用）
+    pub fn load_store_with_notes(&self) -> Result<(RuleStore, Vec<String>)> {
+        if let Some(store) = RuleStore::load(&self.store_path())? {
+            return Ok((store, Vec::new()));
+        }
 
-        for line in content.lines() {
-            let line = line.trim();
-            if line.starts_with("- ") {
-                if let Some(rule) = self.parse_clash_rule_line(&line[2..], policy) {
-                    rules.push(rule);
+        let mut notes = self.migrate_legacy_qx()?;
+        let mut store = RuleStore::default();
+        let mut imported = 0;
+        for format in ALL_FORMATS {
+            let existing: Vec<PathBuf> = POLICIES
+                .iter()
+                .filter_map(|p| self.existing_rule_file(format, *p))
+                .collect();
+            if existing.is_empty() {
+                continue;
+            }
+            store.enable(format);
+            // 之前生成的是 .srs 时继续输出二进制规则集
+            if existing
+                .iter()
+                .any(|p| p.extension() == Some(OsStr::new("srs")))
+            {
+                store.singbox.format = SingBoxRuleSetFormat::Binary;
+            }
+            for policy in POLICIES {
+                for rule in self.read_rules(format, policy)? {
+                    let known = store
+                        .rules
+                        .iter()
+                        .any(|s| s.rule.rule_type == rule.rule_type && s.rule.value == rule.value);
+                    if !known {
+                        store.upsert(rule, None);
+                        imported += 1;
+                    }
                 }
             }
         }
-
-        Ok(rules)
+        if imported > 0 {
+            notes.push(format!(
+                "Imported {} rule(s) from existing rule files into {}",
+                imported,
+                self.store_path().display()
+            ));
+        }
+        Ok((store, notes))
     }
 
-    /// 解析单条 Clash 规则行 (V2: 只有 TYPE,VALUE，没有 POLICY)
-    fn parse_clash_rule_line(
-        &self,
-        line: &str,
-        policy: super::formatter::RulePolicy,
-    ) -> Option<Rule> {
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() >= 2 {
-            let rule_type = match parts[0].trim() {
-                "IP-CIDR" => super::formatter::RuleType::IpCidr,
-                "DOMAIN-SUFFIX" => super::formatter::RuleType::DomainSuffix,
-                "DOMAIN" => super::formatter::RuleType::Domain,
-                "PROCESS-NAME" => super::formatter::RuleType::ProcessName,
-                _ => return None,
+    /// 把旧版本生成的 Quantumult X 规则文件改名为 `rules-{policy}.qx.list`
+    ///
+    /// 旧版本的 QX 文件与 Surge 同名（`rules-{policy}.list`），不改名会被当作 Surge 规则导入并启用 Surge。
+    /// 两个文件都存在时无法自动合并，提示用户手动处理。返回改名的提示。
+    fn migrate_legacy_qx(&self) -> Result<Vec<String>> {
+        let mut notes = Vec::new();
+        for policy in POLICIES {
+            let legacy = self.get_rule_file_path(OutputFormat::Surge, policy);
+            let Ok(content) = fs::read(&legacy) else {
+                continue;
             };
-            let value = parts[1].trim().to_string();
-            // policy 由参数传入（基于文件名）
-            return Some(Rule {
-                rule_type,
-                value,
-                policy,
-            });
+            if !document::is_qx_list(&content) {
+                continue;
+            }
+            let target = self.get_rule_file_path(OutputFormat::QuantumultX, policy);
+            if target.exists() {
+                anyhow::bail!(
+                    "{} contains Quantumult X rules but {} already exists, merge them into {} and remove {}",
+                    legacy.display(),
+                    target.display(),
+                    target.display(),
+                    legacy.display()
+                );
+            }
+            fs::rename(&legacy, &target).with_context(|| {
+                format!(
+                    "Failed to rename {} to {}",
+                    legacy.display(),
+                    target.display()
+                )
+            })?;
+            notes.push(format!(
+                "Renamed legacy Quantumult X rule file {} to {}",
+                legacy.display(),
+                target.display()
+            ));
         }
-        None
+        Ok(notes)
     }
 
-    /// 解析 Surge 格式规则
-    fn parse_surge_rules(
-        &self,
-        content: &str,
-        policy: super::formatter::RulePolicy,
-    ) -> Result<Vec<Rule>> {
-        let mut rules = Vec::new();
+    /// 保存主存储，并把规则渲染到所有启用的格式
+    ///
+    /// 新内容合并到已有文件中，手动添加的规则和注释会被保留（见 document 模块）；
+    /// 任何一个已有文件无法解析时不写入任何文件。所有文件原子替换，
+    /// 其中一个写入失败时全部回滚（见 atomic 模块）。调用方需持有更新锁。
+    /// 内容没有变化的文件不重写，避免无谓地触发客户端的文件监视；返回实际写入的文件和提示。
+    fn save_and_render(&self, store: &RuleStore) -> Result<Rendered> {
+        let path = self.store_path();
+        // 上一次保存时由主存储管理的规则
+        let previous = RuleStore::load(&path)?.unwrap_or_default();
 
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
+        let mut writes = vec![(path, store.to_toml()?.into_bytes())];
+        let mut notes = Vec::new();
+        for format in &store.formats {
+            let formatter = self.store_formatter(store, *format);
+            for policy in POLICIES {
+                writes.extend(self.render_rules(formatter.as_ref(), policy, store, &previous)?);
+            }
+
+            // 该格式无法表达的规则（如 Surge 的 PROCESS-PATH）在渲染时跳过
+            let skipped = store
+                .rules
+                .iter()
+                .filter(|s| formatter.format_rule(&s.rule).is_none())
+                .count();
+            if skipped > 0 {
+                notes.push(format!(
+                    "Note: {} rule(s) cannot be expressed in {} format and were skipped",
+                    skipped, format
+                ));
             }
-            // Surge 格式与 Clash 类似 (TYPE,VALUE)
-            if let Some(rule) = self.parse_clash_rule_line(line, policy) {
-                rules.push(rule);
+
+            for path in self.stale_files(store, *format) {
+                notes.push(format!(
+                    "Note: {} was generated with earlier {} options and is no longer updated, \
+                     remove it and update the client config",
+                    path.display(),
+                    format
+                ));
             }
         }
 
-        Ok(rules)
+        writes.retain(|(path, content)| fs::read(path).map_or(true, |old| &old != content));
+        atomic::write_all(&writes)?;
+        Ok(Rendered {
+            changed: writes.into_iter().map(|(path, _)| path).collect(),
+            notes,
+        })
     }
 
-    /// 解析 Quantumult X 格式规则
-    fn parse_qx_rules(
-        &self,
-        content: &str,
-        policy: super::formatter::RulePolicy,
-    ) -> Result<Vec<Rule>> {
-        let mut rules = Vec::new();
+    /// 从主存储中删除选中的规则并重新渲染，返回被删除的规则
+    pub fn remove_rules(&self, selector: &RuleSelector) -> Result<Vec<StoredRule>> {
+        let _lock = self.lock()?;
+        let mut store = self.load_store()?;
+        let removed = store.remove(selector);
+        if !removed.is_empty() {
+            print_notes(&self.save_and_render(&store)?.notes);
+        }
+        Ok(removed)
+    }
 
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
+    /// 修改选中规则的策略并重新渲染，返回策略发生变化的规则
+    ///
+    /// 已经在目标策略中的规则保持不变，不计入返回值。
+    pub fn move_rules(&self, selector: &RuleSelector, to: RulePolicy) -> Result<Vec<StoredRule>> {
+        let _lock = self.lock()?;
+        let mut store = self.load_store()?;
+        let moved = store.set_policy(selector, to);
+        if !moved.is_empty() {
+            print_notes(&self.save_and_render(&store)?.notes);
+        }
+        Ok(moved)
+    }
+
+    /// 通知启用格式对应的代理客户端重新加载规则
+    ///
+    /// 支持 Clash / mihomo（外部控制器）、Surge（HTTP API）和 sing-box（Clash 兼容 API 确认版本，
+    /// 1.10 之前的版本发送 SIGHUP）。规则已经写入，重载失败只打印警告。
+    pub fn reload(&self) -> Result<()> {
+        let store = self.load_store()?;
+        let home = std::env::var("HOME").context("Failed to get HOME directory")?;
+        let mut unsupported = Vec::new();
+        for format in &store.formats {
+            let Some(controller) = reload::discover(*format, &store.controller, Path::new(&home))
+            else {
+                unsupported.push(format.to_string());
                 continue;
+            };
+            let providers = self.store_formatter(&store, *format).provider_names();
+            match controller.reload(&providers) {
+                Ok(result) => println!(
+                    "Reloaded {} via {} ({}): {}",
+                    format, controller.url, controller.source, result
+                ),
+                Err(e) => eprintln!(
+                    "Warning: failed to reload {} via {}: {:#}",
+                    format, controller.url, e
+                ),
             }
+        }
+        if !unsupported.is_empty() {
+            println!(
+                "Note: no controller found for {}, reload it in the client manually",
+                unsupported.join(", ")
+            );
+        }
+        Ok(())
+    }
 
-            let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
-            if parts.len() >= 2 {
-                let rule_type = match parts[0].to_lowercase().as_str() {
-                    "ip-cidr" => super::formatter::RuleType::IpCidr,
-                    "host-suffix" => super::formatter::RuleType::DomainSuffix,
-                    "host" => super::formatter::RuleType::Domain,
-                    _ => continue,
-                };
-                let value = parts[1].to_string();
-                // QX 可能在文件里有 policy，也可能没有。为了统一，我们这里信任文件名对应的 policy
-                // 也可以尝试解析 parts[2] 但我们主要关注 overwrite 逻辑
-                rules.push(Rule {
-                    rule_type,
-                    value,
-                    policy,
-                });
+    /// 生成指定策略的规则文件内容，返回 (路径, 内容) 列表
+    ///
+    /// 已有文件时与其合并：`previous` 中的规则不再出现时被移除；手动添加、
+    /// 但在主存储中属于其他策略的规则也被移除，同一条规则不会同时出现在两个策略文件中。
+    /// 其他内容原样保留。
+    fn render_rules(
+        &self,
+        formatter: &dyn Formatter,
+        policy: RulePolicy,
+        store: &RuleStore,
+        previous: &RuleStore,
+    ) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        let other_policies: Vec<&Rule> = store
+            .rules
+            .iter()
+            .map(|s| &s.rule)
+            .filter(|rule| rule.policy != policy)
+            .collect();
+        let mut files = Vec::new();
+        for file in formatter.generate_files(&store.rules_for(policy)) {
+            let path = self.rule_file_path(formatter, policy, file.suffix);
+            if !path.exists() {
+                files.push((path, file.content));
+                continue;
             }
+            let existing =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let content = document::merge(
+                formatter.syntax(file.suffix),
+                &existing,
+                &file.content,
+                policy,
+                |rule| {
+                    previous
+                        .rules
+                        .iter()
+                        .map(|s| &s.rule)
+                        .chain(other_policies.iter().copied())
+                        .any(|managed| document::same_rule(managed, rule))
+                },
+            )
+            .with_context(|| {
+                format!(
+                    "Refusing to overwrite {}, fix or move it away first",
+                    path.display()
+                )
+            })?;
+            files.push((path, content));
         }
-
-        Ok(rules)
+        Ok(files)
     }
 
-    /// 解析 Sing-box 格式规则
-    fn parse_singbox_rules(
+    /// 读取现有规则
+    pub fn read_rules(
         &self,
-        content: &str,
+        format: OutputFormat,
         policy: super::formatter::RulePolicy,
     ) -> Result<Vec<Rule>> {
-        let json: serde_json::Value = serde_json::from_str(content).unwrap_or_default();
-        let mut rules = Vec::new();
-
-        if let Some(rules_array) = json.get("rules").and_then(|r| r.as_array()) {
-            for rule_obj in rules_array {
-                // 不再读取 outbound，直接使用传入的 policy
-
-                // 处理 IP-CIDR
-                if let Some(ip_cidrs) = rule_obj.get("ip_cidr").and_then(|v| v.as_array()) {
-                    for ip in ip_cidrs {
-                        if let Some(ip_str) = ip.as_str() {
-                            rules.push(Rule {
-                                rule_type: super::formatter::RuleType::IpCidr,
-                                value: ip_str.to_string(),
-                                policy,
-                            });
-                        }
-                    }
-                }
+        let Some(path) = self.existing_rule_file(format, policy) else {
+            return Ok(Vec::new());
+        };
+        let content = fs::read(&path)?;
 
-                // 处理 Process Name
-                if let Some(process_names) = rule_obj.get("process_name").and_then(|v| v.as_array())
-                {
-                    for name in process_names {
-                        if let Some(name_str) = name.as_str() {
-                            rules.push(Rule {
-                                rule_type: super::formatter::RuleType::ProcessName,
-                                value: name_str.to_string(),
-                                policy,
-                            });
-                        }
-                    }
-                }
+        // sing-box 的 JSON 语法同时识别之前生成的 .srs
+        let syntax = create_formatter(format).syntax(None);
+        document::parse_rules(syntax, &content, policy)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+/// 打印导入和渲染时的提示
+fn print_notes(notes: &[String]) {
+    for note in notes {
+        println!("{}", note);
+    }
+}
+
+/// 格式无法表达某类规则时的错误提示
+fn unsupported_rule(format: impl std::fmt::Display, rule_type: &RuleType) -> String {
+    match rule_type {
+        RuleType::ProcessName => format!(
+            "{} rules cannot express process names, use --pid with --by destination",
+            format
+        ),
+        RuleType::ProcessPath => format!(
+            "{} rules cannot express process paths, use --by name",
+            format
+        ),
+        _ => format!("{} rules cannot express {} rules", format, rule_type),
+    }
+}
+
+/// 是否含有通配符
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// 匹配通配符的运行中进程名（去重并排序）
+fn running_process_names(pattern: &str) -> Result<Vec<String>> {
+    let matcher = glob::Pattern::new(pattern)
+        .with_context(|| format!("Invalid name pattern: {}", pattern))?;
+    let names: BTreeSet<String> = crate::macos::libproc::list_all_pids()?
+        .into_iter()
+        .filter_map(crate::macos::libproc::get_process_name)
+        .filter(|name| matcher.matches(name))
+        .collect();
+    if names.is_empty() {
+        anyhow::bail!("No running process matches name '{}'", pattern);
+    }
+    Ok(names.into_iter().collect())
+}
+
+/// 把路径参数解析为可执行文件的绝对路径
+///
+/// .app bundle 解析为其主程序；通配符匹配到的普通文件只保留可执行文件。
+fn executable_paths(pattern: &str) -> Result<Vec<String>> {
+    let glob = is_glob(pattern);
+    let candidates: Vec<PathBuf> = if glob {
+        glob::glob(pattern)
+            .with_context(|| format!("Invalid path pattern: {}", pattern))?
+            .filter_map(|entry| entry.ok())
+            .collect()
+    } else {
+        vec![PathBuf::from(pattern)]
+    };
+
+    let mut paths = BTreeSet::new();
+    for candidate in candidates {
+        if candidate.extension() == Some(OsStr::new("app")) && candidate.is_dir() {
+            paths.insert(bundle_executable(&candidate)?);
+        } else if !glob || is_executable(&candidate) {
+            // 进程路径是解析过符号链接的绝对路径
+            let path = fs::canonicalize(&candidate).unwrap_or(candidate);
+            if !path.is_absolute() {
+                anyhow::bail!("Process path must be absolute: {}", path.display());
             }
+            paths.insert(path);
         }
+    }
+    if paths.is_empty() {
+        anyhow::bail!("No executable or .app bundle matches '{}'", pattern);
+    }
+    Ok(paths
+        .into_iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// .app bundle 的主程序：`Contents/MacOS/{CFBundleExecutable}`
+fn bundle_executable(bundle: &Path) -> Result<PathBuf> {
+    let info = bundle.join("Contents/Info.plist");
+    let plist = plist::Value::from_file(&info)
+        .with_context(|| format!("Failed to read {}", info.display()))?;
+    let executable = plist
+        .as_dictionary()
+        .and_then(|dict| dict.get("CFBundleExecutable"))
+        .and_then(|value| value.as_string())
+        .with_context(|| format!("No CFBundleExecutable in {}", info.display()))?;
+    let bundle = fs::canonicalize(bundle).unwrap_or_else(|_| bundle.to_path_buf());
+    Ok(bundle.join("Contents/MacOS").join(executable))
+}
+
+/// 是否为可执行的普通文件
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// 解析 IP 或网段（单个 IP 视为 /32 或 /128）
+pub(super) fn parse_net(s: &str) -> Option<IpNet> {
+    s.parse::<IpNet>()
+        .ok()
+        .or_else(|| s.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// 把远端地址整理为规则网段
+///
+/// 跳过未指定、回环、组播、局域网和 TUN fake-IP 地址（它们不是真实目的地），
+/// `collapse` 时先截断到 /24 (IPv4) 或 /64 (IPv6) 再合并相邻网段，否则每个地址一条 /32 或 /128。
+fn destination_nets(remotes: impl Iterator<Item = IpAddr>, collapse: bool) -> Vec<IpNet> {
+    let nets: Vec<IpNet> = remotes
+        .filter(|ip| {
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || crate::scan::is_lan_ip(ip)
+                || crate::scan::is_tun_virtual_ip(ip))
+        })
+        .map(|ip| {
+            let net = IpNet::from(ip);
+            if !collapse {
+                return net;
+            }
+            let prefix = match net {
+                IpNet::V4(_) => COLLAPSE_PREFIX_V4,
+                IpNet::V6(_) => COLLAPSE_PREFIX_V6,
+            };
+            IpNet::new(ip, prefix).map(|n| n.trunc()).unwrap_or(net)
+        })
+        .collect();
+
+    // aggregate 同时完成去重、排序和相邻网段合并
+    IpNet::aggregate(&nets)
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(list: &[&str]) -> Vec<IpAddr> {
+        list.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_destination_nets_skips_local() {
+        let remotes = ips(&[
+            "149.154.167.51",
+            "149.154.167.51",
+            "127.0.0.1",
+            "192.168.1.1",
+            "198.18.0.7",
+            "0.0.0.0",
+            "fe80::1",
+            "2001:67c:4e8:f004::9",
+        ]);
+        let nets: Vec<String> = destination_nets(remotes.into_iter(), false)
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(nets, ["149.154.167.51/32", "2001:67c:4e8:f004::9/128"]);
+    }
+
+    #[test]
+    fn test_rule_selector() {
+        let rule = |rule_type, value: &str| Rule {
+            rule_type,
+            value: value.to_string(),
+            policy: RulePolicy::Direct,
+        };
+        let by_name = RuleSelector::Name("Slack".to_string());
+        assert!(by_name.matches(&rule(RuleType::ProcessName, "Slack")));
+        assert!(by_name.matches(&rule(
+            RuleType::ProcessPath,
+            "/Applications/Slack.app/Contents/MacOS/Slack"
+        )));
+        assert!(!by_name.matches(&rule(RuleType::Domain, "Slack")));
+
+        let by_value = RuleSelector::Value("1.2.3.4".to_string());
+        assert!(by_value.matches(&rule(RuleType::IpCidr, "1.2.3.4/32")));
+        assert!(!by_value.matches(&rule(RuleType::IpCidr, "1.2.3.0/24")));
+        assert!(RuleSelector::Value("example.com".to_string())
+            .matches(&rule(RuleType::DomainSuffix, "example.com")));
+    }
+
+    #[test]
+    fn test_destination_nets_collapse() {
+        let remotes = ips(&[
+            "149.154.167.51",
+            "149.154.167.99",
+            "149.154.166.10",
+            "91.108.56.1",
+        ]);
+        let nets: Vec<String> = destination_nets(remotes.into_iter(), true)
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(nets, ["91.108.56.0/24", "149.154.166.0/23"]);
+    }
+
+    #[test]
+    fn test_executable_paths() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("proxy-audit-paths-{}", std::process::id()));
+        let macos = dir.join("Telegram.app/Contents/MacOS");
+        fs::create_dir_all(&macos).unwrap();
+        fs::write(
+            dir.join("Telegram.app/Contents/Info.plist"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>CFBundleExecutable</key><string>Telegram</string>
+</dict></plist>"#,
+        )
+        .unwrap();
+        fs::write(macos.join("Telegram"), b"").unwrap();
+        fs::write(dir.join("tool"), b"").unwrap();
+        fs::set_permissions(dir.join("tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("README"), b"").unwrap();
+        let dir = fs::canonicalize(&dir).unwrap();
+        let telegram = dir
+            .join("Telegram.app/Contents/MacOS/Telegram")
+            .to_string_lossy()
+            .into_owned();
+
+        let bundle = executable_paths(&dir.join("Telegram.app").to_string_lossy()).unwrap();
+        assert_eq!(bundle, vec![telegram.clone()]);
+
+        // 通配符跳过不可执行的文件
+        let all = executable_paths(&format!("{}/*", dir.display())).unwrap();
+        assert_eq!(
+            all,
+            [telegram, dir.join("tool").to_string_lossy().into_owned()]
+        );
+
+        assert!(executable_paths(&format!("{}/*.exe", dir.display())).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_legacy_qx_list() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-qx-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = RuleFileManager {
+            config_dir: dir.clone(),
+            wait_for_lock: true,
+        };
+        fs::write(
+            dir.join("rules-proxy.list"),
+            "# proxy-audit generated rules\nhost-suffix, example.com\nip-cidr, 1.2.3.0/24, proxy\n",
+        )
+        .unwrap();
+
+        let store = manager.load_store().unwrap();
+        assert_eq!(store.formats, [OutputFormat::QuantumultX]);
+        assert_eq!(store.rules_for(RulePolicy::Proxy).len(), 2);
+        assert!(!dir.join("rules-proxy.list").exists());
+        assert!(dir.join("rules-proxy.qx.list").exists());
+
+        // .qx.list 已经存在时不覆盖，要求手动合并
+        fs::write(dir.join("rules-proxy.list"), "host, example.org\n").unwrap();
+        assert!(manager.load_store().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stale_files_after_layout_switch() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-stale-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = RuleFileManager {
+            config_dir: dir.clone(),
+            wait_for_lock: true,
+        };
+        let mut store = RuleStore::default();
+        store.enable(OutputFormat::Clash);
+        store.clash.layout = ClashLayout::Split;
+        manager.save_and_render(&store).unwrap();
+        assert!(manager.stale_files(&store, OutputFormat::Clash).is_empty());
+
+        store.clash.layout = ClashLayout::Classical;
+        store.clash.format = ClashProviderFormat::Text;
+        manager.save_and_render(&store).unwrap();
+        let stale = manager.stale_files(&store, OutputFormat::Clash);
+        assert_eq!(stale.len(), 9);
+        assert!(stale.contains(&dir.join("rules-direct-domain.yaml")));
+        assert!(stale.contains(&dir.join("rules-direct.yaml")));
+        assert!(!stale.contains(&dir.join("rules-direct.txt")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hand_added_rule_moves_with_store() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-moved-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = RuleFileManager {
+            config_dir: dir.clone(),
+            wait_for_lock: true,
+        };
+        let mut store = RuleStore::default();
+        store.enable(OutputFormat::Clash);
+        manager.save_and_render(&store).unwrap();
+
+        // 用户手动在 DIRECT 文件中添加了同一个进程
+        let direct = dir.join("rules-direct.yaml");
+        let mut content = fs::read_to_string(&direct).unwrap();
+        content.push_str("  - PROCESS-NAME,Bar\n  - GEOIP,CN\n");
+        fs::write(&direct, content).unwrap();
+
+        let bar = Rule {
+            rule_type: RuleType::ProcessName,
+            value: "Bar".to_string(),
+            policy: RulePolicy::Proxy,
+        };
+        manager
+            .add_rules(&[bar], Some(OutputFormat::Clash), false, None)
+            .unwrap();
+        let direct_content = fs::read_to_string(&direct).unwrap();
+        assert!(!direct_content.contains("PROCESS-NAME,Bar"));
+        assert!(direct_content.contains("GEOIP,CN"));
+        let proxy = fs::read_to_string(dir.join("rules-proxy.yaml")).unwrap();
+        assert!(proxy.contains("PROCESS-NAME,Bar"));
+
+        let selector = RuleSelector::Name("Bar".to_string());
+        assert_eq!(
+            manager
+                .move_rules(&selector, RulePolicy::Reject)
+                .unwrap()
+                .len(),
+            1
+        );
+        let proxy = fs::read_to_string(dir.join("rules-proxy.yaml")).unwrap();
+        assert!(!proxy.contains("PROCESS-NAME,Bar"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_add_rules_without_format() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-noformat-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = RuleFileManager {
+            config_dir: dir.clone(),
+            wait_for_lock: true,
+        };
+        let curl = vec![Rule {
+            rule_type: RuleType::ProcessName,
+            value: "curl".to_string(),
+            policy: RulePolicy::Direct,
+        }];
+
+        // 没有已启用的格式时不会悄悄启用 Clash
+        let err = manager.add_rules(&curl, None, false, None).unwrap_err();
+        assert!(err.to_string().contains("rule init"));
+        assert!(!manager.store_path().exists());
+
+        // 只启用了 Quantumult X 时进程规则无法表达，返回错误而不是“没有变化”
+        let mut store = RuleStore::default();
+        store.enable(OutputFormat::QuantumultX);
+        manager.save_and_render(&store).unwrap();
+        let err = manager.add_rules(&curl, None, false, None).unwrap_err();
+        assert!(err.to_string().contains("cannot express process names"));
+
+        // 启用 Clash 后 Quantumult X 仍然无法表达，需要显式跳过
+        let err = manager
+            .add_rules(&curl, Some(OutputFormat::Clash), false, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("--skip-unsupported"));
+        let outcome = manager
+            .add_rules(&curl, Some(OutputFormat::Clash), true, None)
+            .unwrap();
+        assert!(outcome.changed.contains(&dir.join("rules-direct.yaml")));
+        assert!(outcome.notes.iter().any(|n| n.contains("quantumultx")));
+        assert_eq!(outcome.unchanged_message(), None);
+
+        // 其他进程持有锁时 TUI 不等待，直接返回错误
+        let lock = manager.lock().unwrap();
+        let err = RuleFileManager {
+            config_dir: dir.clone(),
+            wait_for_lock: true,
+        }
+        .without_waiting()
+        .add_rules(&curl, None, true, None)
+        .unwrap_err();
+        assert!(err.to_string().contains("another process"));
+        drop(lock);
+
+        let outcome = manager.add_rules(&curl, None, true, None).unwrap();
+        assert_eq!(
+            outcome.unchanged_message().as_deref(),
+            Some("curl is already DIRECT, no file changed")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_add_rules_checks_every_enabled_format() {
+        let dir = std::env::temp_dir().join(format!("proxy-audit-allfmt-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = RuleFileManager {
+            config_dir: dir.clone(),
+            wait_for_lock: true,
+        };
+        let mut store = RuleStore::default();
+        store.enable(OutputFormat::Clash);
+        store.enable(OutputFormat::Surge);
+        manager.save_and_render(&store).unwrap();
+
+        // Clash 能表达 PROCESS-PATH，Surge 不能：即使指定了 -f clash 也要报错
+        let path = vec![Rule {
+            rule_type: RuleType::ProcessPath,
+            value: "/usr/bin/curl".to_string(),
+            policy: RulePolicy::Direct,
+        }];
+        let err = manager
+            .add_rules(&path, Some(OutputFormat::Clash), false, None)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("surge rules cannot express process paths"));
+        assert!(manager.load_store().unwrap().rules.is_empty());
 
-        Ok(rules)
+        let outcome = manager
+            .add_rules(&path, Some(OutputFormat::Clash), true, None)
+            .unwrap();
+        assert!(outcome.notes.iter().any(|n| n.contains("surge")));
+        let clash = fs::read_to_string(dir.join("rules-direct.yaml")).unwrap();
+        assert!(clash.contains("PROCESS-PATH,/usr/bin/curl"));
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
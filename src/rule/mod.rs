@@ -4,9 +4,15 @@
 //! 1. 从进程连接信息生成代理分流规则
 //! 2. 支持多种代理软件规则格式（Clash, Surge, Quantumult X, Sing-box）
 //! 3. 管理规则文件的初始化和追加
+//! 4. 以 `rules.toml` 为唯一数据源，渲染所有启用的客户端格式
 
+mod atomic;
+mod document;
 pub mod formatter;
 mod manager;
+mod reload;
+mod srs;
+pub mod store;
 
-pub use formatter::{OutputFormat, RulePolicy};
-pub use manager::RuleFileManager;
+pub use formatter::{MatchBy, OutputFormat, RulePolicy};
+pub use manager::{AddOutcome, RuleFileManager, RuleSelector, RuleTarget};
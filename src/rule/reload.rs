@@ -0,0 +1,606 @@
+//! # 规则变更后通知代理客户端重新加载
+//!
+//! - Clash / mihomo: 外部控制器 `PUT /providers/rules/{name}` 刷新本工具的 rule-provider，
+//!   找不到 provider（名称被改过）时退回 `PUT /configs?force=true` 重新加载整个配置
+//! - Surge: HTTP API `POST /v1/profiles/reload`
+//! - sing-box: 通过 Clash 兼容 API 的 `GET /version` 确认版本。1.10 起会监视本地 `rule_set` 文件
+//!   并在修改后自动重新加载，不需要操作；更早的版本 API 无法刷新规则集
+//!   （`PUT /providers/rules/{name}` 返回 404，`PUT /configs` 什么也不做），
+//!   改为向 sing-box 进程发送 SIGHUP，`sing-box run` 收到后重新读取配置和规则集
+//!
+//! ## 控制器地址
+//! 优先使用 `rules.toml` 中的配置：
+//! ```toml
+//! [controller.clash]
+//! url = "http://127.0.0.1:9090"
+//! secret = "xxx"
+//!
+//! [controller.surge]
+//! url = "http://127.0.0.1:6171"
+//! secret = "surge-http-api-key"
+//!
+//! [controller.sing-box]
+//! url = "http://127.0.0.1:9090"
+//! ```
+//! 没有配置时从客户端的配置文件中查找（mihomo 的 `external-controller` / `secret`、
+//! Surge 的 `http-api`、sing-box 的 `experimental.clash_api`），
+//! 最后探测本机 9090 / 9097 端口上的 Clash 控制器（按 `/version` 区分 mihomo 和 sing-box）。
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::formatter::OutputFormat;
+use crate::proxy::{fingerprint, ListenerProtocol};
+
+/// 请求超时（控制器都在本机）
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 探测 Clash 控制器的默认端口（mihomo / Clash Verge）
+const CLASH_CONTROLLER_PORTS: [u16; 2] = [9090, 9097];
+
+/// mihomo / Clash 的配置文件（相对于主目录）
+const CLASH_CONFIG_FILES: [&str; 4] = [
+    ".config/mihomo/config.yaml",
+    ".config/clash.meta/config.yaml",
+    ".config/clash/config.yaml",
+    "Library/Application Support/io.github.clash-verge-rev.clash-verge-rev/clash-verge.yaml",
+];
+
+/// sing-box 的配置文件（相对于主目录；绝对路径为 Homebrew 的安装位置）
+const SINGBOX_CONFIG_FILES: [&str; 3] = [
+    ".config/sing-box/config.json",
+    "/opt/homebrew/etc/sing-box/config.json",
+    "/usr/local/etc/sing-box/config.json",
+];
+
+/// sing-box 开始监视本地规则集文件的版本
+const SINGBOX_WATCH_VERSION: (u32, u32) = (1, 10);
+
+/// Surge 配置文件所在目录（相对于主目录）
+const SURGE_PROFILE_DIRS: [&str; 2] = [
+    "Library/Application Support/Surge/Profiles",
+    "Library/Mobile Documents/iCloud~com~nssurge~inc/Documents",
+];
+
+// ========================================
+// 配置
+// ========================================
+
+/// 单个控制器的配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControllerConfig {
+    /// 控制器地址，如 `http://127.0.0.1:9090`
+    pub url: String,
+    /// Clash 的 secret 或 Surge 的 HTTP API key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+/// 各客户端的控制器配置（rules.toml 的 `[controller.*]` 段）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Controllers {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clash: Option<ControllerConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surge: Option<ControllerConfig>,
+    #[serde(default, rename = "sing-box", skip_serializing_if = "Option::is_none")]
+    pub singbox: Option<ControllerConfig>,
+}
+
+impl Controllers {
+    /// 是否没有任何配置
+    pub fn is_empty(&self) -> bool {
+        self.clash.is_none() && self.surge.is_none() && self.singbox.is_none()
+    }
+
+    /// 指定格式的配置
+    fn get(&self, format: OutputFormat) -> Option<&ControllerConfig> {
+        match format {
+            OutputFormat::Clash => self.clash.as_ref(),
+            OutputFormat::Surge => self.surge.as_ref(),
+            OutputFormat::SingBox => self.singbox.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+// ========================================
+// 控制器
+// ========================================
+
+/// 控制器 API 类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Api {
+    /// Clash RESTful API（mihomo）
+    Clash,
+    /// Surge HTTP API
+    Surge,
+    /// sing-box 的 Clash 兼容 API（`experimental.clash_api`）
+    SingBox,
+}
+
+/// 找到的控制器
+#[derive(Debug, Clone)]
+pub struct Controller {
+    /// API 类型
+    pub api: Api,
+    /// 控制器地址（不带结尾的 /）
+    pub url: String,
+    /// secret / key
+    pub secret: Option<String>,
+    /// 地址的来源，如 "rules.toml"、配置文件路径
+    pub source: String,
+}
+
+impl Controller {
+    /// 通知客户端重新加载规则
+    ///
+    /// `providers` 为本工具的 rule-provider 名称（仅 Clash API 使用），返回结果描述。
+    pub fn reload(&self, providers: &[String]) -> Result<String> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .no_proxy()
+            .build()?;
+        match self.api {
+            Api::Clash => self.reload_clash(&client, providers),
+            Api::Surge => {
+                let url = format!("{}/v1/profiles/reload", self.url);
+                let mut request = client.post(&url);
+                if let Some(key) = &self.secret {
+                    request = request.header("X-Key", key);
+                }
+                check(send(request, &url)?, "Surge HTTP API key")?;
+                Ok("reloaded profile".to_string())
+            }
+            Api::SingBox => self.reload_singbox(&client),
+        }
+    }
+
+    /// sing-box 1.10 起不需要操作，更早的版本发送 SIGHUP
+    fn reload_singbox(&self, client: &Client) -> Result<String> {
+        let version = self.version(client)?;
+        let parsed = parse_singbox_version(&version)
+            .with_context(|| format!("{} is not a sing-box controller ({})", self.url, version))?;
+        if parsed >= SINGBOX_WATCH_VERSION {
+            return Ok(format!(
+                "{} reloads local rule sets when they change, nothing to do",
+                version
+            ));
+        }
+        let pids = signal_singbox()?;
+        let pids: Vec<String> = pids.iter().map(ToString::to_string).collect();
+        Ok(format!(
+            "{} does not watch rule sets, sent SIGHUP to PID {}",
+            version,
+            pids.join(", ")
+        ))
+    }
+
+    /// Clash 兼容 API 的 `GET /version`，如 "sing-box 1.10.1"、"v1.18.10"
+    fn version(&self, client: &Client) -> Result<String> {
+        let url = format!("{}/version", self.url);
+        let response = self
+            .authorized(client.get(&url))
+            .send()
+            .with_context(|| format!("Failed to connect to {}", url))?;
+        check(response.status(), "controller secret")?;
+        let body: serde_json::Value = response
+            .json()
+            .with_context(|| format!("Invalid response from {}", url))?;
+        body["version"]
+            .as_str()
+            .map(str::to_string)
+            .with_context(|| format!("No version in response from {}", url))
+    }
+
+    fn reload_clash(&self, client: &Client, providers: &[String]) -> Result<String> {
+        let mut missing = Vec::new();
+        for name in providers {
+            let url = format!("{}/providers/rules/{}", self.url, name);
+            let status = send(self.authorized(client.put(&url)), &url)?;
+            if status == StatusCode::NOT_FOUND {
+                missing.push(name.as_str());
+                continue;
+            }
+            check(status, "controller secret")?;
+        }
+        if missing.is_empty() {
+            return Ok(format!("refreshed {} rule provider(s)", providers.len()));
+        }
+
+        // 空 path 表示重新加载启动时使用的配置文件
+        let url = format!("{}/configs?force=true", self.url);
+        let request = self
+            .authorized(client.put(&url))
+            .json(&serde_json::json!({ "path": "", "payload": "" }));
+        check(send(request, &url)?, "controller secret")?;
+        Ok(format!(
+            "reloaded config (rule provider {} not found)",
+            missing.join(", ")
+        ))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.secret {
+            Some(secret) => request.bearer_auth(secret),
+            None => request,
+        }
+    }
+}
+
+fn send(request: RequestBuilder, url: &str) -> Result<StatusCode> {
+    let response = request
+        .send()
+        .with_context(|| format!("Failed to connect to {}", url))?;
+    Ok(response.status())
+}
+
+fn check(status: StatusCode, credential: &str) -> Result<()> {
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        bail!("{} (check the {})", status, credential);
+    }
+    if !status.is_success() {
+        bail!("controller returned {}", status);
+    }
+    Ok(())
+}
+
+/// 解析 sing-box 的版本号 "sing-box 1.10.0-beta.3" -> (1, 10)，其他客户端返回 None
+fn parse_singbox_version(version: &str) -> Option<(u32, u32)> {
+    let number = version
+        .strip_prefix("sing-box ")?
+        .trim()
+        .trim_start_matches('v');
+    let mut parts = number.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// 向 sing-box 进程发送 SIGHUP，返回收到信号的 PID
+///
+/// sing-box 以 root 运行（TUN 模式）时需要 sudo；图形客户端（SFM）的进程名不同，找不到时报错。
+fn signal_singbox() -> Result<Vec<i32>> {
+    let pids: Vec<i32> = crate::macos::list_all_pids()?
+        .into_iter()
+        .filter(|pid| crate::macos::get_process_name(*pid).as_deref() == Some("sing-box"))
+        .collect();
+    if pids.is_empty() {
+        bail!("no sing-box process found, restart the client manually");
+    }
+    for pid in &pids {
+        if unsafe { libc::kill(*pid, libc::SIGHUP) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to signal sing-box (PID {}), try sudo", pid));
+        }
+    }
+    Ok(pids)
+}
+
+// ========================================
+// 查找控制器
+// ========================================
+
+/// 查找指定格式对应客户端的控制器，不支持自动重载的格式返回 None
+pub fn discover(format: OutputFormat, configured: &Controllers, home: &Path) -> Option<Controller> {
+    let api = match format {
+        OutputFormat::Clash => Api::Clash,
+        OutputFormat::Surge => Api::Surge,
+        OutputFormat::SingBox => Api::SingBox,
+        _ => return None,
+    };
+
+    if let Some(config) = configured.get(format) {
+        return Some(Controller {
+            api,
+            url: config.url.trim_end_matches('/').to_string(),
+            secret: config.secret.clone(),
+            source: "rules.toml".to_string(),
+        });
+    }
+
+    let from_file = match format {
+        OutputFormat::Clash => CLASH_CONFIG_FILES.iter().find_map(|file| {
+            let path = home.join(file);
+            let (addr, secret) = parse_clash_config(&std::fs::read_to_string(&path).ok()?)?;
+            Some((addr, secret, path))
+        }),
+        OutputFormat::Surge => surge_profiles(home).into_iter().find_map(|path| {
+            let (addr, key) = parse_surge_profile(&std::fs::read_to_string(&path).ok()?)?;
+            Some((addr, Some(key), path))
+        }),
+        OutputFormat::SingBox => SINGBOX_CONFIG_FILES.iter().find_map(|file| {
+            // join 绝对路径时直接使用该路径
+            let path = home.join(file);
+            let (addr, secret) = parse_singbox_config(&std::fs::read_to_string(&path).ok()?)?;
+            Some((addr, secret, path))
+        }),
+        _ => None,
+    };
+    if let Some((addr, secret, path)) = from_file {
+        return Some(Controller {
+            api,
+            url: format!("http://{}", local_addr(&addr)),
+            secret,
+            source: path.display().to_string(),
+        });
+    }
+
+    // 最后探测默认端口，按 /version 区分 mihomo 和 sing-box（设置了 secret 时无法区分，视为 mihomo）
+    if api == Api::Surge {
+        return None;
+    }
+    CLASH_CONTROLLER_PORTS.into_iter().find_map(|port| {
+        if fingerprint(port, Duration::from_millis(300)) != ListenerProtocol::ClashController {
+            return None;
+        }
+        let controller = Controller {
+            api,
+            url: format!("http://127.0.0.1:{}", port),
+            secret: None,
+            source: format!("port {}", port),
+        };
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .no_proxy()
+            .build()
+            .ok()?;
+        let is_singbox = controller
+            .version(&client)
+            .is_ok_and(|v| parse_singbox_version(&v).is_some());
+        (is_singbox == (api == Api::SingBox)).then_some(controller)
+    })
+}
+
+/// 从 sing-box 配置中读取 `experimental.clash_api` 的 `external_controller` 和 `secret`
+fn parse_singbox_config(content: &str) -> Option<(String, Option<String>)> {
+    let config: serde_json::Value = serde_json::from_str(content).ok()?;
+    let api = &config["experimental"]["clash_api"];
+    let controller = api["external_controller"]
+        .as_str()
+        .filter(|c| !c.is_empty())?;
+    let secret = api["secret"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    Some((controller.to_string(), secret))
+}
+
+/// 从 mihomo 配置中读取 `external-controller` 和 `secret`（只看顶层键）
+fn parse_clash_config(content: &str) -> Option<(String, Option<String>)> {
+    let mut controller = None;
+    let mut secret = None;
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = unquote(value.split(" #").next().unwrap_or("").trim());
+        match key {
+            "external-controller" if !value.is_empty() => controller = Some(value.to_string()),
+            "secret" if !value.is_empty() => secret = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    controller.map(|c| (c, secret))
+}
+
+/// 从 Surge 配置中读取 `http-api = key@host:port`
+fn parse_surge_profile(content: &str) -> Option<(String, String)> {
+    content.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        if key.trim() != "http-api" {
+            return None;
+        }
+        let (api_key, addr) = value.trim().rsplit_once('@')?;
+        Some((addr.to_string(), api_key.to_string()))
+    })
+}
+
+/// Surge 配置文件列表
+fn surge_profiles(home: &Path) -> Vec<PathBuf> {
+    let mut profiles: Vec<PathBuf> = SURGE_PROFILE_DIRS
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(home.join(dir)).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+        .collect();
+    profiles.sort();
+    profiles
+}
+
+/// 监听地址转换为本机可访问的地址：`:9090`、`0.0.0.0:9090` → `127.0.0.1:9090`
+fn local_addr(addr: &str) -> String {
+    match addr.rsplit_once(':') {
+        Some(("" | "0.0.0.0" | "[::]" | "::", port)) => {
+            format!("127.0.0.1:{}", port)
+        }
+        _ => addr.to_string(),
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim_matches(|c| c == '\'' || c == '"')
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// 本地桩服务：依次用给定状态码应答，把 "METHOD PATH AUTH" 发回测试线程
+    fn stub(statuses: Vec<u16>) -> (String, mpsc::Receiver<String>) {
+        stub_responses(statuses.into_iter().map(|s| (s, "")).collect())
+    }
+
+    /// 本地桩服务：依次用给定状态码和 body 应答
+    fn stub_responses(responses: Vec<(u16, &'static str)>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (stream, (status, body)) in listener.incoming().flatten().zip(responses) {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut auth = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(": ").unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "authorization" | "x-key" => auth = value.to_string(),
+                        "content-length" => length = value.parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                let mut request_body = vec![0; length];
+                reader.read_exact(&mut request_body).unwrap();
+
+                let parts: Vec<&str> = request_line.split_whitespace().collect();
+                tx.send(format!("{} {} {}", parts[0], parts[1], auth))
+                    .unwrap();
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    fn controller(api: Api, url: String) -> Controller {
+        Controller {
+            api,
+            url,
+            secret: Some("s3cret".to_string()),
+            source: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_clash_refreshes_providers() {
+        let (url, rx) = stub(vec![204, 204]);
+        let providers = [
+            "proxy-audit-direct".to_string(),
+            "proxy-audit-proxy".to_string(),
+        ];
+        let result = controller(Api::Clash, url).reload(&providers).unwrap();
+        assert_eq!(result, "refreshed 2 rule provider(s)");
+        assert_eq!(
+            rx.recv().unwrap(),
+            "PUT /providers/rules/proxy-audit-direct Bearer s3cret"
+        );
+        assert_eq!(
+            rx.recv().unwrap(),
+            "PUT /providers/rules/proxy-audit-proxy Bearer s3cret"
+        );
+    }
+
+    #[test]
+    fn test_clash_falls_back_to_config_reload() {
+        let (url, rx) = stub(vec![404, 204]);
+        let providers = ["proxy-audit-direct".to_string()];
+        let result = controller(Api::Clash, url).reload(&providers).unwrap();
+        assert!(result.starts_with("reloaded config"));
+        rx.recv().unwrap();
+        assert_eq!(rx.recv().unwrap(), "PUT /configs?force=true Bearer s3cret");
+
+        let (url, _rx) = stub(vec![401]);
+        let err = controller(Api::Clash, url).reload(&providers).unwrap_err();
+        assert!(err.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn test_surge_reload() {
+        let (url, rx) = stub(vec![200]);
+        controller(Api::Surge, url).reload(&[]).unwrap();
+        assert_eq!(rx.recv().unwrap(), "POST /v1/profiles/reload s3cret");
+    }
+
+    #[test]
+    fn test_parse_client_configs() {
+        let clash =
+            "mixed-port: 7890\nexternal-controller: '0.0.0.0:9090' # api\nsecret: \"abc\"\n";
+        let (addr, secret) = parse_clash_config(clash).unwrap();
+        assert_eq!(local_addr(&addr), "127.0.0.1:9090");
+        assert_eq!(secret.as_deref(), Some("abc"));
+        assert!(parse_clash_config("mixed-port: 7890\n").is_none());
+
+        let surge = "[General]\nhttp-api = my-key@0.0.0.0:6171\n";
+        let (addr, key) = parse_surge_profile(surge).unwrap();
+        assert_eq!(local_addr(&addr), "127.0.0.1:6171");
+        assert_eq!(key, "my-key");
+    }
+
+    #[test]
+    fn test_singbox_reload() {
+        // 1.10 起监视规则集文件，只查询版本，不做其他操作
+        let (url, rx) = stub_responses(vec![(
+            200,
+            r#"{"meta":true,"premium":true,"version":"sing-box 1.10.2"}"#,
+        )]);
+        let result = controller(Api::SingBox, url).reload(&[]).unwrap();
+        assert!(result.contains("nothing to do"), "{}", result);
+        assert_eq!(rx.recv().unwrap(), "GET /version Bearer s3cret");
+
+        // mihomo 的控制器不能当作 sing-box 使用
+        let (url, _rx) = stub_responses(vec![(200, r#"{"meta":true,"version":"v1.18.10"}"#)]);
+        let err = controller(Api::SingBox, url).reload(&[]).unwrap_err();
+        assert!(err.to_string().contains("not a sing-box controller"));
+
+        assert_eq!(parse_singbox_version("sing-box 1.9.3"), Some((1, 9)));
+        assert_eq!(
+            parse_singbox_version("sing-box 1.10.0-beta.3"),
+            Some((1, 10))
+        );
+        assert!(parse_singbox_version("sing-box 1.9.3").unwrap() < SINGBOX_WATCH_VERSION);
+        assert_eq!(parse_singbox_version("v1.18.10"), None);
+    }
+
+    #[test]
+    fn test_discover_singbox() {
+        let config = r#"{"experimental": {"clash_api": {"external_controller": "0.0.0.0:9090", "secret": "abc"}}}"#;
+        let (addr, secret) = parse_singbox_config(config).unwrap();
+        assert_eq!(local_addr(&addr), "127.0.0.1:9090");
+        assert_eq!(secret.as_deref(), Some("abc"));
+        assert!(parse_singbox_config(r#"{"inbounds": []}"#).is_none());
+
+        let configured = Controllers {
+            singbox: Some(ControllerConfig {
+                url: "http://127.0.0.1:9091/".to_string(),
+                secret: None,
+            }),
+            ..Default::default()
+        };
+        let controller = discover(
+            OutputFormat::SingBox,
+            &configured,
+            Path::new("/nonexistent"),
+        )
+        .unwrap();
+        assert_eq!(controller.api, Api::SingBox);
+        assert_eq!(controller.url, "http://127.0.0.1:9091");
+        assert!(discover(OutputFormat::Loon, &configured, Path::new("/nonexistent")).is_none());
+    }
+}
@@ -0,0 +1,668 @@
+//! # sing-box 二进制规则集 (.srs)
+//!
+//! 与 sing-box `common/srs` 的编码保持一致，可作为 `"format": "binary"` 的 local / remote 规则集加载。
+//!
+//! ## 文件结构
+//! ```text
+//! "SRS" | version (u8) | zlib(
+//!     uvarint 规则数
+//!     每条规则: 0x00 (default) | 规则项... | 0xFF | invert (u8)
+//! )
+//! ```
+//! 规则项以类型字节开头：
+//! - 字符串列表 (process_name 0x0B、process_path 0x0C): uvarint 个数，每项 uvarint 长度 + UTF-8
+//! - ip_cidr (0x06): IP 集合，版本 0x01 | u64 (大端) 区间数 | 每个区间的起止地址（uvarint 长度 + 字节）
+//! - domain / domain_suffix (0x02): 域名集合，版本 0x00 | leaves | label 位图 | labels
+//!
+//! ## 域名集合
+//! 所有域名按字符反转（`example.com` -> `moc.elpmaxe`）后排序，构成一棵按层序编码的 succinct trie：
+//! - labels: 按层序排列的每条边的字节
+//! - label 位图 (u64 数组): 每个节点的边依次记为 0，节点结束记为 1
+//! - leaves (u64 数组): 第 i 个节点是否为一个完整的键
+//!
+//! 两个位图都写为 uvarint 个数 + 大端 u64，labels 写为 uvarint 长度 + 字节。
+//! domain_suffix 在键末尾追加标记：以 `.` 开头的后缀加 `\r`（只匹配子域名），
+//! 其他后缀在版本 2 起加 `\n`（匹配域名本身及子域名），
+//! 版本 1 拆成完整域名和 `.` 开头的后缀两个键。
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use ipnet::{IpNet, Ipv4Subnets, Ipv6Subnets};
+
+use super::formatter::{Rule, RulePolicy, RuleType};
+use super::manager::parse_net;
+
+/// 文件头魔数
+pub const MAGIC: &[u8; 3] = b"SRS";
+
+/// 支持的规则集版本（sing-box 1.8 / 1.10 / 1.11）
+pub const MAX_VERSION: u8 = 3;
+
+/// 规则类型：普通规则
+const RULE_TYPE_DEFAULT: u8 = 0;
+
+/// 规则项类型
+const ITEM_DOMAIN: u8 = 2;
+const ITEM_IP_CIDR: u8 = 6;
+const ITEM_PROCESS_NAME: u8 = 11;
+const ITEM_PROCESS_PATH: u8 = 12;
+const ITEM_FINAL: u8 = 0xFF;
+
+/// IP 集合的编码版本
+const IP_SET_VERSION: u8 = 1;
+
+/// 域名集合的编码版本
+const DOMAIN_SET_VERSION: u8 = 0;
+
+/// 域名键的结尾标记：只匹配子域名（以 `.` 开头的 domain_suffix）
+const DOMAIN_PREFIX_LABEL: u8 = b'\r';
+
+/// 域名键的结尾标记：匹配域名本身及子域名（版本 2 起的 domain_suffix）
+const DOMAIN_ROOT_LABEL: u8 = b'\n';
+
+// ========================================
+// 编码
+// ========================================
+
+/// 把规则编码为 .srs 文件
+///
+/// 同一类型的规则合并为一条 headless 规则（sing-box 中不同类型的规则项是“与”的关系），
+/// DOMAIN 和 DOMAIN-SUFFIX 与 sing-box 一样合并为同一个域名规则项。
+pub fn encode(rules: &[Rule], version: u8) -> Vec<u8> {
+    let domains = domain_keys(rules, version);
+    let names = values_of(rules, RuleType::ProcessName);
+    let paths = values_of(rules, RuleType::ProcessPath);
+    let nets: Vec<IpNet> = rules
+        .iter()
+        .filter(|r| matches!(r.rule_type, RuleType::IpCidr | RuleType::IpCidr6))
+        .filter_map(|r| parse_net(&r.value))
+        .collect();
+
+    let mut body = Vec::new();
+    let count = [
+        !domains.is_empty(),
+        !names.is_empty(),
+        !paths.is_empty(),
+        !nets.is_empty(),
+    ]
+    .iter()
+    .filter(|present| **present)
+    .count();
+    write_uvarint(&mut body, count as u64);
+    if !domains.is_empty() {
+        body.push(RULE_TYPE_DEFAULT);
+        body.push(ITEM_DOMAIN);
+        write_domain_set(&mut body, &domains);
+        finish_rule(&mut body);
+    }
+    if !names.is_empty() {
+        write_string_rule(&mut body, ITEM_PROCESS_NAME, &names);
+    }
+    if !paths.is_empty() {
+        write_string_rule(&mut body, ITEM_PROCESS_PATH, &paths);
+    }
+    if !nets.is_empty() {
+        body.push(RULE_TYPE_DEFAULT);
+        body.push(ITEM_IP_CIDR);
+        write_ip_set(&mut body, nets);
+        finish_rule(&mut body);
+    }
+
+    let mut out = Vec::with_capacity(body.len() / 2 + 4);
+    out.extend_from_slice(MAGIC);
+    out.push(version);
+    let mut encoder = ZlibEncoder::new(out, Compression::best());
+    // 写入内存缓冲区不会失败
+    encoder.write_all(&body).expect("write to Vec");
+    encoder.finish().expect("write to Vec")
+}
+
+/// 指定类型的规则值
+fn values_of(rules: &[Rule], rule_type: RuleType) -> Vec<&str> {
+    rules
+        .iter()
+        .filter(|r| r.rule_type == rule_type)
+        .map(|r| r.value.as_str())
+        .collect()
+}
+
+/// 写入只有一个字符串列表项的规则
+fn write_string_rule(buf: &mut Vec<u8>, item: u8, values: &[&str]) {
+    buf.push(RULE_TYPE_DEFAULT);
+    buf.push(item);
+    write_uvarint(buf, values.len() as u64);
+    for value in values {
+        write_bytes(buf, value.as_bytes());
+    }
+    finish_rule(buf);
+}
+
+/// 域名规则转换为排序去重后的反转键（与 sing-box `domain.NewMatcher` 相同）
+fn domain_keys(rules: &[Rule], version: u8) -> Vec<Vec<u8>> {
+    let mut keys = Vec::new();
+    for rule in rules.iter().filter(|r| !r.value.is_empty()) {
+        let value = rule.value.as_str();
+        match rule.rule_type {
+            RuleType::Domain => keys.push(reverse_domain(value)),
+            RuleType::DomainSuffix if value.starts_with('.') => {
+                keys.push(with_label(reverse_domain(value), DOMAIN_PREFIX_LABEL));
+            }
+            RuleType::DomainSuffix if version == 1 => {
+                keys.push(reverse_domain(value));
+                let suffix = format!(".{}", value);
+                keys.push(with_label(reverse_domain(&suffix), DOMAIN_PREFIX_LABEL));
+            }
+            RuleType::DomainSuffix => {
+                keys.push(with_label(reverse_domain(value), DOMAIN_ROOT_LABEL));
+            }
+            _ => {}
+        }
+    }
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// 按字符反转域名
+fn reverse_domain(domain: &str) -> Vec<u8> {
+    domain.chars().rev().collect::<String>().into_bytes()
+}
+
+fn with_label(mut key: Vec<u8>, label: u8) -> Vec<u8> {
+    key.push(label);
+    key
+}
+
+/// 写入域名集合：按层序遍历排好序的键构建 succinct trie
+fn write_domain_set(buf: &mut Vec<u8>, keys: &[Vec<u8>]) {
+    let mut leaves = Vec::new();
+    let mut label_bitmap = Vec::new();
+    let mut labels = Vec::new();
+    let mut bit = 0;
+
+    // 每个节点覆盖的键区间 [start, end) 和所在的列
+    let mut queue = vec![(0, keys.len(), 0)];
+    let mut node = 0;
+    while node < queue.len() {
+        let (mut start, end, col) = queue[node];
+        // 排序后最短的键在最前，长度等于列时该节点是一个完整的键
+        if col == keys[start].len() {
+            start += 1;
+            set_bit(&mut leaves, node);
+        }
+        let mut j = start;
+        while j < end {
+            let from = j;
+            while j < end && keys[j][col] == keys[from][col] {
+                j += 1;
+            }
+            queue.push((from, j, col + 1));
+            labels.push(keys[from][col]);
+            bit += 1;
+        }
+        set_bit(&mut label_bitmap, bit);
+        bit += 1;
+        node += 1;
+    }
+
+    buf.push(DOMAIN_SET_VERSION);
+    write_u64s(buf, &leaves);
+    write_u64s(buf, &label_bitmap);
+    write_bytes(buf, &labels);
+}
+
+fn set_bit(bitmap: &mut Vec<u64>, i: usize) {
+    if bitmap.len() <= i / 64 {
+        bitmap.resize(i / 64 + 1, 0);
+    }
+    bitmap[i / 64] |= 1 << (i % 64);
+}
+
+fn get_bit(bitmap: &[u64], i: usize) -> bool {
+    bitmap
+        .get(i / 64)
+        .is_some_and(|word| word & (1 << (i % 64)) != 0)
+}
+
+fn write_u64s(buf: &mut Vec<u8>, values: &[u64]) {
+    write_uvarint(buf, values.len() as u64);
+    for value in values {
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// 规则结束标记，后跟 invert = false
+fn finish_rule(buf: &mut Vec<u8>) {
+    buf.push(ITEM_FINAL);
+    buf.push(0);
+}
+
+/// 写入 IP 集合：网段转换为合并后的地址区间，IPv4 在前
+fn write_ip_set(buf: &mut Vec<u8>, nets: Vec<IpNet>) {
+    let mut ranges: Vec<(IpAddr, IpAddr)> = IpNet::aggregate(&nets)
+        .into_iter()
+        .map(|net| (net.network(), net.broadcast()))
+        .collect();
+    ranges.sort();
+
+    // aggregate 之后仍可能有首尾相接但无法合并为单个网段的区间
+    let mut merged: Vec<(IpAddr, IpAddr)> = Vec::new();
+    for (from, to) in ranges {
+        match merged.last_mut() {
+            Some(last) if next_addr(last.1) == Some(from) => last.1 = to,
+            _ => merged.push((from, to)),
+        }
+    }
+
+    buf.push(IP_SET_VERSION);
+    buf.extend_from_slice(&(merged.len() as u64).to_be_bytes());
+    for (from, to) in merged {
+        write_bytes(buf, &addr_bytes(from));
+        write_bytes(buf, &addr_bytes(to));
+    }
+}
+
+/// 同一地址族中的下一个地址
+fn next_addr(addr: IpAddr) -> Option<IpAddr> {
+    match addr {
+        IpAddr::V4(v4) => u32::from(v4)
+            .checked_add(1)
+            .map(|n| IpAddr::V4(Ipv4Addr::from(n))),
+        IpAddr::V6(v6) => u128::from(v6)
+            .checked_add(1)
+            .map(|n| IpAddr::V6(Ipv6Addr::from(n))),
+    }
+}
+
+fn addr_bytes(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(v4) => v4.octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
+
+fn write_uvarint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_uvarint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+// ========================================
+// 解码
+// ========================================
+
+/// 文件内容是否为 .srs 格式
+pub fn is_srs(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// 解析 .srs 文件，返回其中的域名、进程和 IP 规则
+///
+/// 只支持本工具会生成的规则项，遇到关键字、端口等其他规则项时返回错误，
+/// 以免把部分内容当作完整的规则集。IP 集合按区间还原为网段，相邻网段可能已被合并。
+pub fn decode(bytes: &[u8], policy: RulePolicy) -> Result<Vec<Rule>> {
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        bail!("not a sing-box binary rule set (missing SRS header)");
+    };
+    let Some((&version, compressed)) = rest.split_first() else {
+        bail!("truncated rule set header");
+    };
+    if version == 0 || version > MAX_VERSION {
+        bail!("unsupported rule set version {}", version);
+    }
+
+    let mut body = Vec::new();
+    ZlibDecoder::new(compressed)
+        .read_to_end(&mut body)
+        .context("Failed to decompress rule set")?;
+    let mut reader = Reader { buf: &body };
+
+    let mut rules = Vec::new();
+    let count = reader.uvarint()?;
+    for _ in 0..count {
+        let rule_type = reader.byte()?;
+        if rule_type != RULE_TYPE_DEFAULT {
+            bail!("logical rules are not supported");
+        }
+        loop {
+            match reader.byte()? {
+                ITEM_FINAL => {
+                    if reader.byte()? != 0 {
+                        bail!("inverted rules are not supported");
+                    }
+                    break;
+                }
+                ITEM_PROCESS_NAME => {
+                    rules.extend(reader.strings(RuleType::ProcessName, policy)?);
+                }
+                ITEM_PROCESS_PATH => {
+                    rules.extend(reader.strings(RuleType::ProcessPath, policy)?);
+                }
+                ITEM_IP_CIDR => rules.extend(reader.ip_set(policy)?),
+                ITEM_DOMAIN => rules.extend(reader.domain_set(policy)?),
+                other => bail!("unsupported rule item type {}", other),
+            }
+        }
+    }
+    Ok(rules)
+}
+
+/// 解压后数据的顺序读取器
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8> {
+        let (&b, rest) = self
+            .buf
+            .split_first()
+            .context("unexpected end of rule set")?;
+        self.buf = rest;
+        Ok(b)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        if self.buf.len() < len {
+            bail!("unexpected end of rule set");
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    fn uvarint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            value |= u64::from(b & 0x7F) << shift;
+            if b < 0x80 {
+                return Ok(value);
+            }
+        }
+        bail!("uvarint overflow")
+    }
+
+    fn bytes(&mut self) -> Result<&[u8]> {
+        let len = self.uvarint()? as usize;
+        self.take(len)
+    }
+
+    fn strings(&mut self, rule_type: RuleType, policy: RulePolicy) -> Result<Vec<Rule>> {
+        let count = self.uvarint()?;
+        let mut rules = Vec::new();
+        for _ in 0..count {
+            let value = std::str::from_utf8(self.bytes()?).context("invalid UTF-8 in rule set")?;
+            rules.push(Rule {
+                rule_type: rule_type.clone(),
+                value: value.to_string(),
+                policy,
+            });
+        }
+        Ok(rules)
+    }
+
+    fn u64s(&mut self) -> Result<Vec<u64>> {
+        let count = self.uvarint()? as usize;
+        let bytes = self.take(count.checked_mul(8).context("invalid bitmap length")?)?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_be_bytes(chunk.try_into().expect("8-byte chunk")))
+            .collect())
+    }
+
+    /// 还原域名集合中的键
+    ///
+    /// 版本 1 的后缀被拆成完整域名和 `.` 开头的后缀，两者都存在时合并回一条 DOMAIN-SUFFIX。
+    fn domain_set(&mut self, policy: RulePolicy) -> Result<Vec<Rule>> {
+        let version = self.byte()?;
+        if version != DOMAIN_SET_VERSION {
+            bail!("unsupported domain set version {}", version);
+        }
+        let leaves = self.u64s()?;
+        let label_bitmap = self.u64s()?;
+        let labels = self.bytes()?;
+
+        // 按层序遍历：位图中的 0 是当前节点的一条边（子节点依次编号），1 表示当前节点结束
+        let mut prefixes: Vec<Vec<u8>> = vec![Vec::new()];
+        let (mut node, mut label) = (0, 0);
+        for bit in 0..label_bitmap.len() * 64 {
+            if node >= prefixes.len() {
+                break;
+            }
+            if get_bit(&label_bitmap, bit) {
+                node += 1;
+                continue;
+            }
+            let &byte = labels.get(label).context("corrupt domain set")?;
+            label += 1;
+            let key = with_label(prefixes[node].clone(), byte);
+            prefixes.push(key);
+        }
+
+        let mut domains = Vec::new();
+        let mut suffixes = Vec::new();
+        let mut subdomains = Vec::new();
+        for (i, key) in prefixes.into_iter().enumerate() {
+            if !get_bit(&leaves, i) {
+                continue;
+            }
+            let (list, key) = match key.split_last() {
+                Some((&DOMAIN_PREFIX_LABEL, rest)) => (&mut subdomains, rest.to_vec()),
+                Some((&DOMAIN_ROOT_LABEL, rest)) => (&mut suffixes, rest.to_vec()),
+                _ => (&mut domains, key),
+            };
+            let key = String::from_utf8(key).context("invalid UTF-8 in rule set")?;
+            list.push(key.chars().rev().collect::<String>());
+        }
+        for suffix in subdomains {
+            let bare = suffix
+                .strip_prefix('.')
+                .and_then(|bare| domains.iter().position(|d| d == bare));
+            match bare {
+                Some(index) => suffixes.push(domains.remove(index)),
+                None => suffixes.push(suffix),
+            }
+        }
+
+        let rule = |rule_type: RuleType, value: String| Rule {
+            rule_type,
+            value,
+            policy,
+        };
+        Ok(domains
+            .into_iter()
+            .map(|d| rule(RuleType::Domain, d))
+            .chain(
+                suffixes
+                    .into_iter()
+                    .map(|s| rule(RuleType::DomainSuffix, s)),
+            )
+            .collect())
+    }
+
+    fn ip_set(&mut self, policy: RulePolicy) -> Result<Vec<Rule>> {
+        let version = self.byte()?;
+        if version != IP_SET_VERSION {
+            bail!("unsupported IP set version {}", version);
+        }
+        let count = u64::from_be_bytes(self.take(8)?.try_into()?);
+
+        let mut rules = Vec::new();
+        for _ in 0..count {
+            let from = self.bytes()?.to_vec();
+            let to = self.bytes()?.to_vec();
+            let nets: Vec<IpNet> = match (from.len(), to.len()) {
+                (4, 4) => Ipv4Subnets::new(
+                    Ipv4Addr::from(<[u8; 4]>::try_from(from.as_slice())?),
+                    Ipv4Addr::from(<[u8; 4]>::try_from(to.as_slice())?),
+                    0,
+                )
+                .map(IpNet::V4)
+                .collect(),
+                (16, 16) => Ipv6Subnets::new(
+                    Ipv6Addr::from(<[u8; 16]>::try_from(from.as_slice())?),
+                    Ipv6Addr::from(<[u8; 16]>::try_from(to.as_slice())?),
+                    0,
+                )
+                .map(IpNet::V6)
+                .collect(),
+                _ => bail!("invalid IP range in rule set"),
+            };
+            rules.extend(nets.into_iter().map(|net| Rule {
+                rule_type: match net {
+                    IpNet::V4(_) => RuleType::IpCidr,
+                    IpNet::V6(_) => RuleType::IpCidr6,
+                },
+                value: net.to_string(),
+                policy,
+            }));
+        }
+        Ok(rules)
+    }
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(rule_type: RuleType, value: &str) -> Rule {
+        Rule {
+            rule_type,
+            value: value.to_string(),
+            policy: RulePolicy::Proxy,
+        }
+    }
+
+    #[test]
+    fn test_encode_layout() {
+        let bytes = encode(&[rule(RuleType::ProcessName, "curl")], 3);
+        assert_eq!(&bytes[..4], b"SRS\x03");
+
+        let mut body = Vec::new();
+        ZlibDecoder::new(&bytes[4..])
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"\x01\x00\x0b\x01\x04curl\xff\x00");
+
+        let bytes = encode(&[rule(RuleType::IpCidr, "1.2.3.4")], 1);
+        let mut body = Vec::new();
+        ZlibDecoder::new(&bytes[4..])
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(
+            body,
+            b"\x01\x00\x06\x01\0\0\0\0\0\0\0\x01\x04\x01\x02\x03\x04\x04\x01\x02\x03\x04\xff\x00"
+        );
+
+        // 键 "b\n" 和 "ba"：根节点一条边 b，b 节点两条边 \n、a，两个子节点都是完整的键
+        let bytes = encode(
+            &[
+                rule(RuleType::Domain, "ab"),
+                rule(RuleType::DomainSuffix, "b"),
+            ],
+            2,
+        );
+        let mut body = Vec::new();
+        ZlibDecoder::new(&bytes[4..])
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(
+            body,
+            b"\x01\x00\x02\x00\x01\0\0\0\0\0\0\0\x0c\x01\0\0\0\0\0\0\0\x72\x03b\na\xff\x00"
+        );
+    }
+
+    #[test]
+    fn test_domain_round_trip() {
+        let rules = [
+            rule(RuleType::Domain, "api.telegram.org"),
+            rule(RuleType::DomainSuffix, "telegram.org"),
+            rule(RuleType::DomainSuffix, ".t.me"),
+            rule(RuleType::DomainSuffix, "例子.测试"),
+        ];
+        // 版本 1 把后缀拆成两个键，读回时合并
+        for version in 1..=MAX_VERSION {
+            let decoded = decode(&encode(&rules, version), RulePolicy::Direct).unwrap();
+            let mut values: Vec<(RuleType, &str)> = decoded
+                .iter()
+                .map(|r| (r.rule_type.clone(), r.value.as_str()))
+                .collect();
+            values.sort_by(|a, b| a.1.cmp(b.1));
+            assert_eq!(
+                values,
+                [
+                    (RuleType::DomainSuffix, ".t.me"),
+                    (RuleType::Domain, "api.telegram.org"),
+                    (RuleType::DomainSuffix, "telegram.org"),
+                    (RuleType::DomainSuffix, "例子.测试"),
+                ],
+                "version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let rules = [
+            rule(RuleType::ProcessName, "Telegram"),
+            rule(
+                RuleType::ProcessPath,
+                "/Applications/Slack.app/Contents/MacOS/Slack",
+            ),
+            rule(RuleType::IpCidr, "91.108.56.0/24"),
+            rule(RuleType::IpCidr, "149.154.167.51"),
+            rule(RuleType::IpCidr6, "2001:67c:4e8::/48"),
+        ];
+        let decoded = decode(&encode(&rules, 2), RulePolicy::Proxy).unwrap();
+        let values: Vec<(RuleType, &str)> = decoded
+            .iter()
+            .map(|r| (r.rule_type.clone(), r.value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (RuleType::ProcessName, "Telegram"),
+                (
+                    RuleType::ProcessPath,
+                    "/Applications/Slack.app/Contents/MacOS/Slack"
+                ),
+                (RuleType::IpCidr, "91.108.56.0/24"),
+                (RuleType::IpCidr, "149.154.167.51/32"),
+                (RuleType::IpCidr6, "2001:67c:4e8::/48"),
+            ]
+        );
+        assert!(decoded.iter().all(|r| r.policy == RulePolicy::Proxy));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(decode(b"{\"version\": 1}", RulePolicy::Direct).is_err());
+        assert!(decode(b"SRS\x09", RulePolicy::Direct).is_err());
+        assert!(decode(b"SRS\x01garbage", RulePolicy::Direct).is_err());
+
+        // 不支持的规则项（domain_keyword）给出错误而不是丢弃
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        let mut encoder = ZlibEncoder::new(bytes, Compression::best());
+        encoder.write_all(b"\x01\x00\x03\x00").unwrap();
+        let err = decode(&encoder.finish().unwrap(), RulePolicy::Direct).unwrap_err();
+        assert!(err.to_string().contains("unsupported rule item type 3"));
+    }
+}
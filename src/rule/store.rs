@@ -0,0 +1,227 @@
+//! # 规则主存储
+//!
+//! 所有客户端格式共用一个 `rules.toml` 作为唯一数据源，
+//! 各格式的 `rules-{direct,proxy,reject}.*` 文件都由它渲染生成，
+//! 同时使用 Surge 和 mihomo 的用户不会再出现两套规则互相偏离。
+//!
+//! ## 文件格式
+//! ```toml
+//! # 启用的输出格式，每次修改后重新渲染
+//! formats = ["clash", "surge"]
+//!
+//! # Clash provider 格式 (yaml / text) 与拆分方式 (classical / split)
+//! [clash]
+//! format = "text"
+//! layout = "split"
+//!
+//! # sing-box 规则集格式 (source / binary) 与版本
+//! [sing-box]
+//! format = "binary"
+//! version = 3
+//!
+//! # 代理客户端控制器（`--reload` 使用，可省略，见 reload 模块）
+//! [controller.clash]
+//! url = "http://127.0.0.1:9090"
+//! secret = "xxx"
+//!
+//! [[rule]]
+//! type = "PROCESS-NAME"
+//! value = "Telegram"
+//! policy = "PROXY"
+//! note = "长连接，修改后需重启应用"
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::formatter::{ClashOptions, OutputFormat, Rule, RulePolicy, SingBoxOptions};
+use super::manager::RuleSelector;
+use super::reload::Controllers;
+
+/// 主存储文件名（位于配置目录下）
+pub const STORE_FILE: &str = "rules.toml";
+
+/// 规则主存储
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleStore {
+    /// 启用的输出格式
+    #[serde(default)]
+    pub formats: Vec<OutputFormat>,
+    /// Clash 输出选项
+    #[serde(default)]
+    pub clash: ClashOptions,
+    /// sing-box 输出选项
+    #[serde(default, rename = "sing-box")]
+    pub singbox: SingBoxOptions,
+    /// 代理客户端控制器地址
+    #[serde(default, skip_serializing_if = "Controllers::is_empty")]
+    pub controller: Controllers,
+    /// 全部规则，保持添加顺序
+    #[serde(default, rename = "rule", skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<StoredRule>,
+}
+
+/// 主存储中的单条规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredRule {
+    /// 规则本身（类型、值、策略）
+    #[serde(flatten)]
+    pub rule: Rule,
+    /// 备注
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl RuleStore {
+    /// 从文件加载，文件不存在时返回 None
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let store = toml::from_str(&content)
+            .with_context(|| format!("Invalid rule store: {}", path.display()))?;
+        Ok(Some(store))
+    }
+
+    /// 序列化为 TOML 文本
+    pub fn to_toml(&self) -> Result<String> {
+        let body = toml::to_string(self).context("Failed to serialize rule store")?;
+        Ok(format!(
+            "# proxy-audit rule store: 所有客户端格式的规则文件都由此生成\n{}",
+            body
+        ))
+    }
+
+    /// 启用输出格式，返回是否新启用
+    pub fn enable(&mut self, format: OutputFormat) -> bool {
+        if self.formats.contains(&format) {
+            return false;
+        }
+        self.formats.push(format);
+        true
+    }
+
+    /// 添加或覆盖规则（类型和值相同即视为同一条），返回原来的策略
+    ///
+    /// 新的备注为空时保留原备注。
+    pub fn upsert(&mut self, rule: Rule, note: Option<String>) -> Option<RulePolicy> {
+        if let Some(existing) = self
+            .rules
+            .iter_mut()
+            .find(|s| s.rule.rule_type == rule.rule_type && s.rule.value == rule.value)
+        {
+            let previous = existing.rule.policy;
+            existing.rule.policy = rule.policy;
+            if note.is_some() {
+                existing.note = note;
+            }
+            return Some(previous);
+        }
+        self.rules.push(StoredRule { rule, note });
+        None
+    }
+
+    /// 删除选中的规则，返回被删除的规则
+    pub fn remove(&mut self, selector: &RuleSelector) -> Vec<StoredRule> {
+        let (removed, kept) = self
+            .rules
+            .drain(..)
+            .partition(|s| selector.matches(&s.rule));
+        self.rules = kept;
+        removed
+    }
+
+    /// 把选中的规则改为指定策略，返回策略发生变化的规则（变化前）
+    pub fn set_policy(&mut self, selector: &RuleSelector, to: RulePolicy) -> Vec<StoredRule> {
+        let mut changed = Vec::new();
+        for stored in &mut self.rules {
+            if selector.matches(&stored.rule) && stored.rule.policy != to {
+                changed.push(stored.clone());
+                stored.rule.policy = to;
+            }
+        }
+        changed
+    }
+
+    /// 指定策略的规则
+    pub fn rules_for(&self, policy: RulePolicy) -> Vec<Rule> {
+        self.rules
+            .iter()
+            .filter(|s| s.rule.policy == policy)
+            .map(|s| s.rule.clone())
+            .collect()
+    }
+}
+
+// ========================================
+// 测试模块
+// ========================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::formatter::RuleType;
+
+    fn rule(rule_type: RuleType, value: &str, policy: RulePolicy) -> Rule {
+        Rule {
+            rule_type,
+            value: value.to_string(),
+            policy,
+        }
+    }
+
+    #[test]
+    fn test_toml_round_trip() {
+        let mut store = RuleStore::default();
+        store.enable(OutputFormat::Clash);
+        store.enable(OutputFormat::SingBox);
+        assert!(!store.enable(OutputFormat::Clash));
+        store.upsert(
+            rule(RuleType::ProcessName, "Telegram", RulePolicy::Proxy),
+            Some("needs restart".to_string()),
+        );
+        store.upsert(
+            rule(RuleType::IpCidr, "1.2.3.4/32", RulePolicy::Direct),
+            None,
+        );
+
+        let text = store.to_toml().unwrap();
+        assert!(text.contains(r#"formats = ["clash", "sing-box"]"#));
+        assert!(text.contains(r#"type = "PROCESS-NAME""#));
+
+        let parsed: RuleStore = toml::from_str(&text).unwrap();
+        assert_eq!(parsed.formats, store.formats);
+        assert_eq!(parsed.rules.len(), 2);
+        assert_eq!(parsed.rules[0].rule.value, "Telegram");
+        assert_eq!(parsed.rules[0].note.as_deref(), Some("needs restart"));
+        assert_eq!(parsed.rules[1].rule.rule_type, RuleType::IpCidr);
+        assert_eq!(parsed.rules[1].note, None);
+    }
+
+    #[test]
+    fn test_upsert_and_set_policy() {
+        let mut store = RuleStore::default();
+        store.upsert(
+            rule(RuleType::ProcessName, "Telegram", RulePolicy::Direct),
+            Some("note".to_string()),
+        );
+        let previous = store.upsert(
+            rule(RuleType::ProcessName, "Telegram", RulePolicy::Proxy),
+            None,
+        );
+        assert_eq!(previous, Some(RulePolicy::Direct));
+        assert_eq!(store.rules.len(), 1);
+        assert_eq!(store.rules[0].note.as_deref(), Some("note"));
+
+        let selector = RuleSelector::Name("Telegram".to_string());
+        assert_eq!(store.set_policy(&selector, RulePolicy::Proxy).len(), 0);
+        assert_eq!(store.set_policy(&selector, RulePolicy::Reject).len(), 1);
+        assert_eq!(store.rules_for(RulePolicy::Reject).len(), 1);
+        assert_eq!(store.remove(&selector).len(), 1);
+        assert!(store.rules.is_empty());
+    }
+}
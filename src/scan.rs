@@ -3,8 +3,10 @@
 //! 这个模块是核心业务逻辑所在，负责：
 //! 1. 扫描所有进程及其 socket 连接
 //! 2. 判断每个进程的代理使用模式
-//! 3. 可选：探测本地代理的出口 IP
-//! 4. 可选：使用 GeoIP 查询出口国家
+//! 3. 可选：并发探测本地代理的出口 IP（结果缓存到磁盘）
+//! 4. 可选：探测直连出口 IP，标记出口与直连相同的代理
+//! 5. 可选：使用 GeoIP 查询出口国家和运营商（ASN）
+//! 6. 可选：使用 GeoIP 统计每个进程的连接目的地分布
 //!
 //! ## 代理模式判定逻辑（按优先级）
 //! 1. SYSTEM_PROXY: 连接目标 IP:port 匹配系统代理服务器
@@ -14,11 +16,12 @@
 
 use std::collections::HashMap;
 use std::net::IpAddr;
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::geo::GeoDb;
+use crate::geo::{GeoDb, GeoResult};
 use crate::macos::{self, SocketInfo};
-use crate::proxy::{self, SystemProxy};
+use crate::proxy::exit::{self, ExitCache, ExitRoute};
+use crate::proxy::{self, ListenerProtocol, SystemProxy};
 use anyhow::Result;
 use rayon::prelude::*;
 use serde::Serialize;
@@ -28,7 +31,7 @@ use serde::Serialize;
 // ========================================
 
 /// 进程的代理使用模式
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, clap::ValueEnum)]
 pub enum ProxyMode {
     /// 通过系统代理（HTTP/HTTPS/SOCKS）
     SystemProxy,
@@ -70,14 +73,97 @@ pub struct ProcessResult {
     /// 代理详情（如代理地址和端口）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy: Option<String>,
-    /// 出口国家/地区
+    /// 出口国家/地区（命中自定义网段标签时为标签）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub country: Option<String>,
+    /// 出口 IP 命中的自定义网段标签
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// 出口城市级位置（需要 City 数据库）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// 出口纬度（需要 City 数据库）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    /// 出口经度（需要 City 数据库）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    /// 出口坐标精度半径（公里）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accuracy_radius: Option<u16>,
+    /// 出口运营商（如 "AS20473 AS-CHOOPA"，需要 ASN 数据库）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isp: Option<String>,
+    /// 出口 IP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_ip: Option<IpAddr>,
+    /// 出口 IP 与直连出口相同（代理失效或命中了 DIRECT 规则）
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub exit_is_direct: bool,
     /// 详细信息（如代理进程的 PID 和名称）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detail: Option<String>,
     /// 连接数量
     pub conns_count: usize,
+    /// 连接目的地分布（按 socket 数量降序）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub destinations: Vec<DestinationGroup>,
+}
+
+/// 一组相同归属地的连接目的地
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DestinationGroup {
+    /// 国家 ISO 代码；命中自定义网段标签时为标签，局域网地址为 "LAN"，查询不到为 "??"
+    pub country: String,
+    /// 自治系统编号（需要 ASN 数据库）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// 自治系统所属组织
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_org: Option<String>,
+    /// 该归属地的 socket 数量
+    pub sockets: usize,
+}
+
+impl std::fmt::Display for DestinationGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.asn {
+            Some(asn) => write!(f, "{} sockets {}/AS{}", self.sockets, self.country, asn),
+            None => write!(f, "{} sockets {}", self.sockets, self.country),
+        }
+    }
+}
+
+// ========================================
+// 本地监听端口结果
+// ========================================
+
+/// 单个本地监听端口的识别结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerInfo {
+    /// 监听端口
+    pub port: u16,
+    /// 监听进程 ID
+    pub pid: i32,
+    /// 监听进程名
+    pub name: String,
+    /// 握手识别出的协议
+    pub protocol: ListenerProtocol,
+    /// 猜测的代理客户端
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+/// 单个进程的代理模式判定结果（出口探测之前）
+struct Detection {
+    /// 代理模式
+    mode: ProxyMode,
+    /// 代理地址
+    proxy: Option<String>,
+    /// 详细信息
+    detail: Option<String>,
+    /// 需要探测出口的路径
+    exit_route: Option<ExitRoute>,
 }
 
 // ========================================
@@ -98,21 +184,25 @@ pub struct ScanContext {
     pub geo_db: Option<GeoDb>,
     /// 是否探测出口
     pub probe_exit: bool,
+    /// 忽略磁盘缓存，强制重新探测出口
+    pub refresh_exit: bool,
     /// 只显示走代理的进程
     pub only_routed: bool,
     /// 过滤目标 PID
     pub target_pid: Option<i32>,
     /// 调试模式
     pub debug: bool,
-    /// 出口 IP 缓存 (Proxy -> Exit IP)
-    /// 使用 Arc<Mutex> 以支持多线程并行扫描
-    pub exit_ip_cache: Arc<Mutex<HashMap<String, Option<IpAddr>>>>,
+    /// 物理网络接口（仅在默认路由为 VPN 时记录，用于直连探测绕过 TUN）
+    pub physical_iface: Option<String>,
+    /// 直连出口 IP（扫描完成后填充）
+    pub direct_exit: Option<IpAddr>,
 }
 
 impl ScanContext {
     pub fn new(
         geo_db: Option<GeoDb>,
         probe_exit: bool,
+        refresh_exit: bool,
         only_routed: bool,
         target_pid: Option<i32>,
         debug: bool,
@@ -125,6 +215,11 @@ impl ScanContext {
         let default_iface =
             proxy::get_default_route_interface().unwrap_or_else(|_| "unknown".to_string());
         let is_vpn = proxy::scutil::is_vpn_interface(&default_iface);
+        let physical_iface = if is_vpn {
+            proxy::scutil::get_physical_interface()
+        } else {
+            None
+        };
 
         // 3. (已移除) 打开 GeoIP 数据库逻辑已移至外部
 
@@ -138,10 +233,12 @@ impl ScanContext {
             listen_ports,
             geo_db, // 直接使用传入的实例
             probe_exit,
+            refresh_exit,
             only_routed,
             target_pid,
             debug,
-            exit_ip_cache: Arc::new(Mutex::new(HashMap::new())),
+            physical_iface,
+            direct_exit: None,
         })
     }
 
@@ -179,8 +276,8 @@ impl ScanContext {
 /// 1. 获取所有进程 PID
 /// 2. 构建本地监听端口映射
 /// 3. 遍历每个进程，获取 socket 连接
-/// 4. 判断代理模式
-/// 5. 可选：探测出口 IP
+/// 4. 判断代理模式，记录需要探测的代理端点
+/// 5. 可选：对去重后的代理端点并发探测出口 IP
 /// 6. 可选：GeoIP 查询
 ///
 /// ## 返回
@@ -192,8 +289,8 @@ pub fn scan_all_processes(ctx: &mut ScanContext) -> Result<Vec<ProcessResult>> {
     // 2. 构建监听端口映射
     ctx.build_listen_ports(&pids);
 
-    // 3. 扫描每个进程（并行）
-    let mut results: Vec<ProcessResult> = pids
+    // 3. 扫描每个进程（并行），同时记录每个进程需要探测的出口路径
+    let classified: Vec<(ProcessResult, Option<ExitRoute>)> = pids
         .into_par_iter()
         .filter_map(|pid| {
             // 如果指定了 PID，跳过不匹配的进程
@@ -237,38 +334,115 @@ pub fn scan_all_processes(ctx: &mut ScanContext) -> Result<Vec<ProcessResult>> {
             if sockets.is_empty() {
                 // 没有连接的进程
                 if !ctx.only_routed {
-                    return Some(ProcessResult {
+                    let result = ProcessResult {
                         pid,
                         name,
                         path,
                         mode: ProxyMode::Direct,
                         proxy: None,
                         country: None,
+                        label: None,
+                        city: None,
+                        latitude: None,
+                        longitude: None,
+                        accuracy_radius: None,
+                        isp: None,
+                        exit_ip: None,
+                        exit_is_direct: false,
                         detail: None,
                         conns_count: 0,
-                    });
+                        destinations: Vec::new(),
+                    };
+                    return Some((result, None));
                 }
                 return None;
             }
 
             // 判断代理模式
-            let (mode, proxy, detail, country) = determine_proxy_mode(ctx, &sockets);
+            let detection = determine_proxy_mode(ctx, &sockets);
 
             // 如果只显示走代理的，过滤掉 DIRECT
-            if ctx.only_routed && mode == ProxyMode::Direct {
+            if ctx.only_routed && detection.mode == ProxyMode::Direct {
                 return None;
             }
 
-            Some(ProcessResult {
+            let result = ProcessResult {
                 pid,
                 name,
                 path,
-                mode,
-                proxy,
-                country,
-                detail,
+                mode: detection.mode,
+                proxy: detection.proxy,
+                country: None,
+                label: None,
+                city: None,
+                latitude: None,
+                longitude: None,
+                accuracy_radius: None,
+                isp: None,
+                exit_ip: None,
+                exit_is_direct: false,
+                detail: detection.detail,
                 conns_count: sockets.len(),
-            })
+                destinations: summarize_destinations(ctx.geo_db.as_ref(), &sockets),
+            };
+            Some((result, detection.exit_route))
+        })
+        .collect();
+
+    // 4. 对去重后的出口路径并发探测（优先使用磁盘缓存）
+    //    有代理出口需要探测时，同时探测直连出口作为对照；
+    //    默认路由为 TUN 但找不到物理接口时，"直连" 请求也会经过 TUN，跳过对照
+    let direct_route = match (ctx.is_vpn, &ctx.physical_iface) {
+        (true, None) => {
+            if ctx.debug && ctx.probe_exit {
+                println!("DEBUG: no physical interface found, skipping direct exit probe");
+            }
+            None
+        }
+        (_, iface) => Some(ExitRoute::Direct(iface.clone())),
+    };
+    let exits = if ctx.probe_exit {
+        let mut routes: Vec<ExitRoute> = classified
+            .iter()
+            .filter_map(|(_, route)| route.clone())
+            .collect();
+        if let Some(direct_route) = direct_route.as_ref().filter(|_| !routes.is_empty()) {
+            routes.push(direct_route.clone());
+        }
+
+        let mut cache = ExitCache::load_default();
+        let exits = exit::probe_routes(&routes, &mut cache, ctx.refresh_exit);
+        if let Err(e) = cache.save() {
+            eprintln!("Warning: Failed to save exit cache: {:#}", e);
+        }
+        exits
+    } else {
+        HashMap::new()
+    };
+    ctx.direct_exit = direct_route.and_then(|r| exits.get(&r).copied().flatten());
+
+    // 5. 用出口 IP 填充国家/地区和运营商，并与直连出口对比
+    let mut results: Vec<ProcessResult> = classified
+        .into_iter()
+        .map(|(mut result, route)| {
+            let exit_ip = route.and_then(|r| exits.get(&r).copied().flatten());
+            if let Some(exit_ip) = exit_ip {
+                let geo = lookup_geo(ctx, &exit_ip);
+                result.country = Some(
+                    geo.as_ref()
+                        .map(|g| g.to_string())
+                        .unwrap_or_else(|| format!("exit_ip={}", exit_ip)),
+                );
+                result.label = geo.as_ref().and_then(|g| g.label.clone());
+                result.city = geo.as_ref().and_then(|g| g.place());
+                result.latitude = geo.as_ref().and_then(|g| g.latitude);
+                result.longitude = geo.as_ref().and_then(|g| g.longitude);
+                result.accuracy_radius = geo.as_ref().and_then(|g| g.accuracy_radius);
+                result.isp = geo.and_then(|g| g.asn_label());
+                result.exit_ip = Some(exit_ip);
+                result.exit_is_direct = ctx.direct_exit == Some(exit_ip);
+            }
+            result
         })
         .collect();
 
@@ -278,6 +452,45 @@ pub fn scan_all_processes(ctx: &mut ScanContext) -> Result<Vec<ProcessResult>> {
     Ok(results)
 }
 
+/// 识别所有本地回环监听端口的协议
+///
+/// ## 流程
+/// 1. 获取所有进程 PID
+/// 2. 构建本地监听端口映射
+/// 3. 对每个端口并行做离线握手，识别协议并猜测客户端
+///
+/// ## 返回
+/// 按端口排序的监听端口列表
+pub fn scan_listeners(ctx: &mut ScanContext) -> Result<Vec<ListenerInfo>> {
+    // 握手超时：本地回环连接很快，300ms 足够区分"无响应"
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(300);
+
+    let pids = macos::list_all_pids()?;
+    ctx.build_listen_ports(&pids);
+
+    let mut listeners: Vec<ListenerInfo> = ctx
+        .listen_ports
+        .par_iter()
+        .map(|(&port, (pid, name))| {
+            let protocol = proxy::fingerprint(port, HANDSHAKE_TIMEOUT);
+            if ctx.debug {
+                println!("DEBUG: port {} ({}) -> {}", port, name, protocol);
+            }
+            ListenerInfo {
+                port,
+                pid: *pid,
+                name: name.clone(),
+                protocol,
+                client: proxy::guess_client(name, port, protocol),
+            }
+        })
+        .collect();
+
+    listeners.sort_by_key(|l| l.port);
+
+    Ok(listeners)
+}
+
 /// 判断进程的代理模式
 ///
 /// 遍历所有 socket 连接，按优先级判断：
@@ -285,10 +498,7 @@ pub fn scan_all_processes(ctx: &mut ScanContext) -> Result<Vec<ProcessResult>> {
 /// 2. LOCAL_PROXY: 连接本地回环地址
 /// 3. VPN_LIKELY: VPN 接口 + 有远端连接
 /// 4. DIRECT: 默认
-fn determine_proxy_mode(
-    ctx: &ScanContext,
-    sockets: &[SocketInfo],
-) -> (ProxyMode, Option<String>, Option<String>, Option<String>) {
+fn determine_proxy_mode(ctx: &ScanContext, sockets: &[SocketInfo]) -> Detection {
     let mut has_remote_conn = false;
     let mut system_proxy_conn: Option<(IpAddr, u16)> = None;
     let mut local_proxy_conn: Option<(IpAddr, u16)> = None;
@@ -350,7 +560,7 @@ fn determine_proxy_mode(
     // 1. SYSTEM_PROXY: 连接到系统代理端口
     if let Some((ip, port)) = system_proxy_conn {
         let mut detail = None;
-        let mut country = None;
+        let mut exit_route = None;
 
         // 查找监听该端口的进程
         if let Some((proxy_pid, proxy_name)) = ctx.listen_ports.get(&port) {
@@ -358,52 +568,20 @@ fn determine_proxy_mode(
                 "proxy_pid={} proxy_name=\"{}\"",
                 proxy_pid, proxy_name
             ));
-
-            // 如果启用了出口探测
-            if ctx.probe_exit {
-                let cache_key = format!("{}:{}", ip, port);
-
-                // 1. 尝试从缓存读取
-                let cached_ip = {
-                    let cache = ctx.exit_ip_cache.lock().unwrap();
-                    cache.get(&cache_key).cloned()
-                };
-
-                let exit_ip = if let Some(cached) = cached_ip {
-                    cached
-                } else {
-                    // 2. 缓存未命中，进行探测
-                    let result = probe_exit_ip(ip, port);
-                    // 3. 写入缓存
-                    let mut cache = ctx.exit_ip_cache.lock().unwrap();
-                    cache.insert(cache_key, result);
-                    result
-                };
-
-                if let Some(ip) = exit_ip {
-                    // 这里由于有了 &mut ctx，不能直接调用 lookup_country(ctx, ...)，需要解耦
-                    // 但我们可以只用 geo_db 部分。为了简单，我们先克隆 geo_db 的引用或者调整结构
-                    // 实际上 ctx.geo_db 是 Option<GeoDb>，我们可以直接用
-                    if let Some(geo) = &ctx.geo_db {
-                        country = geo.lookup(ip).map(|r| format!("{} {}", r.iso_code, r.name));
-                    }
-                    if country.is_none() {
-                        country = Some(format!("exit_ip={}", ip));
-                    }
-                }
-            }
+            // 出口探测在所有进程扫描完成后统一进行
+            exit_route = Some(ExitRoute::Proxy(ip, port));
         }
 
         if ctx.debug {
             println!("  -> Detect: SYSTEM_PROXY (connects to {}:{})", ip, port);
         }
 
-        return (
-            ProxyMode::SystemProxy,
-            Some(format!("{}:{}", ip, port)),
+        return Detection {
+            mode: ProxyMode::SystemProxy,
+            proxy: Some(format!("{}:{}", ip, port)),
             detail,
-            country,
-        );
+            exit_route,
+        };
     }
 
     // 2. TUN_PROXY: 使用 TUN 模式透明代理 (198.18.x.x)
@@ -412,64 +590,19 @@ fn determine_proxy_mode(
             println!("  -> Detect: VPN_LIKELY (TUN mode to {}:{})", ip, port);
         }
 
-        let mut country = None;
-        // 如果启用了出口探测，且存在系统代理，尝试通过系统代理探测出口 IP
-        // 假设 TUN 模式的流量最终也是通过同一个代理节点出去的
-        if ctx.probe_exit {
-            let proxy_candidate = ctx
-                .system_proxy
-                .socks
-                .as_ref()
-                .or(ctx.system_proxy.http.as_ref())
-                .or(ctx.system_proxy.https.as_ref());
-
-            if let Some(proxy) = proxy_candidate {
-                // 尝试将 host 解析为 IP (通常是 127.0.0.1)
-                if let Ok(proxy_ip) = proxy.host.parse::<std::net::IpAddr>() {
-                    let cache_key = format!("{}:{}", proxy_ip, proxy.port);
-
-                    // 1. 尝试从缓存读取
-                    let cached_ip = {
-                        let cache = ctx.exit_ip_cache.lock().unwrap();
-                        cache.get(&cache_key).cloned()
-                    };
-
-                    let exit_ip = if let Some(cached) = cached_ip {
-                        cached
-                    } else {
-                        // 2. 缓存未命中，进行探测
-                        let result = probe_exit_ip(proxy_ip, proxy.port);
-                        // 3. 写入缓存
-                        let mut cache = ctx.exit_ip_cache.lock().unwrap();
-                        cache.insert(cache_key, result);
-                        result
-                    };
-
-                    if let Some(ip) = exit_ip {
-                        // 复用 Geo 查询逻辑
-                        if let Some(geo) = &ctx.geo_db {
-                            country = geo.lookup(ip).map(|r| format!("{} {}", r.iso_code, r.name));
-                        }
-                        if country.is_none() {
-                            country = Some(format!("exit_ip={}", ip));
-                        }
-                    }
-                }
-            }
-        }
-
-        return (
-            ProxyMode::VpnLikely, // TUN 模式类似 VPN
-            Some(format!("TUN:{}:{}", ip, port)),
-            Some("tun_mode=true".to_string()),
-            country,
-        );
+        // TUN 接管了默认路由，不使用代理直接请求即可得到 TUN 的真实出口
+        return Detection {
+            mode: ProxyMode::VpnLikely, // TUN 模式类似 VPN
+            proxy: Some(format!("TUN:{}:{}", ip, port)),
+            detail: Some("tun_mode=true".to_string()),
+            exit_route: Some(ExitRoute::DefaultRoute),
+        };
     }
 
     // 3. LOCAL_PROXY: 连接到其他本地端口
     if let Some((ip, port)) = local_proxy_conn {
-        let mut country = None;
         let mut detail = None;
+        let mut exit_route = None;
 
         // 查找监听该端口的进程
         if let Some((proxy_pid, proxy_name)) = ctx.listen_ports.get(&port) {
@@ -477,49 +610,19 @@ fn determine_proxy_mode(
                 "proxy_pid={} proxy_name=\"{}\"",
                 proxy_pid, proxy_name
             ));
-
-            // 如果启用了出口探测
-            if ctx.probe_exit {
-                let cache_key = format!("{}:{}", ip, port);
-
-                // 1. 尝试从缓存读取
-                let cached_ip = {
-                    let cache = ctx.exit_ip_cache.lock().unwrap();
-                    cache.get(&cache_key).cloned()
-                };
-
-                let exit_ip = if let Some(cached) = cached_ip {
-                    cached
-                } else {
-                    // 2. 缓存未命中，进行探测
-                    let result = probe_exit_ip(ip, port);
-                    // 3. 写入缓存
-                    let mut cache = ctx.exit_ip_cache.lock().unwrap();
-                    cache.insert(cache_key, result);
-                    result
-                };
-
-                if let Some(ip) = exit_ip {
-                    if let Some(geo) = &ctx.geo_db {
-                        country = geo.lookup(ip).map(|r| format!("{} {}", r.iso_code, r.name));
-                    }
-                    if country.is_none() {
-                        country = Some(format!("exit_ip={}", ip));
-                    }
-                }
-            }
+            exit_route = Some(ExitRoute::Proxy(ip, port));
         }
 
         if ctx.debug {
             println!("  -> Detect: LOCAL_PROXY (connects to {}:{})", ip, port);
         }
 
-        return (
-            ProxyMode::LocalProxy,
-            Some(format!("{}:{}", ip, port)),
+        return Detection {
+            mode: ProxyMode::LocalProxy,
+            proxy: Some(format!("{}:{}", ip, port)),
             detail,
-            country,
-        );
+            exit_route,
+        };
     }
 
     // 4. VPN_LIKELY: 默认路由走 VPN 且有远端连接
@@ -527,19 +630,24 @@ fn determine_proxy_mode(
         if ctx.debug {
             println!("  -> Detect: VPN_LIKELY (Default Route is VPN)");
         }
-        return (
-            ProxyMode::VpnLikely,
-            Some(ctx.default_iface.clone()),
-            None,
-            None,
-        );
+        return Detection {
+            mode: ProxyMode::VpnLikely,
+            proxy: Some(ctx.default_iface.clone()),
+            detail: None,
+            exit_route: Some(ExitRoute::DefaultRoute),
+        };
     }
 
     // 5. DIRECT: 默认
     if ctx.debug {
         println!("  -> Detect: DIRECT");
     }
-    (ProxyMode::Direct, None, None, None)
+    Detection {
+        mode: ProxyMode::Direct,
+        proxy: None,
+        detail: None,
+        exit_route: None,
+    }
 }
 
 /// 检查端口是否匹配（尝试两种字节序）
@@ -561,7 +669,7 @@ fn check_port_match(port: u16, targets: &[u16]) -> bool {
 /// - 198.18.0.0/15 (ClashX Pro, Surge 等)
 /// - 10.255.x.x (某些 VPN)
 /// - 172.16-31.x.x (某些 VPN)
-fn is_tun_virtual_ip(ip: &IpAddr) -> bool {
+pub(crate) fn is_tun_virtual_ip(ip: &IpAddr) -> bool {
     match ip {
         IpAddr::V4(ipv4) => {
             let octets = ipv4.octets();
@@ -576,61 +684,75 @@ fn is_tun_virtual_ip(ip: &IpAddr) -> bool {
     }
 }
 
-/// 使用 GeoIP 查询 IP 对应的国家
-fn lookup_country(ctx: &ScanContext, ip: &IpAddr) -> Option<String> {
-    ctx.geo_db
-        .as_ref()
-        .and_then(|db| db.lookup(*ip).map(|r| r.to_string()))
-}
-
-/// 探测本地代理的出口 IP
+/// 统计进程连接目的地的归属地分布
 ///
-/// 尝试通过代理访问 https://api.ipify.org?format=json
-/// 返回解析到的出口 IP
-fn probe_exit_ip(proxy_ip: IpAddr, proxy_port: u16) -> Option<IpAddr> {
-    let addr = format!("{}:{}", proxy_ip, proxy_port);
-
-    // 先尝试 SOCKS5 代理
-    let socks_proxy = format!("socks5h://{}", addr);
-    if let Some(ip) = try_probe_with_proxy(&socks_proxy) {
-        return Some(ip);
-    }
+/// 跳过 LISTEN、未建立、回环和 TUN 虚拟 IP（fake-ip 不是真实目的地），
+/// 命中自定义网段标签的地址按标签分组（优先于 "LAN"），其余局域网地址归为 "LAN"。
+/// 加载了 ASN 数据库时按 (国家, ASN) 分组。
+/// 没有 GeoIP 数据库时返回空列表。
+fn summarize_destinations(geo_db: Option<&GeoDb>, sockets: &[SocketInfo]) -> Vec<DestinationGroup> {
+    let Some(geo) = geo_db else {
+        return Vec::new();
+    };
+
+    type GroupKey = (String, Option<u32>, Option<String>);
+    let mut counts: HashMap<GroupKey, usize> = HashMap::new();
+    for sock in sockets {
+        if sock.tcp_state.is_some_and(|state| state.is_listening()) {
+            continue;
+        }
+        let ip = sock.remote_addr;
+        if ip.is_unspecified() || ip.is_loopback() || is_tun_virtual_ip(&ip) {
+            continue;
+        }
 
-    // 再尝试 HTTP 代理
-    let http_proxy = format!("http://{}", addr);
-    if let Some(ip) = try_probe_with_proxy(&http_proxy) {
-        return Some(ip);
+        let key = if let Some(label) = geo.label(ip) {
+            (label.to_string(), None, None)
+        } else if is_lan_ip(&ip) {
+            ("LAN".to_string(), None, None)
+        } else {
+            match geo.lookup(ip) {
+                Some(r) => (r.iso_code, r.asn, r.as_org),
+                None => ("??".to_string(), None, None),
+            }
+        };
+        *counts.entry(key).or_insert(0) += 1;
     }
 
-    None
+    let mut groups: Vec<DestinationGroup> = counts
+        .into_iter()
+        .map(|((country, asn, as_org), sockets)| DestinationGroup {
+            country,
+            asn,
+            as_org,
+            sockets,
+        })
+        .collect();
+    // 数量降序，数量相同时按名称排序保证输出稳定
+    groups.sort_by(|a, b| {
+        b.sockets
+            .cmp(&a.sockets)
+            .then_with(|| a.country.cmp(&b.country))
+            .then_with(|| a.asn.cmp(&b.asn))
+    });
+    groups
 }
 
-/// 尝试通过指定代理探测出口 IP
-fn try_probe_with_proxy(proxy_url: &str) -> Option<IpAddr> {
-    use std::time::Duration;
-
-    // 构建带代理的 HTTP 客户端
-    let proxy = reqwest::Proxy::all(proxy_url).ok()?;
-    let client = reqwest::blocking::Client::builder()
-        .proxy(proxy)
-        .timeout(Duration::from_secs(5))
-        .build()
-        .ok()?;
-
-    // 请求 IP 检测服务
-    #[derive(serde::Deserialize)]
-    struct IpifyResponse {
-        ip: String,
+/// 检查 IP 是否是局域网/链路本地地址
+pub(crate) fn is_lan_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ipv4) => ipv4.is_private() || ipv4.is_link_local(),
+        IpAddr::V6(ipv6) => {
+            let first = ipv6.segments()[0];
+            // fc00::/7 (ULA) 和 fe80::/10 (链路本地)
+            (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+        }
     }
+}
 
-    let resp: IpifyResponse = client
-        .get("https://api.ipify.org?format=json")
-        .send()
-        .ok()?
-        .json()
-        .ok()?;
-
-    resp.ip.parse().ok()
+/// 使用 GeoIP 查询 IP 对应的国家和运营商
+fn lookup_geo(ctx: &ScanContext, ip: &IpAddr) -> Option<GeoResult> {
+    ctx.geo_db.as_ref().and_then(|db| db.lookup(*ip))
 }
 
 // ========================================
@@ -640,6 +762,15 @@ fn try_probe_with_proxy(proxy_url: &str) -> Option<IpAddr> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_is_lan_ip() {
+        assert!(is_lan_ip(&"192.168.1.1".parse().unwrap()));
+        assert!(is_lan_ip(&"10.0.0.8".parse().unwrap()));
+        assert!(is_lan_ip(&"fe80::1".parse().unwrap()));
+        assert!(!is_lan_ip(&"8.8.8.8".parse().unwrap()));
+        assert!(!is_lan_ip(&"2001:4860::8888".parse().unwrap()));
+    }
+
     #[test]
     fn test_proxy_mode_display() {
         assert_eq!(ProxyMode::SystemProxy.to_string(), "SYSTEM_PROXY");